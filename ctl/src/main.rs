@@ -2,60 +2,42 @@ use anyhow::Context;
 use clap::Parser;
 use image::ImageReader;
 use libmoxpaper::MoxpaperClient;
-use libmoxpaper::{BezierChoice, ResizeStrategy, TransitionType, image_data::ImageData};
-use std::{io::Read, path::PathBuf};
-
-fn from_hex(hex: &str) -> anyhow::Result<[u8; 3]> {
-    let hex = hex.trim_start_matches('#');
-
-    let chars = hex
-        .chars()
-        .filter(|&c| c.is_ascii_alphanumeric())
-        .map(|c| c.to_ascii_uppercase() as u8);
-
-    if chars.clone().count() != 6 {
-        return Err(anyhow::anyhow!(
-            "Expected 6 characters for hex color, found {}",
-            chars.count()
-        ));
-    }
-
-    let mut color = [0, 0, 0];
-
-    chars.enumerate().try_for_each(|(i, c)| {
-        match c {
-            b'A'..=b'F' => color[i / 2] += c - b'A' + 10,
-            b'0'..=b'9' => color[i / 2] += c - b'0',
-            _ => {
-                return Err(anyhow::anyhow!(
-                    "Expected [0-9], [a-f], or [A-F], found '{}'",
-                    char::from(c)
-                ));
-            }
-        }
-
-        if i % 2 == 0 {
-            color[i / 2] *= 16;
-        }
-
-        Ok(())
-    })?;
-
-    Ok(color)
-}
-
-/// Clears specified outputs by filling them with a solid color
+use libmoxpaper::{
+    BezierChoice, Choreography, ColorStop, Data, Generated, InterruptPolicy, Playback,
+    PlaylistAction, Repeat, ResizeStrategy, Sequence, Shader, Transition, TransitionStage,
+    TransitionType, WeightedTransition,
+    image_data::ImageData,
+    parse::{
+        from_hex, parse_bezier, parse_choreography, parse_stage, parse_transition_type,
+        parse_weighted_transition, random_pool,
+    },
+};
+use std::{
+    io::Read,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Fills specified outputs with a solid color or a pattern generated at
+/// their resolution
 #[derive(Parser, Debug)]
-pub struct Clear {
-    /// Color in hexadecimal (format: RRGGBB) used to fill the display
-    #[arg(value_parser = from_hex, default_value = "000000")]
-    pub color: [u8; 3],
+pub struct Generate {
+    /// Color in hexadecimal (format: RRGGBB) or a pattern out of
+    /// "linear(<degrees>, <stops>)", "radial(<stops>)",
+    /// "conic(<degrees>, <stops>)", "noise(<seed>, <scale>[, <palette>])",
+    /// "plasma(<seed>, <scale>[, <palette>])" and
+    /// "voronoi(<seed>, <cells>[, <palette>])". Stops are comma separated
+    /// colors each with an optional position, e.g. "#ff8800 40%", and
+    /// palettes plain colors
+    #[arg(value_parser = parse_pattern, default_value = "000000")]
+    pub pattern: Pattern,
 
     /// List of output names to target, separated by commas
     #[arg(short, long, value_delimiter = ',')]
     pub outputs: Vec<String>,
 
-    /// Type of transition when clearing
+    /// Type of transition when clearing, e.g. "fade", "cube-left" or
+    /// "mask(<radial|clock|diamond|noise|image path>[, <softness>])"
     #[arg(long, value_parser = parse_transition_type)]
     pub transition_type: Option<TransitionType>,
 
@@ -67,9 +49,51 @@ pub struct Clear {
     #[arg(long)]
     pub transition_fps: Option<u64>,
 
-    /// Bezier timing, e.g. "ease" or "0.42,0.0,1.0,1.0"
+    /// Timing function, e.g. "ease", "0.42,0.0,1.0,1.0", "steps(4, jump-end)",
+    /// "spring(170, 8)" or "bounce-out"
     #[arg(long, value_parser = parse_bezier)]
     pub bezier: Option<BezierChoice>,
+
+    /// What to do when a transition is already running
+    #[arg(long)]
+    pub transition_interrupt: Option<InterruptPolicy>,
+
+    /// Timing across outputs: "independent", "sync" or "cascade(<delay ms>[, <degrees>])",
+    /// where 0 degrees sweeps left to right and 90 top to bottom
+    #[arg(long, value_parser = parse_choreography)]
+    pub transition_choreography: Option<Choreography>,
+
+    /// Transitions "random" picks from, separated by commas, each with an
+    /// optional weight, e.g. "fade:3,push-left,wipe:0.5"
+    #[arg(long, value_delimiter = ',', value_parser = parse_weighted_transition)]
+    pub transition_pool: Vec<WeightedTransition>,
+
+    /// Don't let "random" pick the previous transition again
+    #[arg(long)]
+    pub transition_avoid_repeat: bool,
+
+    /// Stage of a chained transition, repeat to add more. Space separated
+    /// "key=value" pairs out of type, duration, fps, bezier and through, e.g.
+    /// "type=fade duration=300 through=#000000" where through is a color or
+    /// image to end the stage on
+    #[arg(long = "transition-stage", value_parser = parse_stage)]
+    pub transition_stages: Vec<TransitionStage>,
+}
+
+/// Command to control the playlists set up in the daemon's config
+#[derive(Parser, Debug)]
+pub struct PlaylistControl {
+    /// List of output names to target, separated by commas
+    #[arg(short, long, value_delimiter = ',')]
+    pub outputs: Vec<String>,
+}
+
+/// Command to go through the wallpapers outputs showed
+#[derive(Parser, Debug)]
+pub struct HistoryControl {
+    /// List of output names to target, separated by commas
+    #[arg(short, long, value_delimiter = ',')]
+    pub outputs: Vec<String>,
 }
 
 /// Set of all commands supported by the application
@@ -79,8 +103,37 @@ enum Cli {
     /// Show an image on one or more outputs
     Img(Img),
 
-    /// Fill selected outputs with a specific color
-    Clear(Clear),
+    /// Fill selected outputs with a color or a generated pattern
+    #[command(alias = "clear")]
+    Generate(Generate),
+
+    /// Show the next wallpaper of the playlists on selected outputs
+    Next(PlaylistControl),
+
+    /// Show the previous wallpaper of the playlists on selected outputs
+    Prev(PlaylistControl),
+
+    /// Stop the playlists on selected outputs from changing wallpapers
+    Pause(PlaylistControl),
+
+    /// Let paused playlists change wallpapers again
+    Resume(PlaylistControl),
+
+    /// Show the wallpapers set last again, e.g. after starting the daemon
+    /// with --no-restore
+    Restore,
+
+    /// Make the daemon read its config again and apply what changed
+    Reload,
+
+    /// Go back to the previous wallpaper on selected outputs
+    Undo(HistoryControl),
+
+    /// Go forward again after undo on selected outputs
+    Redo(HistoryControl),
+
+    /// List the wallpapers selected outputs showed, the current one marked
+    History(HistoryControl),
 
     /// Retrieve current output information
     Query,
@@ -89,7 +142,9 @@ enum Cli {
 /// Command to show an image across selected outputs
 #[derive(Parser, Debug)]
 pub struct Img {
-    /// File path to the image, or '-' to read from standard input
+    /// File path to the image, '-' to read from standard input, a directory
+    /// or glob such as 'renders/frame_*.png' of frames to play, or a '.wgsl'
+    /// shader to draw live
     #[arg(value_parser = parse_image)]
     pub image: CliImage,
 
@@ -101,7 +156,28 @@ pub struct Img {
     #[arg(long, default_value = "crop")]
     pub resize: ResizeStrategy,
 
-    /// Type of transition
+    /// How often an animated GIF, APNG or WebP plays, "forever" or a number
+    /// of times after which it stays on its last frame
+    #[arg(long, value_parser = parse_repeat, default_value = "forever")]
+    pub repeat: Repeat,
+
+    /// Frame rate of a directory or glob of frames, 24 by default, or of a
+    /// shader, which draws every frame the output shows by default
+    #[arg(long)]
+    pub fps: Option<f32>,
+
+    /// Order to play a directory or glob of frames in
+    #[arg(long, default_value = "loop")]
+    pub playback: Playback,
+
+    /// Frames to start into a directory or glob of frames. Each further
+    /// output in --outputs starts this many frames further in, to set them
+    /// showing the same frames apart
+    #[arg(long, default_value = "0")]
+    pub frame_offset: u32,
+
+    /// Type of transition, e.g. "fade", "cube-left" or
+    /// "mask(<radial|clock|diamond|noise|image path>[, <softness>])"
     #[arg(long, value_parser = parse_transition_type)]
     pub transition_type: Option<TransitionType>,
 
@@ -113,54 +189,140 @@ pub struct Img {
     #[arg(long)]
     pub transition_fps: Option<u64>,
 
-    /// Bezier timing, e.g. "ease" or "0.42,0.0,1.0,1.0"
+    /// Timing function, e.g. "ease", "0.42,0.0,1.0,1.0", "steps(4, jump-end)",
+    /// "spring(170, 8)" or "bounce-out"
     #[arg(long, value_parser = parse_bezier)]
     pub transition_bezier: Option<BezierChoice>,
+
+    /// What to do when a transition is already running
+    #[arg(long)]
+    pub transition_interrupt: Option<InterruptPolicy>,
+
+    /// Timing across outputs: "independent", "sync" or "cascade(<delay ms>[, <degrees>])",
+    /// where 0 degrees sweeps left to right and 90 top to bottom
+    #[arg(long, value_parser = parse_choreography)]
+    pub transition_choreography: Option<Choreography>,
+
+    /// Transitions "random" picks from, separated by commas, each with an
+    /// optional weight, e.g. "fade:3,push-left,wipe:0.5"
+    #[arg(long, value_delimiter = ',', value_parser = parse_weighted_transition)]
+    pub transition_pool: Vec<WeightedTransition>,
+
+    /// Don't let "random" pick the previous transition again
+    #[arg(long)]
+    pub transition_avoid_repeat: bool,
+
+    /// Stage of a chained transition, repeat to add more. Space separated
+    /// "key=value" pairs out of type, duration, fps, bezier and through, e.g.
+    /// "type=fade duration=300 through=#000000" where through is a color or
+    /// image to end the stage on
+    #[arg(long = "transition-stage", value_parser = parse_stage)]
+    pub transition_stages: Vec<TransitionStage>,
 }
 
-fn parse_bezier(s: &str) -> anyhow::Result<BezierChoice> {
-    let nums = s
-        .split(',')
-        .map(str::trim)
-        .map(str::parse)
-        .collect::<Result<Vec<f32>, _>>();
+fn parse_repeat(s: &str) -> anyhow::Result<Repeat> {
+    match s {
+        "forever" => Ok(Repeat::Forever),
+        times => match times.parse::<u32>()? {
+            0 => Err(anyhow::anyhow!("An animation has to play at least once")),
+            times => Ok(Repeat::Times(times)),
+        },
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Color([u8; 3]),
+    Generated(Generated),
+}
 
-    if let Ok(nums) = nums
-        && nums.len() == 4
-    {
-        return Ok(BezierChoice::Custom((nums[0], nums[1], nums[2], nums[3])));
+fn parse_stops(args: &[&str]) -> anyhow::Result<Vec<ColorStop>> {
+    if args.is_empty() {
+        return Err(anyhow::anyhow!("Gradients require at least one color stop"));
     }
 
-    let bezier = match s {
-        "linear" => BezierChoice::Linear,
-        "ease" => BezierChoice::Ease,
-        "ease-in" => BezierChoice::EaseIn,
-        "ease-out" => BezierChoice::EaseOut,
-        "ease-in-out" => BezierChoice::EaseInOut,
-        _ => BezierChoice::Named(s.into()),
+    args.iter()
+        .map(|stop| {
+            let (color, position) = match stop.split_once(char::is_whitespace) {
+                Some((color, position)) => {
+                    let position = position.trim();
+                    let percent = position
+                        .strip_suffix('%')
+                        .ok_or(anyhow::anyhow!("Expected a percentage, found '{position}'"))?
+                        .trim()
+                        .parse::<f32>()?;
+                    (color, Some(percent / 100.0))
+                }
+                None => (*stop, None),
+            };
+
+            Ok(ColorStop {
+                color: from_hex(color)?,
+                position,
+            })
+        })
+        .collect()
+}
+
+fn parse_pattern(s: &str) -> anyhow::Result<Pattern> {
+    let Some((name, args)) = s
+        .split_once('(')
+        .and_then(|(name, args)| Some((name.trim(), args.strip_suffix(')')?)))
+    else {
+        return Ok(Pattern::Color(from_hex(s)?));
     };
 
-    Ok(bezier)
-}
+    let args = args
+        .split(',')
+        .map(str::trim)
+        .filter(|arg| !arg.is_empty())
+        .collect::<Vec<_>>();
+    let arg = |i: usize, what: &str| {
+        args.get(i)
+            .copied()
+            .ok_or(anyhow::anyhow!("{name}() requires {what}"))
+    };
+    let number = |i: usize, what: &str| Ok::<_, anyhow::Error>(arg(i, what)?.parse::<f32>()?);
+    let seed = || Ok::<_, anyhow::Error>(arg(0, "a seed")?.parse::<u32>()?);
+    let palette = || -> anyhow::Result<Vec<[u8; 3]>> {
+        args.iter().skip(2).map(|color| from_hex(color)).collect()
+    };
+
+    let generated = match name {
+        "linear" => Generated::LinearGradient {
+            angle: number(0, "an angle")?,
+            stops: parse_stops(args.get(1..).unwrap_or_default())?,
+        },
+        "radial" => Generated::RadialGradient {
+            stops: parse_stops(&args)?,
+        },
+        "conic" => Generated::ConicGradient {
+            angle: number(0, "an angle")?,
+            stops: parse_stops(args.get(1..).unwrap_or_default())?,
+        },
+        "noise" => Generated::Noise {
+            seed: seed()?,
+            scale: number(1, "a scale")?,
+            palette: palette()?,
+        },
+        "plasma" => Generated::Plasma {
+            seed: seed()?,
+            scale: number(1, "a scale")?,
+            palette: palette()?,
+        },
+        "voronoi" => Generated::Voronoi {
+            seed: seed()?,
+            cells: arg(1, "a number of cells")?.parse::<u32>()?,
+            palette: palette()?,
+        },
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Expected linear, radial, conic, noise, plasma or voronoi, found '{name}'"
+            ));
+        }
+    };
 
-fn parse_transition_type(s: &str) -> anyhow::Result<TransitionType> {
-    Ok(match s {
-        "none" => TransitionType::None,
-        "simple" => TransitionType::Simple,
-        "fade" => TransitionType::Fade,
-        "left" => TransitionType::Left,
-        "right" => TransitionType::Right,
-        "top" => TransitionType::Top,
-        "bottom" => TransitionType::Bottom,
-        "center" => TransitionType::Center,
-        "outer" => TransitionType::Outer,
-        "any" => TransitionType::Any,
-        "random" => TransitionType::Random,
-        "wipe" => TransitionType::Wipe,
-        "wave" => TransitionType::Wave,
-        "grow" => TransitionType::Grow,
-        s => TransitionType::Custom(s.into()),
-    })
+    Ok(Pattern::Generated(generated))
 }
 
 #[derive(Clone, Debug)]
@@ -204,7 +366,7 @@ pub fn parse_image(raw: &str) -> anyhow::Result<CliImage> {
     }
 
     let path = PathBuf::from(raw);
-    if path.exists() {
+    if path.exists() || Sequence::is_source(&path) {
         return Ok(CliImage::Path(path));
     }
 
@@ -214,19 +376,58 @@ pub fn parse_image(raw: &str) -> anyhow::Result<CliImage> {
     ))
 }
 
+/// How long ago a time in seconds since the Unix epoch was, e.g. "5m ago".
+fn ago(seconds: u64) -> String {
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH + Duration::from_secs(seconds))
+        .unwrap_or_default()
+        .as_secs();
+
+    match elapsed {
+        0..60 => format!("{elapsed}s ago"),
+        60..3600 => format!("{}m ago", elapsed / 60),
+        3600..86400 => format!("{}h ago", elapsed / 3600),
+        _ => format!("{}d ago", elapsed / 86400),
+    }
+}
+
+/// Where a wallpaper comes from, in a line.
+fn describe(data: &Data) -> String {
+    match data {
+        Data::Path(path) => path.display().to_string(),
+        Data::Image(image) => format!("{}x{} image", image.width(), image.height()),
+        Data::Color([r, g, b]) => format!("#{r:02x}{g:02x}{b:02x}"),
+        Data::S3 { bucket, key } => format!("s3://{bucket}/{key}"),
+        Data::Http { url, .. } => url.clone(),
+        Data::Sequence(sequence) => sequence.source.display().to_string(),
+        Data::Shader(shader) => shader.path.display().to_string(),
+        Data::Generated(generated) => format!("{generated:?}"),
+    }
+}
+
 fn main() -> anyhow::Result<()> {
     let mut client = MoxpaperClient::connect().context("Failed to connect to daemon")?;
 
     match Cli::parse() {
         Cli::Img(img) => {
-            let transition = MoxpaperClient::transition(
-                img.transition_type,
-                img.transition_fps,
-                img.transition_duration,
-                img.transition_bezier,
-            );
-
-            let mut builder = client.set().resize(img.resize).transition(transition);
+            let transition = Transition {
+                interrupt: img.transition_interrupt,
+                choreography: img.transition_choreography,
+                random_pool: random_pool(img.transition_pool, img.transition_avoid_repeat),
+                stages: img.transition_stages,
+                ..MoxpaperClient::transition(
+                    img.transition_type,
+                    img.transition_fps,
+                    img.transition_duration,
+                    img.transition_bezier,
+                )
+            };
+
+            let mut builder = client
+                .set()
+                .resize(img.resize)
+                .repeat(img.repeat)
+                .transition(transition);
 
             if !img.outputs.is_empty() {
                 builder = builder.outputs(img.outputs);
@@ -243,6 +444,17 @@ fn main() -> anyhow::Result<()> {
 
                         let image_data = ImageData::from(image);
                         builder.image(image_data).apply()?;
+                    } else if path.extension().is_some_and(|e| e == "wgsl") {
+                        builder.shader(Shader { path, fps: img.fps }).apply()?;
+                    } else if Sequence::is_source(&path) {
+                        builder
+                            .sequence(Sequence {
+                                source: path,
+                                fps: img.fps.unwrap_or(Sequence::DEFAULT_FPS),
+                                playback: img.playback,
+                                offset: img.frame_offset,
+                            })
+                            .apply()?;
                     } else {
                         builder.path(path).apply()?;
                     }
@@ -260,18 +472,54 @@ fn main() -> anyhow::Result<()> {
                 }
             }
         }
-        Cli::Clear(clear) => {
-            let transition = MoxpaperClient::transition(
-                clear.transition_type,
-                clear.transition_fps,
-                clear.transition_duration,
-                clear.bezier,
-            );
+        Cli::Generate(generate) => {
+            let transition = Transition {
+                interrupt: generate.transition_interrupt,
+                choreography: generate.transition_choreography,
+                random_pool: random_pool(
+                    generate.transition_pool,
+                    generate.transition_avoid_repeat,
+                ),
+                stages: generate.transition_stages,
+                ..MoxpaperClient::transition(
+                    generate.transition_type,
+                    generate.transition_fps,
+                    generate.transition_duration,
+                    generate.bezier,
+                )
+            };
             let mut builder = client.set().transition(transition);
-            if !clear.outputs.is_empty() {
-                builder = builder.outputs(clear.outputs);
+            if !generate.outputs.is_empty() {
+                builder = builder.outputs(generate.outputs);
+            }
+            match generate.pattern {
+                Pattern::Color(color) => builder.color(color).apply()?,
+                Pattern::Generated(generated) => builder.generated(generated).apply()?,
+            }
+        }
+        Cli::Next(control) => client.playlist(PlaylistAction::Next, control.outputs)?,
+        Cli::Prev(control) => client.playlist(PlaylistAction::Prev, control.outputs)?,
+        Cli::Pause(control) => client.playlist(PlaylistAction::Pause, control.outputs)?,
+        Cli::Resume(control) => client.playlist(PlaylistAction::Resume, control.outputs)?,
+        Cli::Restore => client.restore()?,
+        Cli::Reload => client.reload()?,
+        Cli::Undo(control) => client.undo(control.outputs)?,
+        Cli::Redo(control) => client.redo(control.outputs)?,
+        Cli::History(control) => {
+            let mut output = None;
+            for entry in client.history(control.outputs)? {
+                if output.as_ref() != Some(&entry.output) {
+                    println!("{}:", entry.output);
+                    output = Some(entry.output.clone());
+                }
+
+                println!(
+                    "  {} {:>8}  {}",
+                    if entry.current { '*' } else { ' ' },
+                    ago(entry.applied),
+                    describe(&entry.wallpaper.data),
+                );
             }
-            builder.color(clear.color).apply()?;
         }
         Cli::Query => {
             for output in client.outputs() {
@@ -0,0 +1,610 @@
+use crate::assets::AssetData;
+use anyhow::Context;
+use image::{
+    AnimationDecoder, ImageFormat, ImageReader,
+    codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+};
+use libmoxpaper::{Playback, Repeat, ResizeStrategy, Sequence, Transition, image_data::ImageData};
+use std::{
+    cmp::Ordering,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        mpsc::{self, Receiver, SyncSender, TryRecvError},
+    },
+    thread,
+    time::Duration,
+};
+
+/// Resized frames an animation player keeps in memory. Animations that
+/// don't fit are decoded again on every loop instead.
+const CACHE_BYTES: usize = 128 * 1024 * 1024;
+
+/// Shortest time a frame stays, browsers treat smaller GIF delays the same
+/// way.
+const MIN_DELAY: Duration = Duration::from_millis(20);
+
+/// Resized frames decoded ahead of the one on screen.
+const PREFETCH_BYTES: usize = 64 * 1024 * 1024;
+
+/// How soon a player looks again when its next frame isn't decoded yet.
+const LATE_POLL: Duration = Duration::from_millis(5);
+
+/// Frames to play after the first one and how often they play.
+#[derive(Clone)]
+pub struct Motion {
+    source: Source,
+    repeat: Repeat,
+}
+
+#[derive(Clone)]
+enum Source {
+    /// An animated GIF, APNG or WebP
+    Animation(Arc<Path>),
+    Sequence {
+        frames: Arc<[PathBuf]>,
+        delay: Duration,
+        playback: Playback,
+        offset: u32,
+    },
+}
+
+impl Motion {
+    /// `None` for still images, including animated formats with a single
+    /// frame.
+    pub fn probe(path: &Path, repeat: Repeat) -> anyhow::Result<Option<Self>> {
+        let animated = match format(path)? {
+            ImageFormat::Gif => frames(path)?.take(2).filter(Result::is_ok).count() == 2,
+            ImageFormat::Png => PngDecoder::new(reader(path)?)?.is_apng()?,
+            ImageFormat::WebP => WebPDecoder::new(reader(path)?)?.has_animation(),
+            _ => false,
+        };
+
+        Ok(animated.then(|| Self {
+            source: Source::Animation(path.into()),
+            repeat,
+        }))
+    }
+
+    /// Lists the frames of `sequence` and decodes the one it starts on.
+    pub fn sequence(sequence: &Sequence, repeat: Repeat) -> anyhow::Result<(ImageData, Self)> {
+        if !sequence.fps.is_finite() || sequence.fps <= 0. {
+            return Err(anyhow::anyhow!("Frame rate has to be positive"));
+        }
+        let delay = Duration::try_from_secs_f32(1. / sequence.fps)
+            .map_err(|_| anyhow::anyhow!("Frame rate is too low"))?;
+
+        let frames = sequence_frames(&sequence.source)?;
+        let first = order(frames.len(), sequence.playback, sequence.offset, repeat)
+            .next()
+            .ok_or(anyhow::anyhow!("No frames to play"))?;
+        let path = &frames[first];
+        let image = image::open(path).with_context(|| format!("{}", path.display()))?;
+
+        let motion = Self {
+            source: Source::Sequence {
+                frames: frames.into(),
+                delay,
+                playback: sequence.playback,
+                offset: sequence.offset,
+            },
+            repeat,
+        };
+
+        Ok((ImageData::from(image), motion))
+    }
+}
+
+fn reader(path: &Path) -> anyhow::Result<BufReader<File>> {
+    let file = File::open(path).with_context(|| format!("{}", path.display()))?;
+    Ok(BufReader::new(file))
+}
+
+fn format(path: &Path) -> anyhow::Result<ImageFormat> {
+    ImageReader::open(path)?
+        .with_guessed_format()?
+        .format()
+        .ok_or(anyhow::anyhow!("Unknown image format: {}", path.display()))
+}
+
+fn frames(path: &Path) -> anyhow::Result<image::Frames<'static>> {
+    Ok(match format(path)? {
+        ImageFormat::Gif => GifDecoder::new(reader(path)?)?.into_frames(),
+        ImageFormat::Png => PngDecoder::new(reader(path)?)?.apng()?.into_frames(),
+        ImageFormat::WebP => WebPDecoder::new(reader(path)?)?.into_frames(),
+        format => return Err(anyhow::anyhow!("{format:?} images aren't animated")),
+    })
+}
+
+/// Images in a directory, or the files a glob matches, in natural order.
+fn sequence_frames(source: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let files = Files::new(source)?;
+
+    let mut frames = std::fs::read_dir(files.dir)
+        .with_context(|| format!("{}", files.dir.display()))?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && files.matches(path))
+        .collect::<Vec<_>>();
+
+    if frames.is_empty() {
+        return Err(anyhow::anyhow!("No frames found in {}", source.display()));
+    }
+
+    frames.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+    Ok(frames)
+}
+
+/// Files named by a directory, all of its images, or by a glob over file
+/// names such as `renders/frame_*.png`.
+pub struct Files<'a> {
+    /// Directory the files are in
+    pub dir: &'a Path,
+    pattern: Option<&'a str>,
+}
+
+impl<'a> Files<'a> {
+    pub fn new(source: &'a Path) -> anyhow::Result<Self> {
+        if source.is_dir() {
+            return Ok(Self {
+                dir: source,
+                pattern: None,
+            });
+        }
+
+        let pattern = source
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or(anyhow::anyhow!("Invalid pattern: {}", source.display()))?;
+        let dir = source
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+
+        Ok(Self {
+            dir,
+            pattern: Some(pattern),
+        })
+    }
+
+    /// Whether the file at `path` is one of them, by its name.
+    pub fn matches(&self, path: &Path) -> bool {
+        match self.pattern {
+            Some(pattern) => path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| glob(pattern.as_bytes(), name.as_bytes())),
+            None => ImageFormat::from_path(path).is_ok(),
+        }
+    }
+}
+
+/// Matches a file name against `*` and `?` wildcards.
+pub fn glob(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, _) => name.is_empty(),
+        (Some((b'*', rest)), _) => {
+            glob(rest, name)
+                || name
+                    .split_first()
+                    .is_some_and(|(_, name)| glob(pattern, name))
+        }
+        (Some((b'?', rest)), Some((_, name))) => glob(rest, name),
+        (Some((p, rest)), Some((n, name))) => p == n && glob(rest, name),
+        (Some(_), None) => false,
+    }
+}
+
+/// Compares names with runs of digits as numbers, so that `frame9.png`
+/// comes before `frame10.png`.
+/// Numbers that only differ in leading zeros order by the first such
+/// difference, fewer zeros first, if the names are otherwise the same.
+pub fn natural_cmp(mut a: &str, mut b: &str) -> Ordering {
+    let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let mut zeros = Ordering::Equal;
+
+    loop {
+        let (Some(x), Some(y)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len()).then(zeros);
+        };
+
+        let ordering = if x.is_ascii_digit() && y.is_ascii_digit() {
+            let (x, rest_a) = a.split_at(digits(a));
+            let (y, rest_b) = b.split_at(digits(b));
+            (a, b) = (rest_a, rest_b);
+
+            if zeros == Ordering::Equal {
+                zeros = x.len().cmp(&y.len());
+            }
+            let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+            x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+        } else {
+            (a, b) = (&a[x.len_utf8()..], &b[y.len_utf8()..]);
+            x.cmp(&y)
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// Indices of the frames a sequence of `len` frames shows, starting
+/// `offset` frames into a pass. A ping-pong pass goes there and back, and a
+/// finite ping-pong ends on the frame it started on.
+fn order(
+    len: usize,
+    playback: Playback,
+    offset: u32,
+    repeat: Repeat,
+) -> impl Iterator<Item = usize> + Send + 'static {
+    let pass: Vec<usize> = match playback {
+        Playback::Loop => (0..len).collect(),
+        Playback::PingPong => (0..len).chain((1..len.saturating_sub(1)).rev()).collect(),
+    };
+    let count = match repeat {
+        _ if pass.is_empty() => 0,
+        Repeat::Forever => usize::MAX,
+        Repeat::Times(times) if playback == Playback::PingPong && pass.len() > 1 => {
+            pass.len() * times as usize + 1
+        }
+        Repeat::Times(times) => pass.len() * times as usize,
+    };
+
+    (offset as usize..)
+        .map(move |position| pass[position % pass.len()])
+        .take(count)
+}
+
+/// What a [`Player`] shows next.
+pub enum Frame {
+    /// Show the image for the duration
+    Show(ImageData, Duration),
+    /// The next image isn't ready, ask again after the duration
+    Wait(Duration),
+    /// The last repeat has ended
+    End,
+}
+
+/// Plays a [`Motion`] on one output, resizing its frames to the output.
+pub enum Player {
+    Animation(AnimationPlayer),
+    Sequence(SequencePlayer),
+}
+
+impl Player {
+    pub fn new(motion: Motion, resize: ResizeStrategy, width: u32, height: u32) -> Self {
+        match motion.source {
+            Source::Animation(path) => Self::Animation(AnimationPlayer::new(
+                path,
+                motion.repeat,
+                resize,
+                width,
+                height,
+            )),
+            Source::Sequence {
+                frames,
+                delay,
+                playback,
+                offset,
+            } => {
+                let order = order(frames.len(), playback, offset, motion.repeat);
+                Self::Sequence(SequencePlayer::new(
+                    frames, order, delay, resize, width, height,
+                ))
+            }
+        }
+    }
+
+    pub fn next_frame(&mut self) -> anyhow::Result<Frame> {
+        match self {
+            Self::Animation(player) => player.next_frame(),
+            Self::Sequence(player) => Ok(player.next_frame()),
+        }
+    }
+}
+
+/// Shows an animated image while a background thread decodes and resizes
+/// the frames that come next. The thread keeps the resized frames until
+/// they exceed [`CACHE_BYTES`], animations that fit are only decoded once.
+pub struct AnimationPlayer {
+    frames: Receiver<anyhow::Result<Frame>>,
+}
+
+impl AnimationPlayer {
+    fn new(
+        path: Arc<Path>,
+        repeat: Repeat,
+        resize: ResizeStrategy,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let (sender, frames) = mpsc::sync_channel(prefetch_frames(width, height));
+
+        let decode = move || {
+            let decoded = decode_animation(&path, repeat, resize, width, height, &sender);
+            if let Err(e) = decoded {
+                _ = sender.send(Err(e));
+            }
+        };
+        if let Err(e) = thread::Builder::new()
+            .name("animation decode".into())
+            .spawn(decode)
+        {
+            log::error!("Failed to start decoding frames: {e}");
+        }
+
+        Self { frames }
+    }
+
+    fn next_frame(&mut self) -> anyhow::Result<Frame> {
+        match self.frames.try_recv() {
+            Ok(frame) => frame,
+            Err(TryRecvError::Empty) => Ok(Frame::Wait(LATE_POLL)),
+            Err(TryRecvError::Disconnected) => Ok(Frame::End),
+        }
+    }
+}
+
+/// Sends the frames of every repeat of an animation, until the player is
+/// dropped. The first frame is already shown as the wallpaper's image, only
+/// its delay is sent the first time.
+fn decode_animation(
+    path: &Path,
+    repeat: Repeat,
+    resize: ResizeStrategy,
+    width: u32,
+    height: u32,
+    sender: &SyncSender<anyhow::Result<Frame>>,
+) -> anyhow::Result<()> {
+    let mut cache: Option<Vec<(ImageData, Duration)>> = Some(Vec::new());
+    let mut cache_bytes = 0;
+    let mut first = true;
+
+    for play in 0.. {
+        if let Repeat::Times(times) = repeat
+            && play >= times
+        {
+            break;
+        }
+
+        if play > 0
+            && let Some(cache) = cache.as_ref()
+        {
+            for (image, delay) in cache {
+                if sender.send(Ok(Frame::Show(image.clone(), *delay))).is_err() {
+                    return Ok(());
+                }
+            }
+            continue;
+        }
+
+        let mut decoded = 0;
+        for frame in frames(path)? {
+            let frame = frame?;
+            let delay = Duration::from(frame.delay()).max(MIN_DELAY);
+            let image = AssetData::new(
+                ImageData::from(frame.into_buffer()),
+                resize,
+                Transition::default(),
+            )
+            .into_resized(width, height)?;
+            decoded += 1;
+
+            if let Some(frames) = cache.as_mut() {
+                cache_bytes += image.data().len();
+                if cache_bytes > CACHE_BYTES {
+                    log::info!(
+                        "{} doesn't fit in memory, decoding it on every loop",
+                        path.display()
+                    );
+                    cache = None;
+                } else {
+                    frames.push((image.clone(), delay));
+                }
+            }
+
+            let frame = if first {
+                first = false;
+                Frame::Wait(delay)
+            } else {
+                Frame::Show(image, delay)
+            };
+            if sender.send(Ok(frame)).is_err() {
+                return Ok(());
+            }
+        }
+
+        if decoded == 0 {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Resized frames decoded ahead of the one on screen, within
+/// [`PREFETCH_BYTES`].
+fn prefetch_frames(width: u32, height: u32) -> usize {
+    let frame_bytes = (width as usize * height as usize * 4).max(1);
+    (PREFETCH_BYTES / frame_bytes).clamp(1, 32)
+}
+
+/// Shows a sequence at a fixed frame rate while a background thread
+/// decodes and resizes the frames that come next.
+pub struct SequencePlayer {
+    frames: Receiver<anyhow::Result<Frame>>,
+}
+
+impl SequencePlayer {
+    fn new(
+        paths: Arc<[PathBuf]>,
+        order: impl Iterator<Item = usize> + Send + 'static,
+        delay: Duration,
+        resize: ResizeStrategy,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let (sender, frames) = mpsc::sync_channel(prefetch_frames(width, height));
+
+        // Sending fails once the player is dropped, which ends the thread.
+        let prefetch = move || {
+            // The first frame is already shown as the wallpaper's image.
+            if sender.send(Ok(Frame::Wait(delay))).is_err() {
+                return;
+            }
+
+            for index in order.skip(1) {
+                let path = &paths[index];
+                let frame = image::open(path)
+                    .with_context(|| format!("{}", path.display()))
+                    .and_then(|image| {
+                        AssetData::new(ImageData::from(image), resize, Transition::default())
+                            .into_resized(width, height)
+                    })
+                    .map(|image| Frame::Show(image, delay));
+
+                if sender.send(frame).is_err() {
+                    return;
+                }
+            }
+        };
+        if let Err(e) = thread::Builder::new()
+            .name("sequence prefetch".into())
+            .spawn(prefetch)
+        {
+            log::error!("Failed to start decoding frames: {e}");
+        }
+
+        Self { frames }
+    }
+
+    fn next_frame(&mut self) -> Frame {
+        match self.frames.try_recv() {
+            Ok(Ok(frame)) => frame,
+            Ok(Err(e)) => {
+                log::warn!("Skipping frame: {e}");
+                Frame::Wait(Duration::ZERO)
+            }
+            Err(TryRecvError::Empty) => Frame::Wait(LATE_POLL),
+            Err(TryRecvError::Disconnected) => Frame::End,
+        }
+    }
+}
+
+/// The motion of an asset loaded from `path`, logging why it plays as a still
+/// image if probing fails.
+pub fn probe(path: &Path, repeat: Repeat) -> Option<Motion> {
+    Motion::probe(path, repeat)
+        .inspect_err(|e| log::warn!("Failed to probe {} for frames: {e}", path.display()))
+        .ok()
+        .flatten()
+}
+
+/// The first frame and motion of a sequence, logging why it can't play.
+pub fn sequence(sequence: &Sequence, repeat: Repeat) -> Option<(ImageData, Motion)> {
+    Motion::sequence(sequence, repeat)
+        .inspect_err(|e| {
+            log::error!(
+                "Failed to load frames from {}: {e}",
+                sequence.source.display()
+            )
+        })
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indices(len: usize, playback: Playback, offset: u32, repeat: Repeat) -> Vec<usize> {
+        order(len, playback, offset, repeat).take(64).collect()
+    }
+
+    #[test]
+    fn glob_wildcards() {
+        assert!(glob(b"frame_*.png", b"frame_001.png"));
+        assert!(glob(b"frame_*.png", b"frame_.png"));
+        assert!(glob(b"*", b""));
+        assert!(glob(b"**.png", b"a.png"));
+        assert!(glob(b"a*b*c", b"abxbc"));
+        assert!(glob(b"frame_??.png", b"frame_01.png"));
+        assert!(!glob(b"frame_??.png", b"frame_1.png"));
+        assert!(!glob(b"*.png", b"a.png.bak"));
+        assert!(!glob(b"?", b""));
+        assert!(!glob(b"", b"a"));
+    }
+
+    #[test]
+    fn natural_order() {
+        assert_eq!(natural_cmp("frame9.png", "frame10.png"), Ordering::Less);
+        assert_eq!(natural_cmp("frame10.png", "frame9.png"), Ordering::Greater);
+        assert_eq!(natural_cmp("frame1.png", "frame1.png"), Ordering::Equal);
+        assert_eq!(natural_cmp("a", "a1"), Ordering::Less);
+        assert_eq!(natural_cmp("a2b", "a2c"), Ordering::Less);
+        assert_eq!(natural_cmp("é1", "é2"), Ordering::Less);
+    }
+
+    #[test]
+    fn natural_order_leading_zeros() {
+        assert_eq!(natural_cmp("frame007.png", "frame8.png"), Ordering::Less);
+        assert_eq!(natural_cmp("frame010.png", "frame9.png"), Ordering::Greater);
+        assert_eq!(natural_cmp("frame7.png", "frame007.png"), Ordering::Less);
+        assert_eq!(natural_cmp("frame007.png", "frame7.png"), Ordering::Greater);
+        assert_eq!(natural_cmp("frame07a", "frame7b"), Ordering::Less);
+        assert_eq!(natural_cmp("0", "000"), Ordering::Less);
+
+        let mut names = ["f10", "f010", "f9", "f09", "f1"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, ["f1", "f9", "f09", "f10", "f010"]);
+    }
+
+    #[test]
+    fn loop_order() {
+        assert_eq!(
+            indices(3, Playback::Loop, 0, Repeat::Times(2)),
+            [0, 1, 2, 0, 1, 2]
+        );
+        assert_eq!(
+            indices(3, Playback::Loop, 1, Repeat::Times(2)),
+            [1, 2, 0, 1, 2, 0]
+        );
+        assert_eq!(indices(3, Playback::Loop, 7, Repeat::Times(1)), [1, 2, 0]);
+        assert_eq!(indices(1, Playback::Loop, 3, Repeat::Times(2)), [0, 0]);
+        assert_eq!(indices(3, Playback::Loop, 0, Repeat::Forever).len(), 64);
+        assert!(indices(0, Playback::Loop, 0, Repeat::Forever).is_empty());
+        assert!(indices(3, Playback::Loop, 0, Repeat::Times(0)).is_empty());
+    }
+
+    #[test]
+    fn ping_pong_order() {
+        assert_eq!(
+            indices(4, Playback::PingPong, 0, Repeat::Times(1)),
+            [0, 1, 2, 3, 2, 1, 0]
+        );
+        assert_eq!(
+            indices(3, Playback::PingPong, 1, Repeat::Times(1)),
+            [1, 2, 1, 0, 1]
+        );
+        assert_eq!(
+            indices(2, Playback::PingPong, 0, Repeat::Times(2)),
+            [0, 1, 0, 1, 0]
+        );
+        assert_eq!(
+            indices(2, Playback::PingPong, 1, Repeat::Times(1)),
+            [1, 0, 1]
+        );
+        assert_eq!(
+            indices(1, Playback::PingPong, 0, Repeat::Times(3)),
+            [0, 0, 0]
+        );
+        assert!(
+            indices(1, Playback::PingPong, 0, Repeat::Forever)
+                .iter()
+                .all(|&index| index == 0)
+        );
+        assert!(indices(0, Playback::PingPong, 0, Repeat::Times(1)).is_empty());
+    }
+}
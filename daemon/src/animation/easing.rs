@@ -0,0 +1,367 @@
+use super::bezier::Bezier;
+use libmoxpaper::{EasingFunction, StepPosition};
+use std::f32::consts::PI;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    In,
+    Out,
+    InOut,
+}
+
+#[derive(Debug, Clone)]
+pub enum Easing {
+    Bezier(Bezier),
+    Steps { steps: u32, jump: StepPosition },
+    Spring(Spring),
+    Bounce(Direction),
+    Elastic(Direction),
+    Back(Direction),
+}
+
+impl Easing {
+    /// Returns `(time_factor, progress)` for a linear time `t` in `0..=1`.
+    ///
+    /// Unlike beziers, springs, elastic and back easings may leave `0..=1`
+    /// before settling on `1.0`.
+    pub fn evaluate(&self, t: f32) -> (f32, f32) {
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            Easing::Bezier(bezier) => bezier.evaluate(t),
+            Easing::Steps { steps, jump } => (t, steps_at(t, *steps, *jump)),
+            Easing::Spring(spring) => (t, spring.evaluate(t)),
+            Easing::Bounce(direction) => (t, directed(t, *direction, bounce_out)),
+            Easing::Elastic(direction) => (t, directed(t, *direction, elastic_out)),
+            Easing::Back(direction) => (t, directed(t, *direction, back_out)),
+        }
+    }
+}
+
+impl From<Bezier> for Easing {
+    fn from(value: Bezier) -> Self {
+        Self::Bezier(value)
+    }
+}
+
+impl From<&EasingFunction> for Easing {
+    fn from(value: &EasingFunction) -> Self {
+        match value {
+            EasingFunction::Steps { steps, jump } => Easing::Steps {
+                steps: *steps,
+                jump: *jump,
+            },
+            EasingFunction::Spring {
+                stiffness,
+                damping,
+                mass,
+            } => Easing::Spring(Spring::new(*stiffness, *damping, *mass)),
+            EasingFunction::BounceIn => Easing::Bounce(Direction::In),
+            EasingFunction::BounceOut => Easing::Bounce(Direction::Out),
+            EasingFunction::BounceInOut => Easing::Bounce(Direction::InOut),
+            EasingFunction::ElasticIn => Easing::Elastic(Direction::In),
+            EasingFunction::ElasticOut => Easing::Elastic(Direction::Out),
+            EasingFunction::ElasticInOut => Easing::Elastic(Direction::InOut),
+            EasingFunction::BackIn => Easing::Back(Direction::In),
+            EasingFunction::BackOut => Easing::Back(Direction::Out),
+            EasingFunction::BackInOut => Easing::Back(Direction::InOut),
+        }
+    }
+}
+
+/// CSS `steps()` as defined in css-easing-1.
+fn steps_at(t: f32, steps: u32, jump: StepPosition) -> f32 {
+    let steps = steps.max(1) as f32;
+
+    let mut step = (t * steps).floor();
+    if matches!(jump, StepPosition::JumpStart | StepPosition::JumpBoth) {
+        step += 1.0;
+    }
+
+    let jumps = match jump {
+        StepPosition::JumpStart | StepPosition::JumpEnd => steps,
+        StepPosition::JumpBoth => steps + 1.0,
+        StepPosition::JumpNone => (steps - 1.0).max(1.0),
+    };
+
+    step.clamp(0.0, jumps) / jumps
+}
+
+/// Builds the in and in-out variants from an ease-out function.
+fn directed(t: f32, direction: Direction, out: fn(f32) -> f32) -> f32 {
+    let ease_in = |t: f32| 1.0 - out(1.0 - t);
+
+    match direction {
+        Direction::Out => out(t),
+        Direction::In => ease_in(t),
+        Direction::InOut if t < 0.5 => ease_in(t * 2.0) / 2.0,
+        Direction::InOut => 0.5 + out(t * 2.0 - 1.0) / 2.0,
+    }
+}
+
+fn bounce_out(t: f32) -> f32 {
+    const N1: f32 = 7.5625;
+    const D1: f32 = 2.75;
+
+    if t < 1.0 / D1 {
+        N1 * t * t
+    } else if t < 2.0 / D1 {
+        let t = t - 1.5 / D1;
+        N1 * t * t + 0.75
+    } else if t < 2.5 / D1 {
+        let t = t - 2.25 / D1;
+        N1 * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / D1;
+        N1 * t * t + 0.984375
+    }
+}
+
+fn elastic_out(t: f32) -> f32 {
+    const C4: f32 = (2.0 * PI) / 3.0;
+
+    if t <= 0.0 {
+        0.0
+    } else if t >= 1.0 {
+        1.0
+    } else {
+        2f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * C4).sin() + 1.0
+    }
+}
+
+fn back_out(t: f32) -> f32 {
+    const C1: f32 = 1.70158;
+    const C3: f32 = C1 + 1.0;
+
+    1.0 + C3 * (t - 1.0).powi(3) + C1 * (t - 1.0).powi(2)
+}
+
+/// Damped spring released from 0 towards 1 with no initial velocity. The
+/// simulated time is stretched so that the spring has settled when the
+/// transition ends.
+#[derive(Debug, Clone, Copy)]
+pub struct Spring {
+    omega: f32,
+    zeta: f32,
+    settle_time: f32,
+}
+
+impl Spring {
+    /// Displacement below which the spring counts as settled.
+    const REST_THRESHOLD: f32 = 0.001;
+
+    pub fn new(stiffness: f32, damping: f32, mass: f32) -> Self {
+        let stiffness = stiffness.max(f32::EPSILON);
+        let mass = mass.max(f32::EPSILON);
+        let damping = damping.max(0.0);
+
+        let omega = (stiffness / mass).sqrt();
+        let zeta = damping / (2.0 * (stiffness * mass).sqrt());
+
+        let decay = if zeta < 1.0 {
+            zeta * omega
+        } else {
+            omega * (zeta - (zeta * zeta - 1.0).sqrt())
+        };
+
+        // An undamped spring never settles, cut it off after a few periods.
+        let settle_time = if decay > f32::EPSILON {
+            let settle_time = -Self::REST_THRESHOLD.ln() / decay;
+            if zeta == 1.0 {
+                settle_time * 1.5
+            } else {
+                settle_time
+            }
+        } else {
+            4.0 * 2.0 * PI / omega
+        };
+
+        Self {
+            omega,
+            zeta,
+            settle_time,
+        }
+    }
+
+    pub fn evaluate(&self, t: f32) -> f32 {
+        if t >= 1.0 {
+            return 1.0;
+        }
+
+        let time = t * self.settle_time;
+        let (omega, zeta) = (self.omega, self.zeta);
+
+        if zeta < 1.0 {
+            let omega_d = omega * (1.0 - zeta * zeta).sqrt();
+            let envelope = (-zeta * omega * time).exp();
+            1.0 - envelope
+                * ((omega_d * time).cos() + (zeta * omega / omega_d) * (omega_d * time).sin())
+        } else if zeta == 1.0 {
+            1.0 - (-omega * time).exp() * (1.0 + omega * time)
+        } else {
+            let root = (zeta * zeta - 1.0).sqrt();
+            let r1 = -omega * (zeta - root);
+            let r2 = -omega * (zeta + root);
+            1.0 - (r2 * (r1 * time).exp() - r1 * (r2 * time).exp()) / (r2 - r1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: usize = 200;
+
+    fn samples() -> impl Iterator<Item = f32> {
+        (0..=SAMPLES).map(|i| i as f32 / SAMPLES as f32)
+    }
+
+    fn progress(easing: &Easing) -> Vec<f32> {
+        samples().map(|t| easing.evaluate(t).1).collect()
+    }
+
+    fn assert_endpoints(easing: &Easing) {
+        let (start, end) = (easing.evaluate(0.0).1, easing.evaluate(1.0).1);
+        assert!(start.abs() < 1e-3, "{easing:?} starts at {start}");
+        assert!((end - 1.0).abs() < 1e-3, "{easing:?} ends at {end}");
+    }
+
+    fn assert_monotonic(easing: &Easing) {
+        progress(easing).windows(2).for_each(|pair| {
+            assert!(pair[1] >= pair[0] - 1e-5, "{easing:?} goes back: {pair:?}");
+        });
+    }
+
+    fn directed_easings() -> Vec<Easing> {
+        [Direction::In, Direction::Out, Direction::InOut]
+            .into_iter()
+            .flat_map(|direction| {
+                [
+                    Easing::Bounce(direction),
+                    Easing::Elastic(direction),
+                    Easing::Back(direction),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn directed_easings_start_and_end() {
+        directed_easings().iter().for_each(assert_endpoints);
+    }
+
+    #[test]
+    fn in_out_is_continuous_at_half() {
+        directed_easings().iter().for_each(|easing| {
+            let before = easing.evaluate(0.5 - 1e-4).1;
+            let after = easing.evaluate(0.5).1;
+            assert!((after - before).abs() < 1e-2, "{easing:?} jumps at 0.5");
+        });
+    }
+
+    #[test]
+    fn bounce_stays_in_range() {
+        [Direction::In, Direction::Out, Direction::InOut]
+            .into_iter()
+            .for_each(|direction| {
+                progress(&Easing::Bounce(direction))
+                    .into_iter()
+                    .for_each(|progress| assert!((-1e-5..=1.0 + 1e-5).contains(&progress)));
+            });
+    }
+
+    #[test]
+    fn back_overshoots() {
+        let min = progress(&Easing::Back(Direction::In))
+            .into_iter()
+            .fold(f32::MAX, f32::min);
+        assert!(min < 0.0);
+
+        let max = progress(&Easing::Back(Direction::Out))
+            .into_iter()
+            .fold(f32::MIN, f32::max);
+        assert!(max > 1.0);
+    }
+
+    #[test]
+    fn steps_values() {
+        let steps = |t, steps, jump| steps_at(t, steps, jump);
+
+        assert_eq!(steps(0.0, 4, StepPosition::JumpEnd), 0.0);
+        assert_eq!(steps(0.25, 4, StepPosition::JumpEnd), 0.25);
+        assert_eq!(steps(0.99, 4, StepPosition::JumpEnd), 0.75);
+        assert_eq!(steps(1.0, 4, StepPosition::JumpEnd), 1.0);
+
+        assert_eq!(steps(0.0, 4, StepPosition::JumpStart), 0.25);
+        assert_eq!(steps(0.99, 4, StepPosition::JumpStart), 1.0);
+        assert_eq!(steps(1.0, 4, StepPosition::JumpStart), 1.0);
+
+        assert_eq!(steps(0.0, 2, StepPosition::JumpNone), 0.0);
+        assert_eq!(steps(0.49, 2, StepPosition::JumpNone), 0.0);
+        assert_eq!(steps(0.5, 2, StepPosition::JumpNone), 1.0);
+        assert_eq!(steps(1.0, 2, StepPosition::JumpNone), 1.0);
+
+        assert_eq!(steps(0.0, 3, StepPosition::JumpBoth), 0.25);
+        assert_eq!(steps(0.5, 3, StepPosition::JumpBoth), 0.5);
+        assert_eq!(steps(1.0, 3, StepPosition::JumpBoth), 1.0);
+    }
+
+    #[test]
+    fn steps_never_go_back() {
+        [
+            StepPosition::JumpStart,
+            StepPosition::JumpEnd,
+            StepPosition::JumpNone,
+            StepPosition::JumpBoth,
+        ]
+        .into_iter()
+        .for_each(|jump| {
+            (2..=7).for_each(|steps| {
+                let easing = Easing::Steps { steps, jump };
+                assert_monotonic(&easing);
+                assert_eq!(easing.evaluate(1.0).1, 1.0);
+            });
+        });
+    }
+
+    #[test]
+    fn steps_with_jump_none_need_two_steps() {
+        assert!(EasingFunction::steps(1, StepPosition::JumpNone).is_err());
+        assert!(EasingFunction::steps(0, StepPosition::JumpEnd).is_err());
+        assert!(EasingFunction::steps(1, StepPosition::JumpEnd).is_ok());
+        assert!(EasingFunction::steps(2, StepPosition::JumpNone).is_ok());
+
+        let config = r#"{"steps":{"steps":1,"jump":"jump_none"}}"#;
+        assert!(serde_json::from_str::<EasingFunction>(config).is_err());
+        let config = r#"{"steps":{"steps":2,"jump":"jump_none"}}"#;
+        assert!(serde_json::from_str::<EasingFunction>(config).is_ok());
+    }
+
+    #[test]
+    fn springs_settle() {
+        [
+            Spring::new(100.0, 10.0, 1.0),
+            Spring::new(100.0, 20.0, 1.0),
+            Spring::new(100.0, 40.0, 1.0),
+            Spring::new(100.0, 0.0, 1.0),
+        ]
+        .into_iter()
+        .for_each(|spring| assert_endpoints(&Easing::Spring(spring)));
+    }
+
+    #[test]
+    fn damped_springs_dont_overshoot() {
+        // Critically damped and overdamped.
+        assert_monotonic(&Easing::Spring(Spring::new(100.0, 20.0, 1.0)));
+        assert_monotonic(&Easing::Spring(Spring::new(100.0, 40.0, 1.0)));
+    }
+
+    #[test]
+    fn underdamped_spring_overshoots() {
+        let max = progress(&Easing::Spring(Spring::new(100.0, 5.0, 1.0)))
+            .into_iter()
+            .fold(f32::MIN, f32::max);
+        assert!(max > 1.0);
+    }
+}
@@ -1,13 +1,23 @@
 pub mod bezier;
+pub mod easing;
+mod pieces;
+pub mod state;
 
-use crate::Moxpaper;
-use bezier::{Bezier, BezierBuilder};
+use crate::{
+    Moxpaper,
+    config::{BezierDefinition, Config},
+};
+use bezier::BezierBuilder;
 use calloop::{
-    LoopHandle,
+    LoopHandle, RegistrationToken,
     timer::{TimeoutAction, Timer},
 };
-use libmoxpaper::TransitionType;
-use rand::prelude::*;
+use easing::Easing;
+use libmoxpaper::{
+    BezierChoice, Choreography, InterruptPolicy, RandomPool, Transition, TransitionType,
+};
+use rand::{SeedableRng, rngs::StdRng};
+use state::TransitionState;
 use std::{
     sync::Arc,
     time::{Duration, Instant},
@@ -105,6 +115,33 @@ impl Default for Clip {
     }
 }
 
+/// Placement of a layer in 3D space, drawn by the perspective renderer
+/// unless flat.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Projection {
+    /// Rotation around the horizontal axis in degrees
+    pub rotate_x: f32,
+    /// Rotation around the vertical axis in degrees
+    pub rotate_y: f32,
+    /// Point rotations happen around, as a fraction of the layer (or piece)
+    /// width and height. Positive z points towards the viewer, in widths.
+    pub pivot: [f32; 3],
+    /// Page curl from the right edge, 0 is flat and 1 curls the layer
+    /// completely off the output
+    pub curl: f32,
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Self {
+            rotate_x: 0.,
+            rotate_y: 0.,
+            pivot: [0.5, 0.5, 0.],
+            curl: 0.,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 pub struct FrameData {
     pub clip: Clip,
@@ -112,6 +149,61 @@ pub struct FrameData {
     pub rotation: f32,
     pub filters: Filters,
     pub transforms: Transforms,
+    pub projection: Projection,
+}
+
+impl FrameData {
+    /// Whether the layer can be drawn by the flat texture renderer.
+    /// `Transforms::rotate` spins the layer in its plane and is only
+    /// honoured by the perspective renderer.
+    pub fn is_flat(&self) -> bool {
+        self.transforms.rotate == 0.
+            && self.projection.rotate_x == 0.
+            && self.projection.rotate_y == 0.
+            && self.projection.curl <= 0.
+    }
+}
+
+/// A rectangle cut out of a layer and animated on its own.
+#[derive(Debug, Clone, Copy)]
+pub struct Piece {
+    /// Area of the layer as fractions of the output: x, y, width, height
+    pub rect: [f32; 4],
+    /// Placement of the piece. Scaling and rotations happen around the
+    /// piece, translations are still fractions of the output.
+    pub frame_data: FrameData,
+}
+
+/// Frame data for both layers of a transition. The outgoing layer is the
+/// image being replaced, the incoming layer is the new wallpaper.
+#[derive(Debug, Clone, Default)]
+pub struct Frames {
+    pub outgoing: FrameData,
+    pub incoming: FrameData,
+    /// Draw the outgoing layer above the incoming one, e.g. for reveals.
+    pub outgoing_on_top: bool,
+    /// Blend the layers through the transition's mask instead of stacking
+    /// them.
+    pub mask: Option<MaskFrame>,
+    /// Draw the outgoing layer as these pieces instead of whole
+    pub outgoing_pieces: Vec<Piece>,
+    /// Draw the incoming layer as these pieces instead of whole
+    pub incoming_pieces: Vec<Piece>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MaskFrame {
+    pub progress: f32,
+    pub softness: f32,
+}
+
+impl Frames {
+    fn incoming(incoming: FrameData) -> Self {
+        Self {
+            incoming,
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -119,8 +211,14 @@ pub struct TransitionConfig {
     pub transition_type: TransitionType,
     pub fps: Option<u64>,
     pub duration: u128,
-    pub bezier: Bezier,
+    pub easing: Easing,
     pub enabled_transition_types: Option<Arc<[TransitionType]>>,
+    pub interrupt: InterruptPolicy,
+    pub choreography: Choreography,
+    /// Start instant shared with other outputs, `None` starts on the first
+    /// rendered frame.
+    pub start_at: Option<Instant>,
+    pub random_pool: RandomPool,
 }
 
 impl Default for TransitionConfig {
@@ -130,37 +228,112 @@ impl Default for TransitionConfig {
             transition_type: TransitionType::default(),
             fps: None,
             duration: 300,
-            bezier: BezierBuilder::new().ease_in(),
+            easing: BezierBuilder::new().ease_in().into(),
+            interrupt: InterruptPolicy::default(),
+            choreography: Choreography::default(),
+            start_at: None,
+            random_pool: RandomPool::default(),
+        }
+    }
+}
+
+impl TransitionConfig {
+    /// Resolves a requested transition against the defaults from `config`.
+    pub fn new(transition: &Transition, config: &Config) -> Self {
+        let easing = match transition.bezier.as_ref().unwrap_or(&config.default_bezier) {
+            BezierChoice::Linear => BezierBuilder::new().linear().into(),
+            BezierChoice::Ease => BezierBuilder::new().ease().into(),
+            BezierChoice::EaseIn => BezierBuilder::new().ease_in().into(),
+            BezierChoice::EaseOut => BezierBuilder::new().ease_out().into(),
+            BezierChoice::EaseInOut => BezierBuilder::new().ease_in_out().into(),
+            BezierChoice::Custom(curve) => BezierBuilder::new()
+                .custom(curve.0, curve.1, curve.2, curve.3)
+                .into(),
+            BezierChoice::Easing(function) => Easing::from(function),
+            BezierChoice::Named(bezier) => match config.bezier.get(bezier) {
+                Some(BezierDefinition::Cubic(a)) => {
+                    BezierBuilder::new().custom(a.0, a.1, a.2, a.3).into()
+                }
+                Some(BezierDefinition::Easing(function)) => Easing::from(function),
+                None => {
+                    log::warn!("Bezier: {bezier} not found");
+                    BezierBuilder::new().linear().into()
+                }
+            },
+        };
+
+        Self {
+            enabled_transition_types: config.enabled_transition_types.as_ref().map(Arc::clone),
+            transition_type: transition
+                .transition_type
+                .clone()
+                .unwrap_or(config.default_transition_type.clone()),
+            fps: transition.fps.or(config.default_fps),
+            duration: transition
+                .duration
+                .unwrap_or(config.default_transition_duration),
+            easing,
+            interrupt: transition
+                .interrupt
+                .unwrap_or(config.default_interrupt_policy),
+            choreography: transition
+                .choreography
+                .unwrap_or(config.default_choreography),
+            start_at: None,
+            random_pool: transition
+                .random_pool
+                .as_ref()
+                .or(config.random_pool.as_ref())
+                .cloned()
+                .unwrap_or_default(),
         }
     }
 }
 
+/// Start instant of a transition on the output at `position`, given the
+/// positions of all outputs that transition together.
+pub fn start_at(
+    choreography: Choreography,
+    now: Instant,
+    position: (i32, i32),
+    positions: &[(i32, i32)],
+) -> Option<Instant> {
+    match choreography {
+        Choreography::Independent => None,
+        Choreography::Sync => Some(now),
+        Choreography::Cascade { delay, direction } => {
+            let (sin, cos) = direction.to_radians().sin_cos();
+            let project = |(x, y): (i32, i32)| x as f32 * cos + y as f32 * sin;
+
+            let own = project(position);
+            let rank = positions
+                .iter()
+                .filter(|position| project(**position) < own)
+                .count() as u64;
+
+            Some(now + Duration::from_millis(rank * delay))
+        }
+    }
+}
+
+/// Thin calloop wrapper around [`TransitionState`] that schedules frames and
+/// feeds it the wall clock.
 pub struct Animation {
-    bezier: Option<Bezier>,
-    transition_config: Option<TransitionConfig>,
+    state: Option<TransitionState>,
     start_time: Option<Instant>,
-    is_active: bool,
-    progress: f32,
-    time_factor: f32,
     handle: LoopHandle<'static, Moxpaper>,
-    rand: Option<f32>,
-    rand_transition: Option<TransitionType>,
-    extents: Extents,
+    rng: StdRng,
+    token: Option<RegistrationToken>,
 }
 
 impl Animation {
     pub fn new(handle: LoopHandle<'static, Moxpaper>) -> Self {
         Self {
             handle,
-            bezier: None,
-            transition_config: None,
+            state: None,
             start_time: None,
-            is_active: false,
-            time_factor: 0.0,
-            progress: 0.0,
-            rand: None,
-            extents: Extents::default(),
-            rand_transition: None,
+            rng: StdRng::from_rng(&mut rand::rng()),
+            token: None,
         }
     }
 
@@ -170,55 +343,30 @@ impl Animation {
         transition_config: TransitionConfig,
         extents: Extents,
     ) {
-        let mut rng = rand::rng();
-
-        self.rand_transition = {
-            let all_transitions = vec![
-                TransitionType::None,
-                TransitionType::Simple,
-                TransitionType::Fade,
-                TransitionType::Left,
-                TransitionType::Right,
-                TransitionType::Top,
-                TransitionType::Bottom,
-                TransitionType::Center,
-                TransitionType::Outer,
-                TransitionType::Any,
-                TransitionType::Wipe,
-                TransitionType::Wave,
-                TransitionType::Grow,
-            ];
-
-            let enabled_transitions: Vec<_> = all_transitions
-                .into_iter()
-                .filter(|transition_type| {
-                    transition_config
-                        .enabled_transition_types
-                        .as_ref()
-                        .is_none_or(|enabled| enabled.contains(transition_type))
-                })
-                .collect();
-
-            if enabled_transitions.is_empty() {
-                Some(TransitionType::None)
-            } else {
-                let random_index = rng.random_range(0..enabled_transitions.len());
-                Some(enabled_transitions[random_index].clone())
-            }
+        let timer = match transition_config.start_at {
+            Some(start_at) => Timer::from_deadline(start_at),
+            None => Timer::immediate(),
         };
-
-        self.extents = extents;
-        self.rand = Some(rng.random_range(0.0..=1.0));
-        self.progress = 0.0;
-        self.start_time = None;
-        self.is_active = true;
-
-        self.bezier = Some(transition_config.bezier.clone());
-        self.transition_config = Some(transition_config);
+        self.start_time = transition_config.start_at;
+        let previous = self
+            .state
+            .as_ref()
+            .map(|state| state.transition_type().clone());
+        self.state = Some(TransitionState::new(
+            transition_config,
+            extents,
+            previous.as_ref(),
+            &mut self.rng,
+        ));
+
+        if let Some(token) = self.token.take() {
+            self.handle.remove(token);
+        }
 
         let output_name = output_name.to_string();
-        self.handle
-            .insert_source(Timer::immediate(), move |_, _, state| {
+        let token = self
+            .handle
+            .insert_source(timer, move |_, _, state| {
                 let output_name = output_name.clone();
 
                 let Some(output) = state
@@ -229,213 +377,70 @@ impl Animation {
                     return TimeoutAction::Drop;
                 };
 
-                output.animation.update();
+                let now = Instant::now();
+                output.animation.update(now);
 
                 output.render();
 
                 if output.animation.start_time.is_none() {
-                    output.animation.start_time = Some(Instant::now());
+                    output.animation.start_time = Some(now);
                 }
 
                 if !output.animation.is_active() {
+                    output.animation.token = None;
                     if let Some(image) = output.target_image.take() {
-                        output.previous_image =
-                            Some((image, output.animation.frame_data().unwrap_or_default()));
+                        output.previous_image = Some(image);
+                    }
+                    if !output.next_stage()
+                        && let Some(stages) = output.queue.pop_front()
+                    {
+                        output.transition_to(stages);
                     }
                     return TimeoutAction::Drop;
                 }
 
                 match output
                     .animation
-                    .transition_config
+                    .state
                     .as_ref()
-                    .and_then(|t| t.fps)
+                    .and_then(|state| state.config().fps)
                 {
                     Some(fps) => TimeoutAction::ToDuration(Duration::from_millis(1000 / fps)),
                     None => TimeoutAction::ToDuration(Duration::ZERO), // Vsync
                 }
             })
             .unwrap();
-    }
-
-    pub fn update(&mut self) -> bool {
-        if !self.is_active {
-            return false;
-        }
 
-        let Some(start_time) = self.start_time else {
-            return false;
-        };
+        self.token = Some(token);
+    }
 
-        let Some(transition_config) = self.transition_config.as_ref() else {
-            return false;
+    /// Advances the running transition to `now`. The first frame is drawn
+    /// before the clock starts, so that it always shows progress 0.
+    pub fn update(&mut self, now: Instant) {
+        let (Some(state), Some(start_time)) = (self.state.as_mut(), self.start_time) else {
+            return;
         };
 
-        let elapsed_ms = start_time.elapsed().as_millis();
-        if elapsed_ms >= transition_config.duration {
-            self.progress = 1.0;
-            self.is_active = false;
-            return true;
+        if !state.is_finished() {
+            state.advance(now.saturating_duration_since(start_time));
         }
-
-        let linear_progress =
-            start_time.elapsed().as_secs_f32() / (transition_config.duration / 1000) as f32;
-
-        match &self.bezier {
-            Some(bezier) => {
-                let (time_factor, progress_value) = bezier.evaluate(linear_progress);
-
-                self.progress = progress_value;
-                self.time_factor = time_factor;
-            }
-            None => self.progress = linear_progress,
-        };
-
-        false
     }
 
     pub fn is_active(&self) -> bool {
-        self.is_active
+        self.state
+            .as_ref()
+            .is_some_and(|state| !state.is_finished())
     }
 
-    pub fn frame_data(&self) -> anyhow::Result<FrameData> {
-        let Some(transition_config) = &self.transition_config else {
-            return Ok(FrameData::default());
-        };
-
-        match &transition_config.transition_type {
-            TransitionType::None => Ok(FrameData::default()),
-
-            TransitionType::Fade => Ok(FrameData {
-                filters: Filters {
-                    opacity: self.progress,
-                    ..Default::default()
-                },
-                ..Default::default()
-            }),
-
-            TransitionType::Simple => Ok(FrameData {
-                filters: Filters {
-                    opacity: self.progress,
-                    ..Default::default()
-                },
-                ..Default::default()
-            }),
-
-            TransitionType::Right => {
-                let clip = Clip {
-                    left: 1.0 - self.progress,
-                    ..Default::default()
-                };
-                Ok(FrameData {
-                    clip,
-                    ..Default::default()
-                })
-            }
-
-            TransitionType::Left => {
-                let clip = Clip {
-                    right: self.progress,
-                    ..Default::default()
-                };
-
-                Ok(FrameData {
-                    clip,
-                    ..Default::default()
-                })
-            }
-
-            TransitionType::Top => {
-                let clip = Clip {
-                    top: 1.0 - self.progress,
-                    ..Default::default()
-                };
-
-                Ok(FrameData {
-                    clip,
-                    ..Default::default()
-                })
-            }
-
-            TransitionType::Bottom => {
-                let clip = Clip {
-                    bottom: self.progress,
-                    ..Default::default()
-                };
-                Ok(FrameData {
-                    clip,
-                    ..Default::default()
-                })
-            }
-
-            TransitionType::Center => {
-                let center = 0.5;
-                let max_extent = self.progress * 0.5;
-
-                let x_scale = (self.extents.height / self.extents.width).max(1.0);
-                let y_scale = (self.extents.width / self.extents.height).max(1.0);
-
-                let half_extent_x = max_extent * x_scale;
-                let half_extent_y = max_extent * y_scale;
-
-                let clip = Clip {
-                    left: center - half_extent_x,
-                    top: center - half_extent_y,
-                    right: center + half_extent_x,
-                    bottom: center + half_extent_y,
-                };
-
-                Ok(FrameData {
-                    clip,
-                    radius: [(1.0 - self.progress) * (0.8 + 0.2 * (self.time_factor * 5.0).sin());
-                        4],
-                    ..Default::default()
-                })
-            }
-
-            TransitionType::Any => {
-                let rand = self.rand.unwrap_or(0.5);
-                let clip = Clip {
-                    left: rand - self.progress,
-                    top: rand - self.progress,
-                    right: rand + self.progress,
-                    bottom: rand + self.progress,
-                };
-
-                Ok(FrameData {
-                    clip,
-                    radius: [(1.0 - self.progress) * (0.8 + 0.2 * (self.time_factor * 5.0).sin());
-                        4],
-                    ..Default::default()
-                })
-            }
-
-            TransitionType::Random => {
-                if let Some(picked) = self.rand_transition.clone() {
-                    let mut temp_config = transition_config.clone();
-                    temp_config.transition_type = picked;
-                    let saved_bezier = self.bezier.clone();
-
-                    let temp_anim = Animation {
-                        bezier: saved_bezier,
-                        transition_config: Some(temp_config),
-                        start_time: self.start_time,
-                        is_active: self.is_active,
-                        progress: self.progress,
-                        time_factor: self.time_factor,
-                        handle: self.handle.clone(),
-                        rand: self.rand,
-                        rand_transition: self.rand_transition.clone(),
-                        extents: self.extents,
-                    };
-
-                    return temp_anim.frame_data();
-                }
-
-                Ok(FrameData::default())
-            }
+    /// The transition being drawn, with `Random` resolved.
+    pub fn transition_type(&self) -> Option<&TransitionType> {
+        self.state.as_ref().map(TransitionState::transition_type)
+    }
 
-            _ => Ok(FrameData::default()),
-        }
+    pub fn frames(&self) -> Frames {
+        self.state
+            .as_ref()
+            .map(TransitionState::frames)
+            .unwrap_or_default()
     }
 }
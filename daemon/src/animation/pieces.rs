@@ -0,0 +1,278 @@
+//! Transitions that cut the layers into pieces. Every piece runs the same
+//! animation, shifted by its own progress offset.
+
+use super::{Extents, Filters, FrameData, Frames, Piece, Projection, Transforms};
+use crate::seeded::random;
+
+/// Flips a grid of tiles from the old wallpaper to the new one, sweeping
+/// diagonally from the top left.
+pub fn tiles(progress: f32, extents: &Extents) -> Frames {
+    let (outgoing_pieces, incoming_pieces) = grid(extents, 8)
+        .map(|cell| {
+            let offset = (cell.column + cell.row) / 2.;
+            let t = stagger(progress, offset, 0.6);
+            flipped(cell.rect, t, true)
+        })
+        .unzip();
+
+    Frames {
+        outgoing_pieces,
+        incoming_pieces,
+        ..Default::default()
+    }
+}
+
+/// Fades out small blocks of the old wallpaper in random order.
+pub fn dissolve(progress: f32, extents: &Extents, seed: u32) -> Frames {
+    let outgoing_pieces = grid(extents, 32)
+        .map(|cell| {
+            let t = stagger(progress, random(seed, cell.index.into(), 0), 0.9);
+            Piece {
+                rect: cell.rect,
+                frame_data: faded(1. - t),
+            }
+        })
+        .collect();
+
+    Frames {
+        outgoing_on_top: true,
+        outgoing_pieces,
+        ..Default::default()
+    }
+}
+
+/// Breaks the old wallpaper into shards that fly away from the center.
+pub fn shatter(progress: f32, extents: &Extents, seed: u32) -> Frames {
+    let outgoing_pieces = grid(extents, 12)
+        .map(|cell| {
+            let [x, y, width, height] = cell.rect;
+            let direction = [x + width / 2. - 0.5, y + height / 2. - 0.5];
+            let distance = direction[0].hypot(direction[1]);
+
+            // Shards near the center break off first.
+            let offset = (distance / 0.71) * 0.7 + random(seed, cell.index.into(), 0) * 0.3;
+            let t = stagger(progress, offset, 0.5);
+            let flight = t * t;
+
+            let spin = random(seed, cell.index.into(), 1) * 2. - 1.;
+            let tumble = random(seed, cell.index.into(), 2) * 2. - 1.;
+            let speed = 1. + random(seed, cell.index.into(), 3);
+            let length = distance.max(f32::EPSILON);
+
+            Piece {
+                rect: cell.rect,
+                frame_data: FrameData {
+                    filters: Filters {
+                        opacity: 1. - flight,
+                        ..Default::default()
+                    },
+                    transforms: Transforms {
+                        translate: [
+                            direction[0] / length * flight * speed,
+                            direction[1] / length * flight * speed,
+                        ],
+                        rotate: spin * 180. * t,
+                        ..Default::default()
+                    },
+                    projection: Projection {
+                        rotate_x: tumble * 120. * t,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            }
+        })
+        .collect();
+
+    Frames {
+        outgoing_on_top: true,
+        outgoing_pieces,
+        ..Default::default()
+    }
+}
+
+/// Lets the old wallpaper crumble into particles that fall off the output,
+/// bottom rows first.
+pub fn particles(progress: f32, extents: &Extents, seed: u32) -> Frames {
+    let outgoing_pieces = grid(extents, 24)
+        .map(|cell| {
+            let offset = (1. - cell.row) * 0.7 + random(seed, cell.index.into(), 0) * 0.3;
+            let t = stagger(progress, offset, 0.6);
+            let fall = t * t;
+            let scale = 1. - 0.5 * t;
+
+            Piece {
+                rect: cell.rect,
+                frame_data: FrameData {
+                    transforms: Transforms {
+                        translate: [
+                            (random(seed, cell.index.into(), 1) - 0.5) * 0.2 * t,
+                            1.2 * fall,
+                        ],
+                        scale_x: scale,
+                        scale_y: scale,
+                        rotate: (random(seed, cell.index.into(), 2) - 0.5) * 360. * t,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            }
+        })
+        .collect();
+
+    Frames {
+        outgoing_on_top: true,
+        outgoing_pieces,
+        ..Default::default()
+    }
+}
+
+/// Venetian blinds: slats turn edge-on and come back showing the new
+/// wallpaper. Vertical blinds have columns of slats turning around the
+/// vertical axis.
+pub fn blinds(progress: f32, vertical: bool) -> Frames {
+    const SLATS: u32 = 10;
+
+    let (outgoing_pieces, incoming_pieces) = (0..SLATS)
+        .map(|i| {
+            let start = i as f32 / SLATS as f32;
+            let size = 1. / SLATS as f32;
+            let rect = if vertical {
+                [start, 0., size, 1.]
+            } else {
+                [0., start, 1., size]
+            };
+
+            let offset = i as f32 / (SLATS - 1) as f32;
+            flipped(rect, stagger(progress, offset, 0.3), vertical)
+        })
+        .unzip();
+
+    Frames {
+        outgoing_pieces,
+        incoming_pieces,
+        ..Default::default()
+    }
+}
+
+/// Strips the wipes are drawn with, narrow enough for their edge to look
+/// smooth.
+const STRIPS: u32 = 128;
+
+/// Slides the new wallpaper down from the top behind a slanted edge, the
+/// left side leading.
+pub fn wipe(progress: f32) -> Frames {
+    const SLANT: f32 = 0.5;
+
+    strips(|x| progress * (1. + SLANT) - x * SLANT)
+}
+
+/// Slides the new wallpaper down from the top behind a rolling wave.
+pub fn wave(progress: f32) -> Frames {
+    const AMPLITUDE: f32 = 0.06;
+    const WAVES: f32 = 2.;
+
+    strips(|x| {
+        let wave = (std::f32::consts::TAU * (WAVES * x - progress)).sin();
+        progress * (1. + 2. * AMPLITUDE) - AMPLITUDE + AMPLITUDE * wave
+    })
+}
+
+/// Shows the new wallpaper in vertical strips down to the height `edge`
+/// gives for the middle of each strip, both in fractions of the output.
+fn strips(edge: impl Fn(f32) -> f32) -> Frames {
+    let width = 1. / STRIPS as f32;
+    let incoming_pieces = (0..STRIPS)
+        .map(|i| {
+            let x = i as f32 * width;
+            let height = edge(x + width / 2.).clamp(0., 1.);
+            Piece {
+                rect: [x, 0., width, height],
+                frame_data: FrameData::default(),
+            }
+        })
+        .collect();
+
+    Frames {
+        incoming_pieces,
+        ..Default::default()
+    }
+}
+
+struct Cell {
+    index: u32,
+    /// Position in the grid, from 0 for the first to 1 for the last column
+    column: f32,
+    row: f32,
+    rect: [f32; 4],
+}
+
+/// Splits the output into `columns` columns of roughly square cells.
+fn grid(extents: &Extents, columns: u32) -> impl Iterator<Item = Cell> {
+    let rows = (columns as f32 * extents.height / extents.width)
+        .round()
+        .max(1.) as u32;
+    let (width, height) = (1. / columns as f32, 1. / rows as f32);
+    let position = |i: u32, count: u32| i as f32 / (count - 1).max(1) as f32;
+
+    (0..rows).flat_map(move |row| {
+        (0..columns).map(move |column| Cell {
+            index: row * columns + column,
+            column: position(column, columns),
+            row: position(row, rows),
+            rect: [column as f32 * width, row as f32 * height, width, height],
+        })
+    })
+}
+
+/// Progress of a piece that starts `offset * spread` into the transition
+/// and then takes the remaining `1 - spread` of it.
+fn stagger(progress: f32, offset: f32, spread: f32) -> f32 {
+    ((progress - offset * spread) / (1. - spread)).clamp(0., 1.)
+}
+
+/// The two sides of a piece turning over, around the vertical axis when
+/// `horizontal` is set. Each side is hidden while it faces away.
+fn flipped(rect: [f32; 4], t: f32, horizontal: bool) -> (Piece, Piece) {
+    let rotation = |degrees: f32, visible: bool| {
+        let (rotate_x, rotate_y) = if horizontal {
+            (0., degrees)
+        } else {
+            (degrees, 0.)
+        };
+
+        FrameData {
+            filters: Filters {
+                opacity: if visible { 1. } else { 0. },
+                ..Default::default()
+            },
+            projection: Projection {
+                rotate_x,
+                rotate_y,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    };
+
+    (
+        Piece {
+            rect,
+            frame_data: rotation(180. * t, t < 0.5),
+        },
+        Piece {
+            rect,
+            frame_data: rotation(-180. * (1. - t), t >= 0.5),
+        },
+    )
+}
+
+fn faded(opacity: f32) -> FrameData {
+    FrameData {
+        filters: Filters {
+            opacity,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
@@ -0,0 +1,869 @@
+use super::{
+    Clip, Extents, Filters, FrameData, Frames, MaskFrame, Projection, Transforms, TransitionConfig,
+    pieces,
+};
+use libmoxpaper::{Mask, MaskSource, TransitionType, WeightedTransition};
+use rand::Rng;
+use std::time::Duration;
+
+/// What `TransitionType::Random` picks from when the pool is empty.
+const RANDOM_TRANSITIONS: &[TransitionType] = &[
+    TransitionType::None,
+    TransitionType::Simple,
+    TransitionType::Fade,
+    TransitionType::Left,
+    TransitionType::Right,
+    TransitionType::Top,
+    TransitionType::Bottom,
+    TransitionType::Center,
+    TransitionType::Outer,
+    TransitionType::Any,
+    TransitionType::Wipe,
+    TransitionType::Wave,
+    TransitionType::Grow,
+    TransitionType::PushLeft,
+    TransitionType::PushRight,
+    TransitionType::PushTop,
+    TransitionType::PushBottom,
+    TransitionType::CoverLeft,
+    TransitionType::CoverRight,
+    TransitionType::CoverTop,
+    TransitionType::CoverBottom,
+    TransitionType::RevealLeft,
+    TransitionType::RevealRight,
+    TransitionType::RevealTop,
+    TransitionType::RevealBottom,
+    TransitionType::ZoomIn,
+    TransitionType::ZoomOut,
+    TransitionType::CubeLeft,
+    TransitionType::CubeRight,
+    TransitionType::CubeTop,
+    TransitionType::CubeBottom,
+    TransitionType::FlipHorizontal,
+    TransitionType::FlipVertical,
+    TransitionType::PageCurl,
+    TransitionType::Tiles,
+    TransitionType::Dissolve,
+    TransitionType::Shatter,
+    TransitionType::Particles,
+    TransitionType::BlindsHorizontal,
+    TransitionType::BlindsVertical,
+    TransitionType::Mask(Mask {
+        source: MaskSource::Radial,
+        softness: 0.05,
+    }),
+    TransitionType::Mask(Mask {
+        source: MaskSource::Clock,
+        softness: 0.05,
+    }),
+    TransitionType::Mask(Mask {
+        source: MaskSource::Diamond,
+        softness: 0.05,
+    }),
+    TransitionType::Mask(Mask {
+        source: MaskSource::Noise,
+        softness: 0.05,
+    }),
+];
+
+/// The pure part of a running transition. It knows nothing about time
+/// sources or event loops: it is advanced with the time elapsed since the
+/// transition started and all randomness is drawn from the RNG passed to
+/// [`TransitionState::new`].
+#[derive(Debug, Clone)]
+pub struct TransitionState {
+    config: TransitionConfig,
+    extents: Extents,
+    picked: TransitionType,
+    rand: f32,
+    progress: f32,
+    time_factor: f32,
+    finished: bool,
+}
+
+impl TransitionState {
+    /// `previous` is the transition last picked on the same output, which
+    /// the random pool can be asked not to repeat.
+    pub fn new<R>(
+        config: TransitionConfig,
+        extents: Extents,
+        previous: Option<&TransitionType>,
+        rng: &mut R,
+    ) -> Self
+    where
+        R: Rng + ?Sized,
+    {
+        let picked = match config.transition_type {
+            TransitionType::Random => pick(&config, previous, rng),
+            _ => TransitionType::None,
+        };
+
+        Self {
+            config,
+            extents,
+            picked,
+            rand: rng.random_range(0.0..=1.0),
+            progress: 0.0,
+            time_factor: 0.0,
+            finished: false,
+        }
+    }
+
+    pub fn config(&self) -> &TransitionConfig {
+        &self.config
+    }
+
+    /// Moves the transition to `elapsed` since its start.
+    pub fn advance(&mut self, elapsed: Duration) {
+        if elapsed.as_millis() >= self.config.duration {
+            self.progress = 1.0;
+            self.finished = true;
+            return;
+        }
+
+        let duration = self.config.duration as f32 / 1000.0;
+        self.seek(elapsed.as_secs_f32() / duration);
+    }
+
+    /// Moves the transition to `t`, a fraction of its duration in `0..=1`.
+    pub fn seek(&mut self, t: f32) {
+        let (time_factor, progress) = self.config.easing.evaluate(t);
+
+        self.progress = progress;
+        self.time_factor = time_factor;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// The transition that is actually drawn, with `Random` resolved.
+    pub fn transition_type(&self) -> &TransitionType {
+        match &self.config.transition_type {
+            TransitionType::Random => &self.picked,
+            transition_type => transition_type,
+        }
+    }
+
+    pub fn frames(&self) -> Frames {
+        let progress = self.progress;
+        let transition_type = self.transition_type();
+
+        match transition_type {
+            TransitionType::None => Frames::default(),
+
+            TransitionType::Fade => Frames::incoming(FrameData {
+                filters: Filters {
+                    opacity: progress,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+
+            TransitionType::Simple => Frames::incoming(FrameData {
+                filters: Filters {
+                    opacity: progress,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+
+            TransitionType::Right => {
+                let clip = Clip {
+                    left: 1.0 - progress,
+                    ..Default::default()
+                };
+                Frames::incoming(FrameData {
+                    clip,
+                    ..Default::default()
+                })
+            }
+
+            TransitionType::Left => {
+                let clip = Clip {
+                    right: progress,
+                    ..Default::default()
+                };
+
+                Frames::incoming(FrameData {
+                    clip,
+                    ..Default::default()
+                })
+            }
+
+            TransitionType::Top => {
+                let clip = Clip {
+                    top: 1.0 - progress,
+                    ..Default::default()
+                };
+
+                Frames::incoming(FrameData {
+                    clip,
+                    ..Default::default()
+                })
+            }
+
+            TransitionType::Bottom => {
+                let clip = Clip {
+                    bottom: progress,
+                    ..Default::default()
+                };
+                Frames::incoming(FrameData {
+                    clip,
+                    ..Default::default()
+                })
+            }
+
+            TransitionType::Center => {
+                let center = 0.5;
+                let max_extent = progress * 0.5;
+
+                let x_scale = (self.extents.height / self.extents.width).max(1.0);
+                let y_scale = (self.extents.width / self.extents.height).max(1.0);
+
+                let half_extent_x = max_extent * x_scale;
+                let half_extent_y = max_extent * y_scale;
+
+                let clip = Clip {
+                    left: center - half_extent_x,
+                    top: center - half_extent_y,
+                    right: center + half_extent_x,
+                    bottom: center + half_extent_y,
+                };
+
+                Frames::incoming(FrameData {
+                    clip,
+                    radius: [(1.0 - progress) * (0.8 + 0.2 * (self.time_factor * 5.0).sin()); 4],
+                    ..Default::default()
+                })
+            }
+
+            TransitionType::Outer => {
+                // Center backwards: the old wallpaper shrinks into the
+                // middle over the new one.
+                let center = 0.5;
+                let max_extent = (1.0 - progress) * 0.5;
+
+                let x_scale = (self.extents.height / self.extents.width).max(1.0);
+                let y_scale = (self.extents.width / self.extents.height).max(1.0);
+
+                let half_extent_x = max_extent * x_scale;
+                let half_extent_y = max_extent * y_scale;
+
+                let clip = Clip {
+                    left: center - half_extent_x,
+                    top: center - half_extent_y,
+                    right: center + half_extent_x,
+                    bottom: center + half_extent_y,
+                };
+
+                Frames {
+                    outgoing: FrameData {
+                        clip,
+                        radius: [progress * (0.8 + 0.2 * (self.time_factor * 5.0).sin()); 4],
+                        ..Default::default()
+                    },
+                    incoming: FrameData::default(),
+                    outgoing_on_top: true,
+                    ..Default::default()
+                }
+            }
+
+            TransitionType::Any => {
+                let rand = self.rand;
+                let clip = Clip {
+                    left: rand - progress,
+                    top: rand - progress,
+                    right: rand + progress,
+                    bottom: rand + progress,
+                };
+
+                Frames::incoming(FrameData {
+                    clip,
+                    radius: [(1.0 - progress) * (0.8 + 0.2 * (self.time_factor * 5.0).sin()); 4],
+                    ..Default::default()
+                })
+            }
+
+            TransitionType::PushLeft
+            | TransitionType::PushRight
+            | TransitionType::PushTop
+            | TransitionType::PushBottom => {
+                let offset = edge(transition_type).offset();
+
+                Frames {
+                    outgoing: translated([-offset[0], -offset[1]], progress),
+                    incoming: translated(offset, 1.0 - progress),
+                    outgoing_on_top: false,
+                    ..Default::default()
+                }
+            }
+
+            TransitionType::CoverLeft
+            | TransitionType::CoverRight
+            | TransitionType::CoverTop
+            | TransitionType::CoverBottom => {
+                let offset = edge(transition_type).offset();
+
+                Frames::incoming(translated(offset, 1.0 - progress))
+            }
+
+            TransitionType::RevealLeft
+            | TransitionType::RevealRight
+            | TransitionType::RevealTop
+            | TransitionType::RevealBottom => {
+                let offset = edge(transition_type).offset();
+
+                Frames {
+                    outgoing: translated([-offset[0], -offset[1]], progress),
+                    incoming: FrameData::default(),
+                    outgoing_on_top: true,
+                    ..Default::default()
+                }
+            }
+
+            TransitionType::ZoomIn => Frames {
+                outgoing: zoomed(1.0 + 0.5 * progress, 1.0 - progress),
+                incoming: zoomed(0.75 + 0.25 * progress, progress),
+                outgoing_on_top: false,
+                ..Default::default()
+            },
+
+            TransitionType::ZoomOut => Frames {
+                outgoing: zoomed(1.0 - 0.25 * progress, 1.0 - progress),
+                incoming: zoomed(1.5 - 0.5 * progress, progress),
+                outgoing_on_top: false,
+                ..Default::default()
+            },
+
+            TransitionType::CubeLeft
+            | TransitionType::CubeRight
+            | TransitionType::CubeTop
+            | TransitionType::CubeBottom => {
+                // The cube's center sits half an edge behind the output.
+                let (sign, horizontal) = match edge(transition_type) {
+                    Edge::Left => (1.0, true),
+                    Edge::Right => (-1.0, true),
+                    Edge::Top => (-1.0, false),
+                    Edge::Bottom => (1.0, false),
+                };
+                let depth = if horizontal {
+                    0.5
+                } else {
+                    0.5 * self.extents.height / self.extents.width
+                };
+
+                Frames {
+                    outgoing: rotated(sign * 90.0 * progress, horizontal, -depth),
+                    incoming: rotated(-sign * 90.0 * (1.0 - progress), horizontal, -depth),
+                    outgoing_on_top: false,
+                    ..Default::default()
+                }
+            }
+
+            TransitionType::FlipHorizontal | TransitionType::FlipVertical => {
+                let horizontal = *transition_type == TransitionType::FlipHorizontal;
+
+                // Each side is hidden while it faces away from the viewer.
+                let mut outgoing = rotated(180.0 * progress, horizontal, 0.0);
+                let mut incoming = rotated(-180.0 * (1.0 - progress), horizontal, 0.0);
+                if progress < 0.5 {
+                    incoming.filters.opacity = 0.0;
+                } else {
+                    outgoing.filters.opacity = 0.0;
+                }
+
+                Frames {
+                    outgoing,
+                    incoming,
+                    outgoing_on_top: false,
+                    ..Default::default()
+                }
+            }
+
+            TransitionType::PageCurl => Frames {
+                outgoing: FrameData {
+                    projection: Projection {
+                        curl: progress,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                incoming: FrameData::default(),
+                outgoing_on_top: true,
+                ..Default::default()
+            },
+
+            TransitionType::Mask(mask) => Frames {
+                // Without any softness pixels at exactly the progress would
+                // never switch.
+                mask: Some(MaskFrame {
+                    progress,
+                    softness: mask.softness.max(1.0 / 255.0),
+                }),
+                ..Default::default()
+            },
+
+            TransitionType::Tiles => pieces::tiles(progress, &self.extents),
+            TransitionType::Dissolve => pieces::dissolve(progress, &self.extents, self.seed()),
+            TransitionType::Shatter => pieces::shatter(progress, &self.extents, self.seed()),
+            TransitionType::Particles => pieces::particles(progress, &self.extents, self.seed()),
+            TransitionType::BlindsHorizontal => pieces::blinds(progress, false),
+            TransitionType::BlindsVertical => pieces::blinds(progress, true),
+            TransitionType::Wipe => pieces::wipe(progress),
+            TransitionType::Wave => pieces::wave(progress),
+
+            TransitionType::Grow => {
+                // Out of a point picked for the transition, to the full
+                // output.
+                let point = [self.rand, (self.rand * 7.0).fract()];
+                let scale = progress;
+
+                Frames::incoming(FrameData {
+                    filters: Filters {
+                        opacity: (progress * 4.0).min(1.0),
+                        ..Default::default()
+                    },
+                    transforms: Transforms {
+                        scale_x: scale,
+                        scale_y: scale,
+                        translate: [
+                            (point[0] - 0.5) * (1.0 - scale),
+                            (point[1] - 0.5) * (1.0 - scale),
+                        ],
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+            }
+
+            // Random is resolved to `picked` above, and transitions the
+            // daemon doesn't know cut straight to the wallpaper.
+            TransitionType::Random | TransitionType::Custom(_) => Frames::default(),
+        }
+    }
+
+    /// Seed for the per-piece randomness, fixed for the whole transition.
+    fn seed(&self) -> u32 {
+        (self.rand * u32::MAX as f32) as u32
+    }
+}
+
+/// Draws a transition from the random pool, weighted and filtered by
+/// `enabled_transition_types`.
+fn pick<R>(
+    config: &TransitionConfig,
+    previous: Option<&TransitionType>,
+    rng: &mut R,
+) -> TransitionType
+where
+    R: Rng + ?Sized,
+{
+    let pool = &config.random_pool;
+    let default_pool;
+    let transitions = if pool.transitions.is_empty() {
+        default_pool = RANDOM_TRANSITIONS
+            .iter()
+            .map(|transition_type| WeightedTransition {
+                transition_type: transition_type.clone(),
+                weight: 1.0,
+            })
+            .collect::<Vec<_>>();
+        &default_pool
+    } else {
+        &pool.transitions
+    };
+
+    let candidates = transitions
+        .iter()
+        .filter(|candidate| {
+            candidate.weight > 0.0
+                && candidate.transition_type != TransitionType::Random
+                && config
+                    .enabled_transition_types
+                    .as_ref()
+                    .is_none_or(|enabled| enabled.contains(&candidate.transition_type))
+        })
+        .collect::<Vec<_>>();
+
+    // Repeating is still better than not transitioning at all when the pool
+    // has a single candidate.
+    let fresh = candidates
+        .iter()
+        .copied()
+        .filter(|candidate| Some(&candidate.transition_type) != previous)
+        .collect::<Vec<_>>();
+    let candidates = if pool.avoid_repeat && !fresh.is_empty() {
+        fresh
+    } else {
+        candidates
+    };
+
+    let total: f32 = candidates.iter().map(|candidate| candidate.weight).sum();
+    if candidates.is_empty() || !total.is_finite() {
+        return TransitionType::None;
+    }
+
+    let mut target = rng.random_range(0.0..total);
+    candidates
+        .iter()
+        .find(|candidate| {
+            target -= candidate.weight;
+            target < 0.0
+        })
+        .or(candidates.last())
+        .map(|candidate| candidate.transition_type.clone())
+        .unwrap_or(TransitionType::None)
+}
+
+/// Edge of the output that a directional transition enters from.
+#[derive(Debug, Clone, Copy)]
+enum Edge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl Edge {
+    /// Translation (as a fraction of the output size) that places a layer
+    /// just outside of this edge.
+    fn offset(self) -> [f32; 2] {
+        match self {
+            Edge::Left => [-1., 0.],
+            Edge::Right => [1., 0.],
+            Edge::Top => [0., -1.],
+            Edge::Bottom => [0., 1.],
+        }
+    }
+}
+
+fn translated(offset: [f32; 2], amount: f32) -> FrameData {
+    FrameData {
+        transforms: Transforms {
+            translate: [offset[0] * amount, offset[1] * amount],
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+/// Rotation around the vertical (`horizontal == true`) or horizontal axis
+/// through the center of the output, `depth` output widths behind it.
+fn rotated(degrees: f32, horizontal: bool, depth: f32) -> FrameData {
+    let (rotate_x, rotate_y) = if horizontal {
+        (0.0, degrees)
+    } else {
+        (degrees, 0.0)
+    };
+
+    FrameData {
+        projection: Projection {
+            rotate_x,
+            rotate_y,
+            pivot: [0.5, 0.5, depth],
+            curl: 0.0,
+        },
+        ..Default::default()
+    }
+}
+
+fn zoomed(scale: f32, opacity: f32) -> FrameData {
+    FrameData {
+        filters: Filters {
+            opacity,
+            ..Default::default()
+        },
+        transforms: Transforms {
+            scale_x: scale,
+            scale_y: scale,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+fn edge(transition_type: &TransitionType) -> Edge {
+    match transition_type {
+        TransitionType::PushRight
+        | TransitionType::CoverRight
+        | TransitionType::RevealRight
+        | TransitionType::CubeRight => Edge::Right,
+        TransitionType::PushTop
+        | TransitionType::CoverTop
+        | TransitionType::RevealTop
+        | TransitionType::CubeTop => Edge::Top,
+        TransitionType::PushBottom
+        | TransitionType::CoverBottom
+        | TransitionType::RevealBottom
+        | TransitionType::CubeBottom => Edge::Bottom,
+        _ => Edge::Left,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::{FrameData, Piece, bezier::BezierBuilder};
+    use libmoxpaper::RandomPool;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use std::fmt::Write;
+
+    const GOLDEN: &str = include_str!("golden/transitions.txt");
+    const SAMPLES: [f32; 3] = [0.0, 0.5, 1.0];
+
+    fn state(transition_type: TransitionType, seed: u64) -> TransitionState {
+        let config = TransitionConfig {
+            transition_type,
+            duration: 1000,
+            easing: BezierBuilder::new().linear().into(),
+            ..Default::default()
+        };
+        let extents = Extents {
+            x: 0.,
+            y: 0.,
+            width: 1920.,
+            height: 1080.,
+        };
+
+        TransitionState::new(config, extents, None, &mut ChaCha8Rng::seed_from_u64(seed))
+    }
+
+    /// Translations add `0.` to fold `-0.0` into `0.0`.
+    fn describe(frame_data: &FrameData) -> String {
+        let FrameData {
+            clip,
+            radius,
+            rotation,
+            filters,
+            transforms,
+            projection,
+        } = frame_data;
+
+        let mut description = format!(
+            "clip=[{:.3} {:.3} {:.3} {:.3}] radius={:.3} rotation={:.3} opacity={:.3} \
+             scale=[{:.3} {:.3}] translate=[{:.3} {:.3}]",
+            clip.left,
+            clip.top,
+            clip.right,
+            clip.bottom,
+            radius[0],
+            rotation,
+            filters.opacity,
+            transforms.scale_x,
+            transforms.scale_y,
+            transforms.translate[0] + 0.,
+            transforms.translate[1] + 0.,
+        );
+
+        if !frame_data.is_flat() {
+            _ = write!(
+                description,
+                " rotate=[{:.3} {:.3}] pivot=[{:.3} {:.3} {:.3}] curl={:.3}",
+                projection.rotate_x + 0.,
+                projection.rotate_y + 0.,
+                projection.pivot[0],
+                projection.pivot[1],
+                projection.pivot[2] + 0.,
+                projection.curl,
+            );
+        }
+
+        if transforms.rotate != 0. {
+            _ = write!(description, " spin={:.3}", transforms.rotate);
+        }
+
+        description
+    }
+
+    /// The first, middle and last piece, all of them would bloat the file.
+    fn describe_pieces(out: &mut String, layer: &str, pieces: &[Piece]) {
+        if pieces.is_empty() {
+            return;
+        }
+
+        _ = writeln!(out, "  {layer}_pieces: {}", pieces.len());
+        [0, pieces.len() / 2, pieces.len() - 1]
+            .into_iter()
+            .for_each(|i| {
+                let Piece { rect, frame_data } = &pieces[i];
+                _ = writeln!(
+                    out,
+                    "    [{i}] rect=[{:.3} {:.3} {:.3} {:.3}] {}",
+                    rect[0],
+                    rect[1],
+                    rect[2],
+                    rect[3],
+                    describe(frame_data)
+                );
+            });
+    }
+
+    fn snapshot() -> String {
+        let mut out = String::new();
+
+        RANDOM_TRANSITIONS
+            .iter()
+            .chain(&[TransitionType::Random])
+            .for_each(|transition_type| {
+                let mut state = state(transition_type.clone(), 0);
+                SAMPLES.iter().for_each(|t| {
+                    state.seek(*t);
+                    let frames = state.frames();
+                    _ = writeln!(out, "{transition_type:?} @ {t:.1}");
+                    _ = writeln!(out, "  outgoing: {}", describe(&frames.outgoing));
+                    _ = writeln!(out, "  incoming: {}", describe(&frames.incoming));
+                    _ = writeln!(out, "  outgoing_on_top: {}", frames.outgoing_on_top);
+                    if let Some(mask) = frames.mask {
+                        _ = writeln!(
+                            out,
+                            "  mask: progress={:.3} softness={:.3}",
+                            mask.progress, mask.softness
+                        );
+                    }
+                    describe_pieces(&mut out, "outgoing", &frames.outgoing_pieces);
+                    describe_pieces(&mut out, "incoming", &frames.incoming_pieces);
+                });
+            });
+
+        out
+    }
+
+    /// Set `MOXPAPER_BLESS=1` to regenerate the golden file after an
+    /// intentional change to a transition.
+    #[test]
+    fn transitions_match_golden() {
+        let snapshot = snapshot();
+
+        if std::env::var_os("MOXPAPER_BLESS").is_some() {
+            std::fs::write(
+                concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/src/animation/golden/transitions.txt"
+                ),
+                &snapshot,
+            )
+            .unwrap();
+            return;
+        }
+
+        snapshot
+            .lines()
+            .zip(GOLDEN.lines())
+            .enumerate()
+            .for_each(|(line, (actual, expected))| {
+                assert_eq!(actual, expected, "golden mismatch on line {}", line + 1);
+            });
+        assert_eq!(snapshot.lines().count(), GOLDEN.lines().count());
+    }
+
+    #[test]
+    fn random_transitions_animate() {
+        let still = describe(&FrameData::default());
+
+        RANDOM_TRANSITIONS
+            .iter()
+            .filter(|transition_type| **transition_type != TransitionType::None)
+            .for_each(|transition_type| {
+                let mut state = state(transition_type.clone(), 0);
+                state.seek(0.5);
+                let frames = state.frames();
+
+                assert!(
+                    describe(&frames.outgoing) != still
+                        || describe(&frames.incoming) != still
+                        || !frames.outgoing_pieces.is_empty()
+                        || !frames.incoming_pieces.is_empty()
+                        || frames.mask.is_some(),
+                    "{transition_type:?} cuts instead of animating"
+                );
+            });
+    }
+
+    #[test]
+    fn pool_rejects_unknown_transitions() {
+        assert!(WeightedTransition::new(TransitionType::Custom("swirl".into()), 1.0).is_err());
+        assert!(WeightedTransition::new(TransitionType::Fade, -1.0).is_err());
+        assert!(WeightedTransition::new(TransitionType::Fade, 2.0).is_ok());
+
+        let pool = r#"{"transitions": [{"transition_type": "swirl"}]}"#;
+        assert!(serde_json::from_str::<RandomPool>(pool).is_err());
+        let pool = r#"{"transitions": [{"transition_type": "fade", "weight": 2}]}"#;
+        assert!(serde_json::from_str::<RandomPool>(pool).is_ok());
+    }
+
+    #[test]
+    fn same_seed_picks_same_random_transition() {
+        let picks = |seed: u64| {
+            (0..8)
+                .map(|i| {
+                    state(TransitionType::Random, seed + i)
+                        .transition_type()
+                        .clone()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(picks(7), picks(7));
+    }
+
+    #[test]
+    fn random_pool_respects_weights_and_repeats() {
+        let pool = |avoid_repeat| RandomPool {
+            transitions: vec![
+                WeightedTransition {
+                    transition_type: TransitionType::Fade,
+                    weight: 1.0,
+                },
+                WeightedTransition {
+                    transition_type: TransitionType::Wipe,
+                    weight: 1.0,
+                },
+                WeightedTransition {
+                    transition_type: TransitionType::Grow,
+                    weight: 0.0,
+                },
+            ],
+            avoid_repeat,
+        };
+        let picks = |avoid_repeat| {
+            let config = TransitionConfig {
+                transition_type: TransitionType::Random,
+                random_pool: pool(avoid_repeat),
+                ..Default::default()
+            };
+            let mut rng = ChaCha8Rng::seed_from_u64(0);
+            (0..64)
+                .map(|_| {
+                    TransitionState::new(
+                        config.clone(),
+                        Extents::default(),
+                        Some(&TransitionType::Fade),
+                        &mut rng,
+                    )
+                    .transition_type()
+                    .clone()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let picks_with_repeats = picks(false);
+        assert!(picks_with_repeats.contains(&TransitionType::Fade));
+        assert!(!picks_with_repeats.contains(&TransitionType::Grow));
+        assert!(picks(true).iter().all(|pick| *pick == TransitionType::Wipe));
+    }
+
+    #[test]
+    fn advance_follows_elapsed_time() {
+        let mut state = state(TransitionType::Fade, 0);
+
+        state.advance(Duration::from_millis(500));
+        assert!((state.frames().incoming.filters.opacity - 0.5).abs() < 1e-4);
+        assert!(!state.is_finished());
+
+        state.advance(Duration::from_millis(1000));
+        assert_eq!(state.frames().incoming.filters.opacity, 1.0);
+        assert!(state.is_finished());
+    }
+}
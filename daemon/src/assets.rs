@@ -1,8 +1,13 @@
-use anyhow::Context;
-use libmoxpaper::{
-    image_data::ImageData,
-    ResizeStrategy, Transition,
+use crate::{
+    animated::{self, Motion},
+    config::Wallpaper,
+    generated,
+    output::shader::{self, ShaderSource},
+    solar::SunSource,
+    timed::{self, TimedSource},
 };
+use anyhow::Context;
+use libmoxpaper::{Generated, ResizeStrategy, Sequence, Shader, Transition, image_data::ImageData};
 use resvg::usvg;
 use std::{collections::HashMap, sync::Arc};
 
@@ -17,6 +22,20 @@ pub struct AssetData {
     pub image: ImageData,
     pub resize: ResizeStrategy,
     pub transition: Transition,
+    /// Frames played after the first one, for animated images
+    pub motion: Option<Motion>,
+    /// Draws the wallpaper live instead of showing `image`
+    pub shader: Option<ShaderSource>,
+    /// Images blended over the day in place of `image`, which is the one
+    /// shown when loaded
+    pub blend: Option<BlendSource>,
+}
+
+/// Images a wallpaper blends between as time goes on.
+#[derive(Clone)]
+pub enum BlendSource {
+    Sun(SunSource),
+    Timed(TimedSource),
 }
 
 impl AssetData {
@@ -25,6 +44,65 @@ impl AssetData {
             image,
             resize,
             transition,
+            motion: None,
+            shader: None,
+            blend: None,
+        }
+    }
+
+    /// Loads a wallpaper from the config, logging why it can't be shown.
+    pub fn load(wallpaper: &Wallpaper) -> Option<Self> {
+        let path = &wallpaper.path;
+        if timed::is_source(path) {
+            return Self::timed(path, wallpaper.resize, wallpaper.transition.clone());
+        }
+
+        let (image, motion, shader) = if path.extension().is_some_and(|e| e == "wgsl") {
+            let shader = Shader {
+                path: path.to_path_buf(),
+                fps: wallpaper.fps,
+            };
+            (shader::placeholder(), None, Some(shader::load(&shader)?))
+        } else if Sequence::is_source(path) {
+            let sequence = Sequence {
+                source: path.to_path_buf(),
+                fps: wallpaper.fps.unwrap_or(Sequence::DEFAULT_FPS),
+                playback: wallpaper.playback,
+                offset: wallpaper.frame_offset,
+            };
+            let (image, motion) = animated::sequence(&sequence, wallpaper.repeat)?;
+            (image, Some(motion), None)
+        } else {
+            match image::open(path) {
+                Ok(img) => (
+                    ImageData::from(img),
+                    animated::probe(path, wallpaper.repeat),
+                    None,
+                ),
+                Err(e) => {
+                    log::error!("{e}: {}", path.display());
+                    return None;
+                }
+            }
+        };
+
+        Some(Self {
+            image,
+            resize: wallpaper.resize,
+            transition: wallpaper.transition.clone(),
+            motion,
+            shader,
+            blend: None,
+        })
+    }
+
+    /// Scales the image to the given output size using its resize strategy.
+    pub fn into_resized(self, width: u32, height: u32) -> anyhow::Result<ImageData> {
+        match self.resize {
+            ResizeStrategy::No => Ok(self.image.pad(width, height, &[0, 0, 0])),
+            ResizeStrategy::Fit => self.image.resize_to_fit(width, height),
+            ResizeStrategy::Crop => self.image.resize_crop(width, height),
+            ResizeStrategy::Stretch => self.image.resize_stretch(width, height),
         }
     }
 }
@@ -40,6 +118,10 @@ pub enum FallbackImage {
         data: Box<[u8]>,
         transition: Transition,
     },
+    Generated {
+        generated: Generated,
+        transition: Transition,
+    },
 }
 
 impl From<AssetData> for FallbackImage {
@@ -74,6 +156,14 @@ impl AssetsManager {
                 FallbackImage::Svg { data, transition } => {
                     self.render_svg_fallback(data, width, height, transition)
                 }
+                FallbackImage::Generated {
+                    generated,
+                    transition,
+                } => AssetData::new(
+                    generated::render(generated, width, height),
+                    ResizeStrategy::No,
+                    transition.clone(),
+                ),
             })
         })
     }
@@ -123,7 +213,21 @@ impl AssetsManager {
         self.images.insert(key, asset_data);
     }
 
+    /// Whether the output has a wallpaper of its own, rather than the
+    /// fallback.
+    pub fn has_asset(&self, name: &str) -> bool {
+        self.images.contains_key(name)
+    }
+
+    pub fn remove_asset(&mut self, key: &str) {
+        self.images.remove(key);
+    }
+
     pub fn set_fallback(&mut self, fallback: FallbackImage) {
         self.fallback = Some(fallback);
     }
+
+    pub fn clear_fallback(&mut self) {
+        self.fallback = None;
+    }
 }
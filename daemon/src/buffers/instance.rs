@@ -19,7 +19,7 @@ where
                 contents: unsafe {
                     std::slice::from_raw_parts(
                         data as *const [Self::DataType] as *const u8,
-                        std::mem::size_of_val(data) * data.len(),
+                        std::mem::size_of_val(data),
                     )
                 },
             }),
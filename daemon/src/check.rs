@@ -0,0 +1,201 @@
+use crate::{
+    animated::Motion,
+    config::{Config, Format, Problem, Wallpaper},
+    output::shader::ShaderSource,
+    playlist,
+    timed::{self, TimedSource},
+};
+use libmoxpaper::{BezierChoice, Playback, Repeat, Sequence, Shader, Transition};
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+/// Evaluates the config at `path`, or the one the daemon would read, and
+/// prints everything that keeps parts of it from working.
+pub fn run(path: Option<&Path>) -> anyhow::Result<()> {
+    let Some((path, code)) = Config::source(path)? else {
+        println!("No config file found, the defaults are used");
+        return Ok(());
+    };
+
+    let (config, mut problems) = Config::parse(&code, Format::of(&path), &[])
+        .map_err(|e| anyhow::anyhow!("{} doesn't evaluate: {e}", path.display()))?;
+    problems.extend(check(&config));
+
+    if problems.is_empty() {
+        println!("{}: ok", path.display());
+        return Ok(());
+    }
+
+    problems.iter().for_each(|problem| println!("{problem}"));
+    Err(anyhow::anyhow!(
+        "{}: {} problem{}",
+        path.display(),
+        problems.len(),
+        if problems.len() == 1 { "" } else { "s" }
+    ))
+}
+
+/// Problems of a config that fits the schema: files that can't be shown,
+/// beziers that aren't defined and buckets missing settings.
+fn check(config: &Config) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut report = |key: String, result: anyhow::Result<()>| {
+        if let Err(e) = result {
+            problems.push(Problem {
+                key,
+                message: format!("{e:#}"),
+            });
+        }
+    };
+
+    let mut transitions = vec![("default_bezier".to_string(), None)];
+
+    sorted(&config.wallpaper).for_each(|(output, wallpaper)| {
+        let key = format!("wallpaper.{output}");
+        report(key.clone(), source(wallpaper));
+        transitions.push((key, Some(&wallpaper.transition)));
+    });
+
+    sorted(&config.playlist).for_each(|(output, playlist)| {
+        let key = format!("playlist.{output}");
+        let result = playlist::entries(playlist).and_then(|entries| {
+            if entries.is_empty() {
+                return Err(anyhow::anyhow!("No images found"));
+            }
+            match entries.iter().find(|path| !path.exists()) {
+                Some(path) => Err(anyhow::anyhow!("{} doesn't exist", path.display())),
+                None => Ok(()),
+            }
+        });
+        report(key.clone(), result);
+        transitions.push((key, Some(&playlist.transition)));
+    });
+
+    sorted(&config.schedule).for_each(|(output, entries)| {
+        entries.iter().enumerate().for_each(|(index, entry)| {
+            let key = format!("schedule.{output}.{index}");
+            let wallpaper = Wallpaper {
+                path: entry.path.clone(),
+                resize: entry.resize,
+                transition: entry.transition.clone(),
+                repeat: entry.repeat,
+                fps: None,
+                playback: Playback::default(),
+                frame_offset: 0,
+            };
+            report(key.clone(), source(&wallpaper));
+            transitions.push((key, Some(&entry.transition)));
+        });
+    });
+
+    sorted(&config.sun).for_each(|(output, sun)| {
+        let key = format!("sun.{output}");
+        if config.location.is_none() {
+            report(
+                key.clone(),
+                Err(anyhow::anyhow!("Needs `location` to be set")),
+            );
+        }
+        if sun.variants.is_empty() {
+            report(key.clone(), Err(anyhow::anyhow!("Has no variants")));
+        }
+        sun.variants
+            .iter()
+            .enumerate()
+            .for_each(|(index, variant)| {
+                let result = image::open(&variant.path)
+                    .map(drop)
+                    .map_err(|e| anyhow::anyhow!("{e}: {}", variant.path.display()));
+                report(format!("{key}.variants.{index}"), result);
+            });
+        transitions.push((key, Some(&sun.transition)));
+    });
+
+    transitions.iter().for_each(|(key, transition)| {
+        let beziers = match transition {
+            Some(transition) => beziers(transition),
+            None => vec![&config.default_bezier],
+        };
+        beziers.into_iter().for_each(|bezier| {
+            if let BezierChoice::Named(name) = bezier
+                && !config.bezier.contains_key(name)
+            {
+                report(
+                    key.clone(),
+                    Err(anyhow::anyhow!("Bezier `{name}` isn't defined in `bezier`")),
+                );
+            }
+        });
+    });
+
+    #[cfg(feature = "s3")]
+    {
+        let mut buckets = config.buckets.iter().collect::<Vec<_>>();
+        buckets.sort_by_key(|(name, _)| *name);
+        buckets.into_iter().for_each(|(name, bucket)| {
+            let key = format!("buckets.{name}");
+            report(key.clone(), bucket.get_access_key().map(drop));
+            report(key.clone(), bucket.get_secret_key().map(drop));
+            if bucket.region.is_none()
+                && s3::Region::from_env("S3_REGION", Some("S3_ENDPOINT")).is_err()
+            {
+                report(
+                    key,
+                    Err(anyhow::anyhow!(
+                        "Needs `region`, or S3_REGION and S3_ENDPOINT in the environment"
+                    )),
+                );
+            }
+        });
+    }
+
+    problems
+}
+
+/// Loads a wallpaper the way [`crate::assets::AssetData::load`] does,
+/// failing with the reason instead of logging it.
+fn source(wallpaper: &Wallpaper) -> anyhow::Result<()> {
+    let path = &wallpaper.path;
+    if timed::is_source(path) {
+        TimedSource::load(path).map(drop)
+    } else if path.extension().is_some_and(|e| e == "wgsl") {
+        ShaderSource::load(&Shader {
+            path: path.to_path_buf(),
+            fps: wallpaper.fps,
+        })
+        .map(drop)
+    } else if Sequence::is_source(path) {
+        let sequence = Sequence {
+            source: path.to_path_buf(),
+            fps: wallpaper.fps.unwrap_or(Sequence::DEFAULT_FPS),
+            playback: wallpaper.playback,
+            offset: wallpaper.frame_offset,
+        };
+        Motion::sequence(&sequence, Repeat::default()).map(drop)
+    } else {
+        image::open(path)
+            .map(drop)
+            .map_err(|e| anyhow::anyhow!("{e}: {}", path.display()))
+    }
+}
+
+/// The beziers a transition and its stages use.
+fn beziers(transition: &Transition) -> Vec<&BezierChoice> {
+    transition
+        .bezier
+        .iter()
+        .chain(
+            transition
+                .stages
+                .iter()
+                .filter_map(|stage| stage.bezier.as_ref()),
+        )
+        .collect()
+}
+
+/// Entries sorted by key, so that problems are reported in the same order
+/// every time.
+fn sorted<V>(entries: &HashMap<Arc<str>, V>) -> impl Iterator<Item = (&Arc<str>, &V)> {
+    let mut entries = entries.iter().collect::<Vec<_>>();
+    entries.sort_by_key(|(key, _)| *key);
+    entries.into_iter()
+}
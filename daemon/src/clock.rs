@@ -0,0 +1,146 @@
+use serde::Deserialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+unsafe extern "C" {
+    /// Reloads the time zone, `localtime_r` doesn't have to.
+    fn tzset();
+}
+
+/// Wall clock time in the system's time zone, daylight saving included.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LocalTime {
+    pub year: i32,
+    /// 1 to 12
+    pub month: u32,
+    /// Day of the month, from 1
+    pub day: u32,
+    /// Seconds since midnight, with fractions
+    pub seconds: f64,
+}
+
+impl LocalTime {
+    pub fn now() -> Self {
+        Self::at(SystemTime::now())
+    }
+
+    pub fn at(time: SystemTime) -> Self {
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let secs = since_epoch.as_secs() as libc::time_t;
+
+        // SAFETY: `tm` is plain data that `localtime_r` fills in, and
+        // `tzset` only reads the environment.
+        let tm = unsafe {
+            let mut tm = std::mem::zeroed::<libc::tm>();
+            tzset();
+            libc::localtime_r(&secs, &mut tm);
+            tm
+        };
+
+        Self {
+            year: tm.tm_year + 1900,
+            month: tm.tm_mon as u32 + 1,
+            day: tm.tm_mday as u32,
+            seconds: (tm.tm_hour * 3600 + tm.tm_min * 60 + tm.tm_sec) as f64
+                + since_epoch.subsec_nanos() as f64 / 1e9,
+        }
+    }
+
+    /// The instant the wall clock shows this time, `None` if it can't be
+    /// told.
+    pub fn system_time(self) -> Option<SystemTime> {
+        let seconds = self.seconds as i32;
+
+        // SAFETY: `tm` is plain data that `mktime` reads and normalizes.
+        let time = unsafe {
+            let mut tm = std::mem::zeroed::<libc::tm>();
+            tm.tm_year = self.year - 1900;
+            tm.tm_mon = self.month as i32 - 1;
+            tm.tm_mday = self.day as i32;
+            tm.tm_hour = seconds / 3600;
+            tm.tm_min = seconds / 60 % 60;
+            tm.tm_sec = seconds % 60;
+            // Let the time zone tell whether daylight saving applies.
+            tm.tm_isdst = -1;
+            tzset();
+            libc::mktime(&mut tm)
+        };
+
+        // `mktime` fails with -1, times before the epoch aren't needed.
+        u64::try_from(time)
+            .ok()
+            .map(|time| UNIX_EPOCH + Duration::from_secs(time))
+    }
+}
+
+/// Time on the wall clock, "HH:MM" or "HH:MM:SS" in the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TimeOfDay {
+    seconds: u32,
+}
+
+impl TimeOfDay {
+    /// Seconds since midnight
+    pub fn seconds(self) -> u32 {
+        self.seconds
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let mut parts = value.split(':').map(|part| part.trim().parse::<u32>());
+        let (Some(hours), Some(minutes), seconds, None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(anyhow::anyhow!(
+                "Expected HH:MM or HH:MM:SS, found '{value}'"
+            ));
+        };
+        let (hours, minutes, seconds) = (hours?, minutes?, seconds.transpose()?.unwrap_or(0));
+
+        if hours > 23 || minutes > 59 || seconds > 59 {
+            return Err(anyhow::anyhow!("Time of day out of range: '{value}'"));
+        }
+
+        Ok(Self {
+            seconds: hours * 3600 + minutes * 60 + seconds,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(value: &str) -> anyhow::Result<u32> {
+        TimeOfDay::try_from(value.to_string()).map(TimeOfDay::seconds)
+    }
+
+    #[test]
+    fn time_of_day() {
+        assert_eq!(time("00:00").unwrap(), 0);
+        assert_eq!(time("08:30").unwrap(), 8 * 3600 + 30 * 60);
+        assert_eq!(time("8:05:09").unwrap(), 8 * 3600 + 5 * 60 + 9);
+        assert_eq!(time(" 23:59:59 ").unwrap(), 24 * 3600 - 1);
+    }
+
+    #[test]
+    fn time_of_day_out_of_range() {
+        assert!(time("24:00").is_err());
+        assert!(time("12:60").is_err());
+        assert!(time("12:00:60").is_err());
+        assert!(time("-1:00").is_err());
+    }
+
+    #[test]
+    fn time_of_day_malformed() {
+        assert!(time("").is_err());
+        assert!(time("12").is_err());
+        assert!(time("12:00:00:00").is_err());
+        assert!(time("noon").is_err());
+        assert!(time("12:").is_err());
+        assert!(time("12.5:00").is_err());
+    }
+}
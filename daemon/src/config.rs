@@ -1,22 +1,159 @@
-use libmoxpaper::{BezierChoice, ResizeStrategy, Transition, TransitionType};
-use serde::Deserialize;
+use crate::clock::TimeOfDay;
+use clap::ValueEnum;
+use libmoxpaper::{
+    BezierChoice, Choreography, EasingFunction, InterruptPolicy, Playback, RandomPool, Repeat,
+    ResizeStrategy, Transition, TransitionType,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::{
     collections::HashMap,
+    fmt,
     path::{Path, PathBuf},
     sync::Arc,
 };
 use tvix_serde::from_str;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, PartialEq)]
 pub struct Wallpaper {
     pub path: Box<Path>,
     #[serde(default)]
     pub resize: ResizeStrategy,
     #[serde(default)]
     pub transition: Transition,
+    #[serde(default)]
+    pub repeat: Repeat,
+    /// Frame rate when `path` is a directory or glob of frames, or a `.wgsl`
+    /// shader
+    #[serde(default)]
+    pub fps: Option<f32>,
+    #[serde(default)]
+    pub playback: Playback,
+    /// Frames this output is ahead in the sequence, to set outputs showing
+    /// the same frames apart
+    #[serde(default)]
+    pub frame_offset: u32,
+}
+
+/// Wallpapers an output cycles through on its own timer
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Playlist {
+    pub source: PlaylistSource,
+    /// Seconds each wallpaper is shown for
+    #[serde(default = "get_default_playlist_interval")]
+    pub interval: u64,
+    #[serde(default)]
+    pub order: PlaylistOrder,
+    /// Also look for images in the subdirectories of `source`
+    #[serde(default)]
+    pub recursive: bool,
+    #[serde(default)]
+    pub cursor: PlaylistCursor,
+    #[serde(default)]
+    pub resize: ResizeStrategy,
+    #[serde(default)]
+    pub transition: Transition,
+    #[serde(default)]
+    pub repeat: Repeat,
+}
+
+/// A directory or glob such as `"/usr/share/backgrounds/*.png"`, or a list of images
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum PlaylistSource {
+    Paths(Vec<PathBuf>),
+    Pattern(PathBuf),
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaylistOrder {
+    /// By file name, with numbers in them compared as numbers
+    #[default]
+    Sequential,
+    /// Shuffled again after every pass
+    Shuffle,
+}
+
+/// Whether the outputs a playlist is shown on change together
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaylistCursor {
+    /// Every output shows the same wallpaper
+    #[default]
+    Shared,
+    /// Every output goes through the playlist on its own
+    PerOutput,
+}
+
+/// Wallpaper shown from its time of day until the time of the next entry
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ScheduleEntry {
+    pub time: TimeOfDay,
+    pub path: Box<Path>,
+    #[serde(default)]
+    pub resize: ResizeStrategy,
+    #[serde(default)]
+    pub transition: Transition,
+    #[serde(default)]
+    pub repeat: Repeat,
+}
+
+/// Where on earth the wallpapers following the sun are shown, in degrees
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    /// Positive north of the equator
+    pub latitude: f64,
+    /// Positive east of Greenwich
+    pub longitude: f64,
+}
+
+/// Wallpaper with variants for the height of the sun
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SunWallpaper {
+    pub variants: Vec<SunVariant>,
+    /// Blends neighbouring variants by elevation instead of switching
+    /// between them
+    #[serde(default)]
+    pub crossfade: bool,
+    #[serde(default)]
+    pub resize: ResizeStrategy,
+    #[serde(default)]
+    pub transition: Transition,
+}
+
+/// Image shown from the elevation of the sun up to the next variant's
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SunVariant {
+    pub path: Box<Path>,
+    /// Degrees above the horizon, negative below it
+    pub elevation: f64,
+    /// Only shown in the morning or evening, e.g. to tell sunrise from
+    /// sunset
+    #[serde(default)]
+    pub phase: Option<SunPhase>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SunPhase {
+    /// Before solar noon
+    Rising,
+    /// After solar noon
+    Setting,
+}
+
+/// A named timing function, either the four control points of a cubic
+/// bezier or one of the other easings, e.g. `"bounce_out"` or
+/// `{ spring = { stiffness = 170; damping = 8; }; }`.
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum BezierDefinition {
+    Cubic((f32, f32, f32, f32)),
+    Easing(EasingFunction),
+}
+
+#[derive(Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum PowerPreference {
     HighPerformance,
@@ -24,7 +161,7 @@ pub enum PowerPreference {
 }
 
 #[cfg(feature = "s3")]
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, PartialEq)]
 pub struct S3Bucket {
     pub url: String,
     pub region: Option<String>,
@@ -67,13 +204,14 @@ impl S3Bucket {
     }
 }
 
-#[derive(Deserialize)]
-#[serde(default)]
+#[derive(Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
     #[cfg(feature = "s3")]
     pub buckets: HashMap<String, S3Bucket>,
     pub power_preference: Option<PowerPreference>,
     pub enabled_transition_types: Option<Arc<[TransitionType]>>,
+    pub random_pool: Option<RandomPool>,
     #[serde(default = "get_default_transition_duration")]
     pub default_transition_duration: u128,
     #[serde(default = "get_default_transition_type")]
@@ -81,8 +219,23 @@ pub struct Config {
     #[serde(default = "get_default_bezier")]
     pub default_bezier: BezierChoice,
     pub default_fps: Option<u64>,
+    pub default_interrupt_policy: InterruptPolicy,
+    pub default_choreography: Choreography,
     pub wallpaper: HashMap<Arc<str>, Wallpaper>,
-    pub bezier: HashMap<Box<str>, (f32, f32, f32, f32)>,
+    /// Playlists by the output they are shown on, or "any" for every output
+    /// without a wallpaper or playlist of its own
+    pub playlist: HashMap<Arc<str>, Playlist>,
+    /// Wallpapers by time of day, keyed like `playlist`. The last entry of
+    /// the day is shown until the first one of the next.
+    pub schedule: HashMap<Arc<str>, Vec<ScheduleEntry>>,
+    /// Needed by `sun`, sunrise and sunset are computed from it
+    pub location: Option<Location>,
+    /// Wallpapers following the sun, keyed like `playlist`
+    pub sun: HashMap<Arc<str>, SunWallpaper>,
+    pub bezier: HashMap<Box<str>, BezierDefinition>,
+    /// Whether the file is a function of the outputs, see [`OutputArgument`]
+    #[serde(skip)]
+    pub of_outputs: bool,
 }
 
 impl Default for Config {
@@ -92,16 +245,237 @@ impl Default for Config {
             buckets: HashMap::new(),
             power_preference: None,
             enabled_transition_types: None,
+            random_pool: None,
             default_transition_duration: 3000,
             default_transition_type: TransitionType::Simple,
             default_bezier: BezierChoice::Custom((0.54, 0., 0.34, 0.99)),
             default_fps: None,
+            default_interrupt_policy: InterruptPolicy::default(),
+            default_choreography: Choreography::default(),
             wallpaper: HashMap::new(),
+            playlist: HashMap::new(),
+            schedule: HashMap::new(),
+            location: None,
+            sun: HashMap::new(),
             bezier: HashMap::new(),
+            of_outputs: false,
         }
     }
 }
 
+/// Settings that are attribute sets of entries, such as one wallpaper for
+/// each output.
+const ENTRIES: [&str; 6] = [
+    "buckets",
+    "wallpaper",
+    "playlist",
+    "schedule",
+    "sun",
+    "bezier",
+];
+
+/// What a config written as a Nix function of the outputs is told about
+/// each of them, e.g.
+/// `outputs: { wallpaper = builtins.listToAttrs (map (o: ...) outputs); }`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct OutputArgument {
+    pub name: Arc<str>,
+    /// Logical size, in pixels
+    pub width: u32,
+    pub height: u32,
+    pub scale: i32,
+    /// `null` if the compositor doesn't tell
+    pub make: Option<Arc<str>>,
+    pub model: Option<Arc<str>>,
+}
+
+/// Language a config file is written in, told apart by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Format {
+    Nix,
+    Toml,
+    Json,
+}
+
+impl Format {
+    /// Nix unless the extension says otherwise.
+    pub fn of(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::Toml,
+            Some("json") => Self::Json,
+            _ => Self::Nix,
+        }
+    }
+
+    /// Evaluates `code`, calling it with `outputs` if it's a Nix function.
+    /// Also returns whether it is one, so that it's evaluated again when the
+    /// outputs change.
+    pub fn evaluate(self, code: &str, outputs: &[OutputArgument]) -> anyhow::Result<(Value, bool)> {
+        #[derive(Deserialize)]
+        struct Evaluated {
+            function: bool,
+            config: Value,
+        }
+
+        match self {
+            Self::Nix => {
+                let mut arguments = String::new();
+                print_nix(&serde_json::to_value(outputs)?, 0, &mut arguments);
+                // The code starts on the first line, so that errors point to
+                // the lines of the file.
+                let code = format!(
+                    "let config = ({code}\n); in {{ \
+                     function = builtins.isFunction config; \
+                     config = if builtins.isFunction config then config {arguments} else config; \
+                     }}"
+                );
+                let evaluated: Evaluated = from_str(&code).map_err(|e| anyhow::anyhow!("{e}"))?;
+                Ok((evaluated.config, evaluated.function))
+            }
+            Self::Toml => Ok((toml::from_str(code)?, false)),
+            Self::Json => Ok((serde_json::from_str(code)?, false)),
+        }
+    }
+
+    /// Whether `code` is a Nix function of the outputs, without calling it.
+    pub fn is_function(self, code: &str) -> bool {
+        match self {
+            Self::Nix => from_str(&format!("builtins.isFunction ({code}\n)")).unwrap_or(false),
+            Self::Toml | Self::Json => false,
+        }
+    }
+
+    /// Writes an evaluated config back out.
+    pub fn print(self, value: &Value) -> anyhow::Result<String> {
+        match self {
+            Self::Nix => {
+                let mut nix = String::new();
+                print_nix(value, 0, &mut nix);
+                Ok(nix + "\n")
+            }
+            // TOML has no null, unset is the same to the config.
+            Self::Toml => Ok(toml::to_string_pretty(&without_nulls(value.clone()))?),
+            Self::Json => Ok(serde_json::to_string_pretty(value)? + "\n"),
+        }
+    }
+}
+
+fn without_nulls(value: Value) -> Value {
+    match value {
+        Value::Array(values) => Value::Array(
+            values
+                .into_iter()
+                .filter(|value| !value.is_null())
+                .map(without_nulls)
+                .collect(),
+        ),
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key, without_nulls(value)))
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+fn print_nix(value: &Value, indent: usize, nix: &mut String) {
+    let pad = |indent: usize| "  ".repeat(indent);
+    match value {
+        Value::Null => nix.push_str("null"),
+        Value::Bool(value) => nix.push_str(&value.to_string()),
+        // Negative numbers would be subtractions in lists.
+        Value::Number(number) if number.as_f64().is_some_and(|n| n < 0.) => {
+            nix.push_str(&format!("({number})"))
+        }
+        Value::Number(number) => nix.push_str(&number.to_string()),
+        Value::String(string) => nix.push_str(&nix_string(string)),
+        Value::Array(values) if values.is_empty() => nix.push_str("[ ]"),
+        Value::Array(values) => {
+            nix.push_str("[\n");
+            values.iter().for_each(|value| {
+                nix.push_str(&pad(indent + 1));
+                print_nix(value, indent + 1, nix);
+                nix.push('\n');
+            });
+            nix.push_str(&pad(indent));
+            nix.push(']');
+        }
+        Value::Object(fields) if fields.is_empty() => nix.push_str("{ }"),
+        Value::Object(fields) => {
+            nix.push_str("{\n");
+            fields.iter().for_each(|(key, value)| {
+                nix.push_str(&pad(indent + 1));
+                nix.push_str(&nix_key(key));
+                nix.push_str(" = ");
+                print_nix(value, indent + 1, nix);
+                nix.push_str(";\n");
+            });
+            nix.push_str(&pad(indent));
+            nix.push('}');
+        }
+    }
+}
+
+fn nix_string(string: &str) -> String {
+    let escaped = string
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace("${", "\\${")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace('\t', "\\t");
+    format!("\"{escaped}\"")
+}
+
+/// Attribute names are quoted unless they are identifiers, such as `DP-1`.
+fn nix_key(key: &str) -> String {
+    const KEYWORDS: [&str; 10] = [
+        "assert", "else", "if", "in", "inherit", "let", "or", "rec", "then", "with",
+    ];
+
+    let mut chars = key.chars();
+    let identifier = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '\'' | '-'))
+        && !KEYWORDS.contains(&key);
+
+    match identifier {
+        true => key.to_owned(),
+        false => nix_string(key),
+    }
+}
+
+/// Part of the config left out, and why.
+#[derive(Debug)]
+pub struct Problem {
+    /// Attribute path, e.g. `wallpaper.DP-1.transition.bezier`
+    pub key: String,
+    pub message: String,
+}
+
+impl From<serde_path_to_error::Error<serde_json::Error>> for Problem {
+    fn from(error: serde_path_to_error::Error<serde_json::Error>) -> Self {
+        let key = match error.path().iter().next() {
+            Some(_) => error.path().to_string(),
+            None => "config".into(),
+        };
+
+        Self {
+            key,
+            message: error.into_inner().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.key, self.message)
+    }
+}
+
 fn get_default_transition_duration() -> u128 {
     3000
 }
@@ -110,6 +484,10 @@ fn get_default_transition_type() -> TransitionType {
     TransitionType::Simple
 }
 
+fn get_default_playlist_interval() -> u64 {
+    1800
+}
+
 fn get_default_bezier() -> BezierChoice {
     BezierChoice::Custom((0.54, 0., 0.34, 0.99))
 }
@@ -119,46 +497,140 @@ impl Config {
     where
         T: AsRef<Path>,
     {
-        let nix_code = if let Some(p) = path {
-            match std::fs::read_to_string(p.as_ref()) {
-                Ok(content) => content,
-                Err(e) => {
-                    log::error!("Failed to read config file: {e}");
-                    return Config::default();
-                }
+        let path = path.as_ref().map(AsRef::as_ref);
+        Self::read(path, &[]).unwrap_or_else(|e| {
+            log::error!("{e}");
+            // A function may only fail without outputs, it's called again
+            // once they're known.
+            let of_outputs = matches!(
+                Self::source(path),
+                Ok(Some((path, code))) if Format::of(&path).is_function(&code)
+            );
+            Config {
+                of_outputs,
+                ..Config::default()
             }
-        } else {
-            match Self::xdg_config_dir() {
-                Ok(base) => {
-                    let candidates = [
-                        base.join("mox/moxpaper/default.nix"),
-                        base.join("mox/moxpaper.nix"),
-                    ];
-                    match candidates
-                        .iter()
-                        .find_map(|p| std::fs::read_to_string(p).ok())
-                    {
-                        Some(content) => content,
-                        None => {
-                            log::warn!("Config file not found");
-                            return Config::default();
-                        }
-                    }
+        })
+    }
+
+    /// Evaluates the config at `path`, or the first of
+    /// [`Config::candidates`] that exists, leaving out the parts that don't
+    /// fit. Without a config file that's the default one.
+    pub fn read(path: Option<&Path>, outputs: &[OutputArgument]) -> anyhow::Result<Self> {
+        let Some((path, code)) = Self::source(path)? else {
+            log::warn!("Config file not found");
+            return Ok(Config::default());
+        };
+
+        let (config, problems) = Self::parse(&code, Format::of(&path), outputs)?;
+        problems
+            .iter()
+            .for_each(|problem| log::error!("Ignoring {problem}"));
+
+        Ok(config)
+    }
+
+    /// The config file at `path`, or the first of [`Config::candidates`]
+    /// that exists, and its contents.
+    pub fn source(path: Option<&Path>) -> anyhow::Result<Option<(PathBuf, String)>> {
+        match path {
+            Some(p) => std::fs::read_to_string(p)
+                .map(|content| Some((p.to_path_buf(), content)))
+                .map_err(|e| anyhow::anyhow!("Failed to read config file: {e}")),
+            None => {
+                let candidates = Self::candidates()
+                    .map_err(|e| anyhow::anyhow!("Failed to determine config directory: {e}"))?;
+                Ok(candidates.into_iter().find_map(|p| {
+                    let content = std::fs::read_to_string(&p).ok()?;
+                    Some((p, content))
+                }))
+            }
+        }
+    }
+
+    /// Evaluates `code`, only failing if it doesn't evaluate at all.
+    /// Settings and entries that don't fit are left out and returned with
+    /// why.
+    pub fn parse(
+        code: &str,
+        format: Format,
+        outputs: &[OutputArgument],
+    ) -> anyhow::Result<(Self, Vec<Problem>)> {
+        let (value, of_outputs) = format.evaluate(code, outputs)?;
+        let (mut config, problems) = Self::from_value(value);
+        config.of_outputs = of_outputs;
+
+        Ok((config, problems))
+    }
+
+    /// The config in an evaluated file, see [`Config::parse`].
+    pub fn from_value(value: Value) -> (Self, Vec<Problem>) {
+        let mut problems = Vec::new();
+        let Value::Object(mut fields) = value else {
+            problems.push(Problem {
+                key: "config".into(),
+                message: "expected an attribute set".into(),
+            });
+            return (Config::default(), problems);
+        };
+
+        let keys = fields.keys().cloned().collect::<Vec<_>>();
+        for key in keys {
+            let Err(e) = Self::part(&key, fields[&key].clone()) else {
+                continue;
+            };
+
+            match fields.get_mut(&key) {
+                // Entries are checked one by one, so that one output's typo
+                // doesn't take the others along.
+                Some(Value::Object(entries)) if ENTRIES.contains(&key.as_str()) => {
+                    entries.retain(|name, value| {
+                        let entry = Value::Object(Map::from_iter([(name.clone(), value.clone())]));
+                        Self::part(&key, entry)
+                            .map_err(|e| problems.push(e.into()))
+                            .is_ok()
+                    });
                 }
-                Err(e) => {
-                    log::error!("Failed to determine config directory: {e}");
-                    return Config::default();
+                _ => {
+                    problems.push(e.into());
+                    fields.remove(&key);
+                    continue;
                 }
             }
-        };
 
-        match from_str(&nix_code) {
-            Ok(config) => config,
-            Err(e) => {
-                log::error!("{e}");
-                Config::default()
+            if let Err(e) = Self::part(&key, fields[&key].clone()) {
+                problems.push(e.into());
+                fields.remove(&key);
             }
         }
+
+        let config = serde_path_to_error::deserialize(Value::Object(fields)).unwrap_or_else(|e| {
+            problems.push(e.into());
+            Config::default()
+        });
+
+        (config, problems)
+    }
+
+    /// A config with only the setting `key`, failing with the path to what
+    /// doesn't fit.
+    fn part(
+        key: &str,
+        value: Value,
+    ) -> Result<Self, serde_path_to_error::Error<serde_json::Error>> {
+        serde_path_to_error::deserialize(Value::Object(Map::from_iter([(key.to_owned(), value)])))
+    }
+
+    /// Files the config is looked for in when no path is given, in order.
+    pub fn candidates() -> anyhow::Result<[PathBuf; 4]> {
+        let base = Self::xdg_config_dir()?;
+
+        Ok([
+            base.join("mox/moxpaper/default.nix"),
+            base.join("mox/moxpaper.nix"),
+            base.join("mox/moxpaper.toml"),
+            base.join("mox/moxpaper.json"),
+        ])
     }
 
     pub fn xdg_config_dir() -> anyhow::Result<PathBuf> {
@@ -168,3 +640,47 @@ impl Config {
             .map_err(Into::into)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn keys(value: Value) -> (Config, Vec<String>) {
+        let (config, problems) = Config::from_value(value);
+        let keys = problems.into_iter().map(|problem| problem.key).collect();
+        (config, keys)
+    }
+
+    #[test]
+    fn problems_name_the_full_path() {
+        let (config, keys) = keys(json!({
+            "wallpaper": {
+                "DP-1": { "path": "a.png", "transition": { "bezier": "sideways" } },
+                "HDMI-A-1": { "path": "b.png" },
+            },
+        }));
+
+        assert_eq!(keys, ["wallpaper.DP-1.transition.bezier"]);
+        assert!(config.wallpaper.contains_key("HDMI-A-1"));
+        assert!(!config.wallpaper.contains_key("DP-1"));
+    }
+
+    #[test]
+    fn problems_of_settings() {
+        let (config, keys) = keys(json!({
+            "default_transition_duration": "long",
+            "default_fps": 30,
+        }));
+
+        assert_eq!(keys, ["default_transition_duration"]);
+        assert_eq!(config.default_transition_duration, 3000);
+        assert_eq!(config.default_fps, Some(30));
+    }
+
+    #[test]
+    fn problems_of_the_whole_config() {
+        let (_, keys) = keys(json!(["wallpaper"]));
+        assert_eq!(keys, ["config"]);
+    }
+}
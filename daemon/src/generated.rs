@@ -0,0 +1,433 @@
+use crate::seeded::random;
+use image::{Rgba, RgbaImage};
+use libmoxpaper::{ColorStop, Generated, image_data::ImageData};
+use std::f32::consts::PI;
+
+/// Renders `generated` at exactly `width` by `height` pixels.
+pub fn render(generated: &Generated, width: u32, height: u32) -> ImageData {
+    let (w, h) = (width as f32, height as f32);
+    let (cx, cy) = (w / 2., h / 2.);
+
+    let image = match generated {
+        Generated::LinearGradient { angle, stops } => {
+            let (sin, cos) = angle.to_radians().sin_cos();
+            // Long enough for the corners to reach the first and last stop.
+            let length = (w * sin).abs() + (h * cos).abs();
+            let gradient = Gradient::new(stops);
+            pixels(width, height, |x, y| {
+                gradient.at(((x - cx) * sin - (y - cy) * cos) / length + 0.5)
+            })
+        }
+        Generated::RadialGradient { stops } => {
+            let radius = cx.hypot(cy);
+            let gradient = Gradient::new(stops);
+            pixels(width, height, |x, y| {
+                gradient.at((x - cx).hypot(y - cy) / radius)
+            })
+        }
+        Generated::ConicGradient { angle, stops } => {
+            let from = angle.to_radians();
+            let gradient = Gradient::new(stops);
+            pixels(width, height, |x, y| {
+                let turn = ((x - cx).atan2(cy - y) - from).rem_euclid(2. * PI);
+                gradient.at(turn / (2. * PI))
+            })
+        }
+        Generated::Noise {
+            seed,
+            scale,
+            palette,
+        } => {
+            let perlin = Perlin::new(*seed);
+            let frequency = scale.max(f32::EPSILON) / w;
+            let gradient = Gradient::palette(palette);
+            pixels(width, height, |x, y| {
+                let (mut value, mut amplitude, mut octave) = (0., 0.5, frequency);
+                for _ in 0..5 {
+                    value += perlin.at(x * octave, y * octave) * amplitude;
+                    amplitude *= 0.5;
+                    octave *= 2.;
+                }
+                gradient.at(value + 0.5)
+            })
+        }
+        Generated::Plasma {
+            seed,
+            scale,
+            palette,
+        } => {
+            let frequency = scale * 2. * PI / w;
+            let phase = |salt| random(*seed, salt, 0) * 2. * PI;
+            let (px, py) = (random(*seed, 4, 0) * w, random(*seed, 5, 0) * h);
+            let gradient = Gradient::palette(palette);
+            pixels(width, height, |x, y| {
+                let value = (x * frequency + phase(0)).sin()
+                    + (y * frequency + phase(1)).sin()
+                    + ((x + y) * frequency * 0.7 + phase(2)).sin()
+                    + ((x - px).hypot(y - py) * frequency + phase(3)).sin();
+                gradient.at(value / 8. + 0.5)
+            })
+        }
+        Generated::Voronoi {
+            seed,
+            cells,
+            palette,
+        } => {
+            // One point jittered inside every cell of a grid, so that only
+            // the neighbouring cells have to be searched.
+            // Cells narrower than a pixel wouldn't show.
+            let columns = (*cells).clamp(1, width.max(1));
+            let size = w / columns as f32;
+            let rows = (h / size).ceil().max(1.) as u32;
+            let index = |column: u32, row: u32| row as u64 * columns as u64 + column as u64;
+            let point = |column: u32, row: u32| {
+                let i = index(column, row);
+                (
+                    (column as f32 + random(*seed, i, 1)) * size,
+                    (row as f32 + random(*seed, i, 2)) * size,
+                )
+            };
+            let gradient = Gradient::palette(palette);
+
+            pixels(width, height, |x, y| {
+                let (column, row) = ((x / size) as i64, (y / size) as i64);
+                let nearest = (-1..=1)
+                    .flat_map(|dy| (-1..=1).map(move |dx| (column + dx, row + dy)))
+                    .filter(|&(c, r)| c >= 0 && r >= 0 && c < columns as i64 && r < rows as i64)
+                    .map(|(c, r)| (c as u32, r as u32))
+                    .min_by(|a, b| {
+                        let distance = |(c, r)| {
+                            let (px, py) = point(c, r);
+                            (x - px).hypot(y - py)
+                        };
+                        distance(*a).total_cmp(&distance(*b))
+                    });
+
+                match nearest {
+                    Some((c, r)) => gradient.at(random(*seed, index(c, r), 3)),
+                    None => gradient.at(0.),
+                }
+            })
+        }
+    };
+
+    ImageData::from(image)
+}
+
+/// Fills an image from a color per pixel center, dithered so that smooth
+/// gradients don't band.
+fn pixels<F>(width: u32, height: u32, color: F) -> RgbaImage
+where
+    F: Fn(f32, f32) -> [f32; 3],
+{
+    RgbaImage::from_fn(width, height, |x, y| {
+        let [r, g, b] = color(x as f32 + 0.5, y as f32 + 0.5);
+        let dither = random(0, y as u64 * width as u64 + x as u64, 4) - 0.5;
+        let channel = |value: f32| (value + dither).round().clamp(0., 255.) as u8;
+        Rgba([channel(r), channel(g), channel(b), 255])
+    })
+}
+
+/// Color stops with their positions resolved.
+struct Gradient {
+    stops: Vec<(f32, [f32; 3])>,
+}
+
+impl Gradient {
+    fn new(stops: &[ColorStop]) -> Self {
+        let last = stops.len().saturating_sub(1);
+        let mut positions = stops
+            .iter()
+            .enumerate()
+            .map(|(i, stop)| match (stop.position, i) {
+                (Some(position), _) => Some(position),
+                (None, 0) => Some(0.),
+                (None, i) if i == last => Some(1.),
+                (None, _) => None,
+            })
+            .collect::<Vec<_>>();
+
+        // Like CSS, stops are never before the previous one and the ones
+        // without a position are spread between their neighbours.
+        let mut previous = f32::MIN;
+        let mut start = 0;
+        for i in 0..positions.len() {
+            let Some(position) = positions[i] else {
+                continue;
+            };
+            let position = position.max(previous);
+            positions[i] = Some(position);
+
+            let gap = i - start;
+            (start + 1..i).for_each(|j| {
+                let t = (j - start) as f32 / gap as f32;
+                positions[j] = Some(previous + (position - previous) * t);
+            });
+            previous = position;
+            start = i;
+        }
+
+        let stops = stops
+            .iter()
+            .zip(positions)
+            .map(|(stop, position)| (position.unwrap_or(1.), stop.color.map(f32::from)))
+            .collect();
+
+        Self { stops }
+    }
+
+    /// Evenly spaced colors, black to white without any.
+    fn palette(colors: &[[u8; 3]]) -> Self {
+        let stops = match colors {
+            [] => vec![[0, 0, 0], [255, 255, 255]],
+            colors => colors.to_vec(),
+        };
+
+        Self::new(
+            &stops
+                .into_iter()
+                .map(|color| ColorStop {
+                    color,
+                    position: None,
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn at(&self, t: f32) -> [f32; 3] {
+        let Some(&(first_position, first)) = self.stops.first() else {
+            return [0.; 3];
+        };
+        if t <= first_position {
+            return first;
+        }
+
+        for pair in self.stops.windows(2) {
+            let ((a_position, a), (b_position, b)) = (pair[0], pair[1]);
+            if t <= b_position {
+                let span = b_position - a_position;
+                let amount = if span > 0. {
+                    (t - a_position) / span
+                } else {
+                    1.
+                };
+                return [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * amount);
+            }
+        }
+
+        self.stops.last().map(|&(_, color)| color).unwrap_or(first)
+    }
+}
+
+/// Improved Perlin noise with a permutation shuffled by the seed.
+struct Perlin {
+    permutation: [u8; 512],
+}
+
+impl Perlin {
+    fn new(seed: u32) -> Self {
+        let mut table: [u8; 256] = std::array::from_fn(|i| i as u8);
+        for i in (1..256).rev() {
+            let j = (random(seed, i as u64, 5) * (i + 1) as f32) as usize;
+            table.swap(i, j.min(i));
+        }
+
+        Self {
+            permutation: std::array::from_fn(|i| table[i % 256]),
+        }
+    }
+
+    /// Noise at a point, roughly between -1 and 1.
+    fn at(&self, x: f32, y: f32) -> f32 {
+        let (xi, yi) = (
+            x.floor() as i64 as usize & 255,
+            y.floor() as i64 as usize & 255,
+        );
+        let (xf, yf) = (x - x.floor(), y - y.floor());
+        let fade = |t: f32| t * t * t * (t * (t * 6. - 15.) + 10.);
+        let (u, v) = (fade(xf), fade(yf));
+
+        let p = &self.permutation;
+        let hash = |x: usize, y: usize| p[p[x] as usize + y];
+        let grad = |hash: u8, x: f32, y: f32| match hash & 7 {
+            0 => x + y,
+            1 => -x + y,
+            2 => x - y,
+            3 => -x - y,
+            4 => x,
+            5 => -x,
+            6 => y,
+            _ => -y,
+        };
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+        let bottom = lerp(
+            grad(hash(xi, yi), xf, yf),
+            grad(hash(xi + 1, yi), xf - 1., yf),
+            u,
+        );
+        let top = lerp(
+            grad(hash(xi, yi + 1), xf, yf - 1.),
+            grad(hash(xi + 1, yi + 1), xf - 1., yf - 1.),
+            u,
+        );
+
+        lerp(bottom, top, v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stop(position: Option<f32>) -> ColorStop {
+        ColorStop {
+            color: [0; 3],
+            position,
+        }
+    }
+
+    fn positions(stops: &[Option<f32>]) -> Vec<f32> {
+        let stops = stops.iter().copied().map(stop).collect::<Vec<_>>();
+        Gradient::new(&stops)
+            .stops
+            .into_iter()
+            .map(|(position, _)| position)
+            .collect()
+    }
+
+    #[test]
+    fn gradient_spreads_stops() {
+        assert_eq!(positions(&[None, None, None]), [0., 0.5, 1.]);
+        assert_eq!(
+            positions(&[None, Some(0.5), None, None]),
+            [0., 0.5, 0.75, 1.]
+        );
+        assert_eq!(
+            positions(&[Some(0.25), None, Some(0.75)]),
+            [0.25, 0.5, 0.75]
+        );
+    }
+
+    #[test]
+    fn gradient_clamps_to_the_previous_stop() {
+        assert_eq!(
+            positions(&[None, Some(0.5), Some(0.25), None, None]),
+            [0., 0.5, 0.5, 0.75, 1.]
+        );
+        // Spread from the clamped position, not the written one.
+        assert_eq!(positions(&[Some(0.5), None, Some(0.1)]), [0.5, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn gradient_colors() {
+        let gradient = Gradient::new(&[
+            ColorStop {
+                color: [0, 0, 0],
+                position: Some(0.25),
+            },
+            ColorStop {
+                color: [200, 100, 0],
+                position: Some(0.75),
+            },
+        ]);
+
+        assert_eq!(gradient.at(0.), [0.; 3]);
+        assert_eq!(gradient.at(0.5), [100., 50., 0.]);
+        assert_eq!(gradient.at(1.), [200., 100., 0.]);
+    }
+
+    #[test]
+    fn gradient_hard_stop() {
+        let gradient = Gradient::new(&[
+            ColorStop {
+                color: [255, 0, 0],
+                position: Some(0.5),
+            },
+            ColorStop {
+                color: [0, 0, 255],
+                position: Some(0.5),
+            },
+        ]);
+
+        assert_eq!(gradient.at(0.4), [255., 0., 0.]);
+        assert_eq!(gradient.at(0.6), [0., 0., 255.]);
+    }
+
+    #[test]
+    fn empty_palette() {
+        let gradient = Gradient::palette(&[]);
+        assert_eq!(gradient.at(0.), [0.; 3]);
+        assert_eq!(gradient.at(1.), [255.; 3]);
+        assert_eq!(Gradient::new(&[]).at(0.5), [0.; 3]);
+    }
+
+    fn all(seed: u32) -> [Generated; 6] {
+        let palette = vec![[10, 20, 30], [200, 150, 100], [0, 255, 0]];
+        let stops = palette
+            .iter()
+            .map(|&color| ColorStop {
+                color,
+                position: None,
+            })
+            .collect::<Vec<_>>();
+
+        [
+            Generated::LinearGradient {
+                angle: 30.,
+                stops: stops.clone(),
+            },
+            Generated::RadialGradient {
+                stops: stops.clone(),
+            },
+            Generated::ConicGradient { angle: 90., stops },
+            Generated::Noise {
+                seed,
+                scale: 3.,
+                palette: palette.clone(),
+            },
+            Generated::Plasma {
+                seed,
+                scale: 2.,
+                palette: palette.clone(),
+            },
+            Generated::Voronoi {
+                seed,
+                cells: 4,
+                palette,
+            },
+        ]
+    }
+
+    #[test]
+    fn render_size() {
+        for generated in all(1) {
+            let image = render(&generated, 37, 23);
+            assert_eq!(image.size(), (37, 23), "{generated:?}");
+        }
+    }
+
+    #[test]
+    fn render_is_deterministic() {
+        for (a, b) in all(7).iter().zip(all(7)) {
+            assert_eq!(render(a, 40, 30).data(), render(&b, 40, 30).data(), "{a:?}");
+        }
+
+        // The seed changes the seeded ones.
+        for (a, b) in all(7).iter().zip(all(8)).skip(3) {
+            assert_ne!(render(a, 40, 30).data(), render(&b, 40, 30).data(), "{a:?}");
+        }
+    }
+
+    #[test]
+    fn voronoi_cells_are_clamped() {
+        for cells in [0, 1, 8, u32::MAX] {
+            let generated = Generated::Voronoi {
+                seed: 3,
+                cells,
+                palette: Vec::new(),
+            };
+            assert_eq!(render(&generated, 8, 5).size(), (8, 5), "{cells}");
+        }
+    }
+}
@@ -0,0 +1,498 @@
+use crate::{Moxpaper, config};
+use libmoxpaper::{
+    Data, HistoryEntry, Sequence, Shader, Transition, TransitionStage, TransitionType,
+    WallpaperData,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Wallpapers kept for each output, older ones are forgotten.
+const LENGTH: usize = 32;
+
+/// The wallpapers each output showed, to go back and forth between.
+#[derive(Default)]
+pub struct History {
+    outputs: HashMap<Arc<str>, Entries>,
+}
+
+struct Entries {
+    entries: VecDeque<Entry>,
+    /// Index of the entry shown now
+    position: usize,
+}
+
+struct Entry {
+    wallpaper: WallpaperData,
+    applied: SystemTime,
+}
+
+impl History {
+    /// Adds a wallpaper an output shows now, dropping the entries that could
+    /// be redone. `previous` is what it showed before, if there's no entry
+    /// for that yet.
+    fn record(
+        &mut self,
+        output: &Arc<str>,
+        wallpaper: WallpaperData,
+        previous: impl FnOnce() -> Option<WallpaperData>,
+    ) {
+        let entries = self
+            .outputs
+            .entry(Arc::clone(output))
+            .or_insert_with(|| Entries {
+                entries: previous()
+                    .map(|wallpaper| Entry {
+                        wallpaper,
+                        applied: SystemTime::now(),
+                    })
+                    .into_iter()
+                    .collect(),
+                position: 0,
+            });
+
+        entries.entries.truncate(entries.position + 1);
+        entries.entries.push_back(Entry {
+            wallpaper,
+            applied: SystemTime::now(),
+        });
+        if entries.entries.len() > LENGTH {
+            entries.entries.pop_front();
+        }
+        entries.position = entries.entries.len() - 1;
+    }
+
+    /// Forgets the entries of an output, it shows something that can't be
+    /// shown again so undoing stops there.
+    fn clear(&mut self, output: &Arc<str>) {
+        self.outputs.insert(
+            Arc::clone(output),
+            Entries {
+                entries: VecDeque::new(),
+                position: 0,
+            },
+        );
+    }
+
+    /// Moves an output to the previous or next entry and returns the
+    /// wallpaper to show, with the transition to get there.
+    fn step(&mut self, output: &str, forward: bool) -> Option<WallpaperData> {
+        let entries = self.outputs.get_mut(output)?;
+        let current = entries.position;
+        let next = match forward {
+            true => current + 1,
+            false => current.checked_sub(1)?,
+        };
+        if next >= entries.entries.len() {
+            return None;
+        }
+        entries.position = next;
+
+        // Undoing plays the transition that got to the current entry back.
+        let transition = match forward {
+            true => entries.entries[next].wallpaper.transition.clone(),
+            false => reversed(&entries.entries[current].wallpaper.transition),
+        };
+
+        Some(WallpaperData {
+            outputs: vec![output.into()],
+            transition,
+            ..entries.entries[next].wallpaper.clone()
+        })
+    }
+
+    /// The entries of `outputs`, or of all outputs if empty.
+    pub fn entries(&self, outputs: &[Arc<str>]) -> Vec<HistoryEntry> {
+        let mut names = self
+            .outputs
+            .keys()
+            .filter(|name| outputs.is_empty() || outputs.contains(name))
+            .collect::<Vec<_>>();
+        names.sort();
+
+        names
+            .into_iter()
+            .flat_map(|name| {
+                let entries = &self.outputs[name];
+                entries
+                    .entries
+                    .iter()
+                    .enumerate()
+                    .map(move |(index, entry)| HistoryEntry {
+                        output: Arc::clone(name),
+                        wallpaper: entry.wallpaper.clone(),
+                        applied: entry
+                            .applied
+                            .duration_since(UNIX_EPOCH)
+                            .unwrap_or_default()
+                            .as_secs(),
+                        current: index == entries.position,
+                    })
+            })
+            .collect()
+    }
+}
+
+impl Moxpaper {
+    /// Adds a wallpaper just shown to the history of the outputs it's shown
+    /// on.
+    pub fn record_history(&mut self, wallpaper: &WallpaperData) {
+        // Images sent along with the request are too large to keep around.
+        if let Data::Image(_) = wallpaper.data {
+            self.clear_history(&wallpaper.outputs);
+            return;
+        }
+
+        let entries: Vec<WallpaperData> = match wallpaper.outputs.is_empty() {
+            true => self
+                .history_outputs(&wallpaper.outputs)
+                .into_iter()
+                .map(|name| WallpaperData {
+                    outputs: vec![name],
+                    ..wallpaper.clone()
+                })
+                .collect(),
+            false => wallpaper.per_output().collect(),
+        };
+
+        entries.into_iter().for_each(|entry| {
+            let output = Arc::clone(&entry.outputs[0]);
+            self.history.record(&output, entry, || {
+                let configured = self
+                    .config
+                    .wallpaper
+                    .get(&output)
+                    .or_else(|| self.config.wallpaper.get("any"))?;
+                Some(configured_wallpaper(&output, configured))
+            });
+        });
+    }
+
+    /// Forgets the history of the outputs an image sent along with a request
+    /// was just shown on, undoing can't go back past it.
+    pub fn clear_history(&mut self, outputs: &[Arc<str>]) {
+        self.history_outputs(outputs)
+            .iter()
+            .for_each(|output| self.history.clear(output));
+    }
+
+    /// The outputs a wallpaper for `outputs` is shown on. Without outputs,
+    /// those are the ones without a wallpaper of their own.
+    fn history_outputs(&self, outputs: &[Arc<str>]) -> Vec<Arc<str>> {
+        match outputs {
+            [] => self
+                .outputs
+                .iter()
+                .map(|output| &output.info.name)
+                .filter(|name| !self.assets.has_asset(name))
+                .cloned()
+                .collect(),
+            outputs => outputs.to_vec(),
+        }
+    }
+
+    /// Shows the previous or next wallpaper of `outputs`, all of them if
+    /// empty.
+    pub fn step_history(&mut self, outputs: &[Arc<str>], forward: bool) {
+        let outputs = match outputs {
+            [] => self
+                .outputs
+                .iter()
+                .map(|output| Arc::clone(&output.info.name))
+                .collect(),
+            outputs => outputs.to_vec(),
+        };
+
+        for output in outputs {
+            let Some(wallpaper) = self.history.step(&output, forward) else {
+                log::info!(
+                    "Nothing to {} on {output}",
+                    if forward { "redo" } else { "undo" }
+                );
+                continue;
+            };
+
+            if let Err(e) = self.load_wallpaper(wallpaper.clone()) {
+                log::error!("Failed to load wallpaper of {output}: {e}");
+                continue;
+            }
+            self.saved.record(&wallpaper);
+            self.render_outputs(|name| *name == output);
+        }
+    }
+}
+
+/// A wallpaper of the config, as a client would set it.
+fn configured_wallpaper(output: &Arc<str>, wallpaper: &config::Wallpaper) -> WallpaperData {
+    let path = wallpaper.path.to_path_buf();
+    let data = if path.extension().is_some_and(|e| e == "wgsl") {
+        Data::Shader(Shader {
+            path,
+            fps: wallpaper.fps,
+        })
+    } else if Sequence::is_source(&path) {
+        Data::Sequence(Sequence {
+            source: path,
+            fps: wallpaper.fps.unwrap_or(Sequence::DEFAULT_FPS),
+            playback: wallpaper.playback,
+            offset: wallpaper.frame_offset,
+        })
+    } else {
+        Data::Path(path)
+    };
+
+    WallpaperData {
+        outputs: vec![Arc::clone(output)],
+        data,
+        resize: wallpaper.resize,
+        transition: wallpaper.transition.clone(),
+        repeat: wallpaper.repeat,
+    }
+}
+
+/// The transition that looks like `transition` played backwards, its
+/// stages last to first.
+fn reversed(transition: &Transition) -> Transition {
+    let reverse = |transition_type: &TransitionType| match transition_type {
+        TransitionType::Left => TransitionType::Right,
+        TransitionType::Right => TransitionType::Left,
+        TransitionType::Top => TransitionType::Bottom,
+        TransitionType::Bottom => TransitionType::Top,
+        TransitionType::Center => TransitionType::Outer,
+        TransitionType::Outer => TransitionType::Center,
+        TransitionType::PushLeft => TransitionType::PushRight,
+        TransitionType::PushRight => TransitionType::PushLeft,
+        TransitionType::PushTop => TransitionType::PushBottom,
+        TransitionType::PushBottom => TransitionType::PushTop,
+        // The new wallpaper sliding in from a side turns into the old one
+        // sliding out to it.
+        TransitionType::CoverLeft => TransitionType::RevealRight,
+        TransitionType::CoverRight => TransitionType::RevealLeft,
+        TransitionType::CoverTop => TransitionType::RevealBottom,
+        TransitionType::CoverBottom => TransitionType::RevealTop,
+        TransitionType::RevealLeft => TransitionType::CoverRight,
+        TransitionType::RevealRight => TransitionType::CoverLeft,
+        TransitionType::RevealTop => TransitionType::CoverBottom,
+        TransitionType::RevealBottom => TransitionType::CoverTop,
+        TransitionType::ZoomIn => TransitionType::ZoomOut,
+        TransitionType::ZoomOut => TransitionType::ZoomIn,
+        TransitionType::CubeLeft => TransitionType::CubeRight,
+        TransitionType::CubeRight => TransitionType::CubeLeft,
+        TransitionType::CubeTop => TransitionType::CubeBottom,
+        TransitionType::CubeBottom => TransitionType::CubeTop,
+        other => other.clone(),
+    };
+
+    let stages = &transition.stages;
+    Transition {
+        transition_type: transition.transition_type.as_ref().map(reverse),
+        stages: stages
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(index, stage)| TransitionStage {
+                transition_type: stage.transition_type.as_ref().map(reverse),
+                // Each stage ends on what the one before it started from.
+                intermediate: stages
+                    .len()
+                    .checked_sub(index + 2)
+                    .and_then(|before| stages[before].intermediate.clone()),
+                ..stage.clone()
+            })
+            .collect(),
+        ..transition.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libmoxpaper::Intermediate;
+
+    fn wallpaper(color: u8, transition_type: TransitionType) -> WallpaperData {
+        WallpaperData {
+            outputs: vec!["DP-1".into()],
+            data: Data::Color([color; 3]),
+            resize: Default::default(),
+            transition: Transition {
+                transition_type: Some(transition_type),
+                ..Default::default()
+            },
+            repeat: Default::default(),
+        }
+    }
+
+    fn color(wallpaper: Option<WallpaperData>) -> Option<u8> {
+        match wallpaper?.data {
+            Data::Color([color, ..]) => Some(color),
+            _ => None,
+        }
+    }
+
+    fn transition_type(wallpaper: Option<WallpaperData>) -> Option<TransitionType> {
+        wallpaper?.transition.transition_type
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let output: Arc<str> = "DP-1".into();
+        let mut history = History::default();
+        history.record(&output, wallpaper(1, TransitionType::Left), || {
+            Some(wallpaper(0, TransitionType::None))
+        });
+        history.record(&output, wallpaper(2, TransitionType::Top), || None);
+
+        assert_eq!(color(history.step(&output, true)), None);
+        assert_eq!(color(history.step(&output, false)), Some(1));
+        assert_eq!(color(history.step(&output, false)), Some(0));
+        assert_eq!(color(history.step(&output, false)), None);
+        assert_eq!(color(history.step(&output, true)), Some(1));
+        assert_eq!(color(history.step("HDMI-A-1", false)), None);
+    }
+
+    #[test]
+    fn steps_play_the_transitions() {
+        let output: Arc<str> = "DP-1".into();
+        let mut history = History::default();
+        history.record(&output, wallpaper(1, TransitionType::Left), || {
+            Some(wallpaper(0, TransitionType::None))
+        });
+
+        // Undoing plays the transition to the entry it leaves backwards.
+        let undo = history.step(&output, false);
+        assert_eq!(transition_type(undo), Some(TransitionType::Right));
+        let redo = history.step(&output, true);
+        assert_eq!(transition_type(redo), Some(TransitionType::Left));
+    }
+
+    #[test]
+    fn record_drops_redo() {
+        let output: Arc<str> = "DP-1".into();
+        let mut history = History::default();
+        history.record(&output, wallpaper(1, TransitionType::Left), || {
+            Some(wallpaper(0, TransitionType::None))
+        });
+        history.step(&output, false);
+        history.record(&output, wallpaper(2, TransitionType::Left), || None);
+
+        assert_eq!(color(history.step(&output, true)), None);
+        assert_eq!(color(history.step(&output, false)), Some(0));
+        assert_eq!(history.entries(&[]).len(), 2);
+    }
+
+    #[test]
+    fn length_is_capped() {
+        let output: Arc<str> = "DP-1".into();
+        let mut history = History::default();
+        for color in 0..LENGTH as u8 + 8 {
+            history.record(&output, wallpaper(color, TransitionType::Left), || None);
+        }
+
+        let entries = history.entries(&[]);
+        assert_eq!(entries.len(), LENGTH);
+        assert!(entries.last().unwrap().current);
+        for color in (8..LENGTH as u8 + 7).rev() {
+            assert_eq!(self::color(history.step(&output, false)), Some(color));
+        }
+        assert_eq!(self::color(history.step(&output, false)), None);
+    }
+
+    #[test]
+    fn clear_stops_undo() {
+        let output: Arc<str> = "DP-1".into();
+        let mut history = History::default();
+        history.record(&output, wallpaper(1, TransitionType::Left), || {
+            Some(wallpaper(0, TransitionType::None))
+        });
+        history.clear(&output);
+        assert_eq!(color(history.step(&output, false)), None);
+
+        history.record(&output, wallpaper(2, TransitionType::Left), || {
+            Some(wallpaper(0, TransitionType::None))
+        });
+        assert_eq!(color(history.step(&output, false)), None);
+        assert_eq!(history.entries(&[]).len(), 1);
+    }
+
+    #[test]
+    fn reversed_transitions() {
+        let reverse = |transition_type| {
+            reversed(&Transition {
+                transition_type: Some(transition_type),
+                ..Default::default()
+            })
+            .transition_type
+            .unwrap()
+        };
+
+        for (transition_type, expected) in [
+            (TransitionType::Left, TransitionType::Right),
+            (TransitionType::Center, TransitionType::Outer),
+            (TransitionType::PushTop, TransitionType::PushBottom),
+            (TransitionType::CoverLeft, TransitionType::RevealRight),
+            (TransitionType::ZoomIn, TransitionType::ZoomOut),
+            (TransitionType::CubeBottom, TransitionType::CubeTop),
+            (TransitionType::Fade, TransitionType::Fade),
+        ] {
+            assert_eq!(reverse(transition_type.clone()), expected);
+            if transition_type != TransitionType::Fade {
+                assert_eq!(reverse(expected), transition_type);
+            }
+        }
+    }
+
+    #[test]
+    fn reversed_stages() {
+        let red = Intermediate::Color([255, 0, 0]);
+        let blue = Intermediate::Image("blue.png".into());
+        // Left through red, top through blue, then a fade to the wallpaper.
+        let transition = Transition {
+            transition_type: Some(TransitionType::Left),
+            duration: Some(500),
+            stages: vec![
+                TransitionStage {
+                    transition_type: Some(TransitionType::Left),
+                    intermediate: Some(red.clone()),
+                    ..Default::default()
+                },
+                TransitionStage {
+                    transition_type: Some(TransitionType::Top),
+                    duration: Some(200),
+                    intermediate: Some(blue.clone()),
+                    ..Default::default()
+                },
+                TransitionStage {
+                    transition_type: Some(TransitionType::Fade),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let reversed = reversed(&transition);
+        assert_eq!(reversed.transition_type, Some(TransitionType::Right));
+        assert_eq!(reversed.duration, Some(500));
+
+        let stages = reversed
+            .stages
+            .iter()
+            .map(|stage| {
+                (
+                    stage.transition_type.clone().unwrap(),
+                    stage.intermediate.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            stages,
+            [
+                (TransitionType::Fade, Some(blue)),
+                (TransitionType::Bottom, Some(red)),
+                (TransitionType::Right, None),
+            ]
+        );
+        assert_eq!(reversed.stages[1].duration, Some(200));
+    }
+}
@@ -1,11 +1,23 @@
+mod animated;
 mod animation;
 mod assets;
 pub mod buffers;
+mod check;
+mod clock;
 mod config;
+mod generated;
+mod history;
 mod output;
+mod playlist;
+mod preview;
+mod reload;
+mod saved;
+mod schedule;
+mod seeded;
+mod solar;
+mod timed;
 mod wgpu_state;
 
-use animation::bezier::BezierBuilder;
 use anyhow::Context;
 use assets::{AssetsManager, FallbackImage};
 use calloop::{EventLoop, LoopHandle, generic::Generic};
@@ -15,11 +27,16 @@ use config::Config;
 use env_logger::Builder;
 use image::RgbaImage;
 use libmoxpaper::{
-    BezierChoice, Data, ResizeStrategy,
+    BezierChoice, Data, Request, ResizeStrategy, Transition, TransitionStage, TransitionType,
+    WallpaperData, WeightedTransition,
     image_data::ImageData,
     ipc::{Ipc, Server},
+    parse::{
+        parse_bezier, parse_stage, parse_transition_type, parse_weighted_transition, random_pool,
+    },
 };
 use log::LevelFilter;
+use output::{Stage, shader};
 use resvg::usvg;
 #[cfg(feature = "s3")]
 use s3::{Bucket, Region, creds::Credentials};
@@ -30,6 +47,7 @@ use std::{
     os::fd::AsRawFd,
     path::{Path, PathBuf},
     sync::Arc,
+    time::Instant,
 };
 use wayland_client::{
     Connection, Dispatch, QueueHandle, delegate_noop,
@@ -49,7 +67,16 @@ struct Moxpaper {
     ipc: Ipc<Server>,
     handle: LoopHandle<'static, Self>,
     assets: AssetsManager,
+    playlists: playlist::Playlists,
+    schedules: schedule::Schedules,
+    saved: saved::Saved,
+    history: history::History,
     config: Config,
+    reload: reload::Reload,
+    /// Outputs configured since the loop last went idle, shown together so
+    /// that their choreography spans all of them and a config written as a
+    /// function of the outputs is evaluated once for them
+    configured: Vec<Arc<str>>,
     #[cfg(feature = "http")]
     client: reqwest::blocking::Client,
     #[cfg(feature = "s3")]
@@ -63,67 +90,40 @@ impl Moxpaper {
         ipc: Ipc<Server>,
         handle: LoopHandle<'static, Self>,
         config: Config,
+        config_path: Option<Box<Path>>,
     ) -> anyhow::Result<Self> {
         let mut assets = AssetsManager::default();
         config.wallpaper.iter().for_each(|(k, v)| {
-            let image = image::open(&v.path);
+            let Some(asset) = assets::AssetData::load(v) else {
+                return;
+            };
 
             if &**k == "any" {
-                match image {
-                    Ok(img) => assets.set_fallback(FallbackImage::Image(assets::AssetData {
-                        image: ImageData::from(img),
-                        resize: v.resize,
-                        transition: v.transition.clone(),
-                    })),
-                    Err(e) => log::error!("{e}: {}", v.path.display()),
-                }
+                assets.set_fallback(FallbackImage::Image(asset));
             } else {
-                match image {
-                    Ok(img) => assets.insert_asset(
-                        Arc::clone(k),
-                        assets::AssetData {
-                            image: ImageData::from(img),
-                            resize: v.resize,
-                            transition: v.transition.clone(),
-                        },
-                    ),
-                    Err(e) => log::error!("{e}: {}", v.path.display()),
-                }
+                assets.insert_asset(Arc::clone(k), asset);
+            }
+        });
+        config.sun.iter().for_each(|(k, v)| {
+            let Some(asset) = assets::AssetData::sun(v, config.location) else {
+                return;
+            };
+
+            if &**k == "any" {
+                assets.set_fallback(FallbackImage::Image(asset));
+            } else {
+                assets.insert_asset(Arc::clone(k), asset);
             }
         });
 
+        let playlists = playlist::Playlists::new(&config, &mut assets, handle.clone());
+        let schedules = schedule::Schedules::new(&config, &mut assets, &handle);
+
         #[cfg(feature = "s3")]
         let buckets: HashMap<String, Box<Bucket>> = config
             .buckets
             .iter()
-            .filter_map(|(k, v)| {
-                let access_key = v.get_access_key().ok()?;
-                let secret_key = v.get_secret_key().ok()?;
-
-                let credentials = Credentials {
-                    access_key: Some(access_key),
-                    secret_key: Some(secret_key),
-                    security_token: None,
-                    session_token: None,
-                    expiration: None,
-                };
-
-                let s3_region = match v.region.as_ref() {
-                    Some(region) => Region::Custom {
-                        region: region.clone(),
-                        endpoint: v.url.clone(),
-                    },
-                    None => Region::from_env("S3_REGION", Some("S3_ENDPOINT")).ok()?,
-                };
-
-                match Bucket::new(k, s3_region, credentials) {
-                    Ok(bucket) => Some((k.clone(), bucket)),
-                    Err(e) => {
-                        log::error!("Failed to create bucket '{k}': {e}");
-                        None
-                    }
-                }
-            })
+            .filter_map(|(k, v)| Some((k.clone(), create_bucket(k, v)?)))
             .collect();
 
         Ok(Self {
@@ -132,6 +132,8 @@ impl Moxpaper {
             #[cfg(feature = "http")]
             client: reqwest::blocking::Client::new(),
             config,
+            reload: reload::Reload::new(config_path),
+            configured: Vec::new(),
             qh,
             ipc,
             handle,
@@ -141,92 +143,570 @@ impl Moxpaper {
             outputs: Vec::new(),
             wgpu: WgpuState::new(conn)?,
             assets,
+            playlists,
+            schedules,
+            saved: saved::Saved::load(),
+            history: history::History::default(),
         })
     }
 
+    /// Creates the buckets that were added or changed in the config and
+    /// drops the removed ones.
+    #[cfg(feature = "s3")]
+    fn reload_buckets(&mut self, previous: &Config) {
+        self.buckets
+            .retain(|name, _| previous.buckets.get(name) == self.config.buckets.get(name));
+        self.config
+            .buckets
+            .iter()
+            .filter(|(name, config)| previous.buckets.get(*name) != Some(*config))
+            .for_each(|(name, config)| {
+                if let Some(bucket) = create_bucket(name, config) {
+                    self.buckets.insert(name.clone(), bucket);
+                }
+            });
+    }
+
     fn render(&mut self) {
-        self.outputs.iter_mut().for_each(|output| {
-            let wallpaper =
-                self.assets
-                    .get(&output.info.name, output.info.width, output.info.height);
-
-            if let Some(wallpaper) = wallpaper {
-                let resized = match wallpaper.resize {
-                    ResizeStrategy::No => {
-                        Ok(wallpaper
-                            .image
-                            .pad(output.info.width, output.info.height, &[0, 0, 0]))
+        self.render_outputs(|_| true);
+    }
+
+    /// Transitions the outputs showing the wallpaper set for `output` in the
+    /// config, where "any" is every output without one of its own.
+    fn render_configured(&mut self, output: &Arc<str>) {
+        if &**output != "any" {
+            self.render_outputs(|name| name == output);
+            return;
+        }
+
+        let own = self
+            .outputs
+            .iter()
+            .filter(|output| self.assets.has_asset(&output.info.name))
+            .map(|output| Arc::clone(&output.info.name))
+            .collect::<Vec<_>>();
+        self.render_outputs(|name| !own.contains(name));
+    }
+
+    /// Shows the wallpapers of an output once it is configured, along with
+    /// the other outputs configured in the same dispatch.
+    fn show_configured(&mut self, output: &Arc<str>) {
+        if self.configured.is_empty() {
+            self.handle.insert_idle(|state| {
+                let mut configured = std::mem::take(&mut state.configured);
+                configured.retain(|name| {
+                    state.outputs.iter().any(|output| output.info.name == *name)
+                });
+
+                state.outputs_changed(&configured);
+                configured.iter().for_each(|name| {
+                    state
+                        .playlists
+                        .add_output(name, &state.config, &mut state.assets);
+                    state.restore_output(name);
+                });
+                state.render_outputs(|name| configured.contains(name));
+            });
+        }
+        if !self.configured.contains(output) {
+            self.configured.push(Arc::clone(output));
+        }
+    }
+
+    /// Transitions the outputs `filter` selects to their wallpaper.
+    fn render_outputs<F>(&mut self, filter: F)
+    where
+        F: Fn(&Arc<str>) -> bool,
+    {
+        let now = Instant::now();
+        // A cascade runs over the outputs that transition, not all of them.
+        let positions = self
+            .outputs
+            .iter()
+            .filter(|output| filter(&output.info.name))
+            .map(|output| (output.info.x, output.info.y))
+            .collect::<Vec<_>>();
+
+        self.outputs
+            .iter_mut()
+            .filter(|output| filter(&output.info.name))
+            .for_each(|output| {
+                let wallpaper =
+                    self.assets
+                        .get(&output.info.name, output.info.width, output.info.height);
+
+                if let Some(wallpaper) = wallpaper {
+                    let stages = Stage::chain(
+                        wallpaper,
+                        output.info.width,
+                        output.info.height,
+                        &self.config,
+                    );
+
+                    match stages {
+                        Ok(mut stages) => {
+                            if let Some(first) = stages.first_mut() {
+                                first.config.start_at = animation::start_at(
+                                    first.config.choreography,
+                                    now,
+                                    (output.info.x, output.info.y),
+                                    &positions,
+                                );
+                            }
+                            output.transition_to(stages);
+                        }
+                        Err(e) => log::error!("{e}"),
                     }
-                    ResizeStrategy::Fit => wallpaper
-                        .image
-                        .resize_to_fit(output.info.width, output.info.height),
-                    ResizeStrategy::Crop => wallpaper
-                        .image
-                        .resize_crop(output.info.width, output.info.height),
-                    ResizeStrategy::Stretch => wallpaper
-                        .image
-                        .resize_stretch(output.info.width, output.info.height),
-                };
+                }
+            });
+    }
+    /// Loads a wallpaper sent by a client as the one of its outputs, or of
+    /// every output without one of its own if it names none.
+    fn load_wallpaper(&mut self, wallpaper: WallpaperData) -> anyhow::Result<()> {
+        if wallpaper.outputs.is_empty() {
+            let image = match wallpaper.data {
+                Data::Image(image) => FallbackImage::Image(assets::AssetData {
+                    image,
+                    resize: wallpaper.resize,
+                    transition: wallpaper.transition,
+                    motion: None,
+                    shader: None,
+                    blend: None,
+                }),
+                Data::Path(path) => {
+                    if timed::is_source(&path) {
+                        match assets::AssetData::timed(
+                            &path,
+                            wallpaper.resize,
+                            wallpaper.transition,
+                        ) {
+                            Some(asset) => FallbackImage::Image(asset),
+                            None => return Err(anyhow::anyhow!("No timed background to show")),
+                        }
+                    } else if path.extension().is_some_and(|e| e == "svg") {
+                        let svg_data = std::fs::read(path)?;
 
-                if let Ok(resized) = resized {
-                    let bezier = wallpaper
-                        .transition
-                        .bezier
-                        .as_ref()
-                        .unwrap_or(&self.config.default_bezier);
-                    let bezier = match bezier {
-                        BezierChoice::Linear => BezierBuilder::new().linear(),
-                        BezierChoice::Ease => BezierBuilder::new().ease(),
-                        BezierChoice::EaseIn => BezierBuilder::new().ease_in(),
-                        BezierChoice::EaseOut => BezierBuilder::new().ease_out(),
-                        BezierChoice::EaseInOut => BezierBuilder::new().ease_in_out(),
-                        BezierChoice::Custom(curve) => {
-                            BezierBuilder::new().custom(curve.0, curve.1, curve.2, curve.3)
+                        FallbackImage::Svg {
+                            data: svg_data.into(),
+                            transition: wallpaper.transition,
                         }
-                        BezierChoice::Named(bezier) => {
-                            if let Some(a) = self.config.bezier.get(bezier) {
-                                BezierBuilder::new().custom(a.0, a.1, a.2, a.3)
-                            } else {
-                                log::warn!("Bezier: {bezier} not found");
-                                BezierBuilder::new().linear()
-                            }
+                    } else {
+                        match image::open(&path).map(ImageData::from) {
+                            Ok(img) => FallbackImage::Image(assets::AssetData {
+                                image: img,
+                                resize: wallpaper.resize,
+                                transition: wallpaper.transition,
+                                motion: animated::probe(&path, wallpaper.repeat),
+                                shader: None,
+                                blend: None,
+                            }),
+                            Err(e) => return Err(anyhow::anyhow!("Image open error: {e}")),
                         }
+                    }
+                }
+                Data::Color(color) => FallbackImage::Color {
+                    color: image::Rgb(color),
+                    transition: wallpaper.transition,
+                },
+                Data::Generated(generated) => FallbackImage::Generated {
+                    generated,
+                    transition: wallpaper.transition,
+                },
+                Data::Sequence(sequence) => match animated::sequence(&sequence, wallpaper.repeat) {
+                    Some((image, motion)) => FallbackImage::Image(assets::AssetData {
+                        image,
+                        resize: wallpaper.resize,
+                        transition: wallpaper.transition,
+                        motion: Some(motion),
+                        shader: None,
+                        blend: None,
+                    }),
+                    None => return Err(anyhow::anyhow!("No frames to show")),
+                },
+                Data::Shader(shader) => match shader::load(&shader) {
+                    Some(source) => FallbackImage::Image(assets::AssetData {
+                        image: shader::placeholder(),
+                        resize: wallpaper.resize,
+                        transition: wallpaper.transition,
+                        motion: None,
+                        shader: Some(source),
+                        blend: None,
+                    }),
+                    None => return Err(anyhow::anyhow!("No shader to show")),
+                },
+                #[cfg(feature = "s3")]
+                Data::S3 { bucket, key } => {
+                    let bucket_obj = match self.buckets.get_mut(&bucket) {
+                        Some(bucket_obj) => bucket_obj,
+                        None => return Err(anyhow::anyhow!("bucket {bucket} not found")),
                     };
-                    let extents = animation::Extents {
-                        x: 0.,
-                        y: 0.,
-                        width: output.info.width as f32,
-                        height: output.info.height as f32,
+                    bucket_obj.set_path_style();
+
+                    let res = match bucket_obj.get_object(&key) {
+                        Ok(res) => res,
+                        Err(e) => {
+                            return Err(anyhow::anyhow!(
+                                "Failed to get S3 object '{}' from bucket '{}': {e}",
+                                key,
+                                bucket
+                            ));
+                        }
                     };
-                    if let Some(image) = output.target_image.take() {
-                        output.previous_image =
-                            Some((image, output.animation.frame_data().unwrap_or_default()));
+
+                    if res.status_code() != 200 {
+                        return Err(anyhow::anyhow!(
+                            "Non 200 status code response for S3 object '{}' in bucket '{}': status {}",
+                            key,
+                            bucket,
+                            res.status_code()
+                        ));
                     }
-                    output.target_image = Some(resized);
-                    output.animation.start(
-                        &output.info.name,
-                        animation::TransitionConfig {
-                            enabled_transition_types: self
-                                .config
-                                .enabled_transition_types
-                                .as_ref()
-                                .map(Arc::clone),
-                            transition_type: wallpaper
-                                .transition
-                                .transition_type
-                                .unwrap_or(self.config.default_transition_type.clone()),
-                            fps: wallpaper.transition.fps.or(self.config.default_fps),
-                            duration: wallpaper
-                                .transition
-                                .duration
-                                .unwrap_or(self.config.default_transition_duration),
-                            bezier,
-                        },
-                        extents,
-                    );
+
+                    let bytes = res.bytes();
+                    if bytes.len() < 1000 {
+                        let content_str = String::from_utf8_lossy(&bytes);
+                        if content_str.trim_start().starts_with("<?xml") {
+                            return Err(anyhow::anyhow!(
+                                "S3 error response for object '{}' in bucket '{}': {}",
+                                key,
+                                bucket,
+                                content_str
+                            ));
+                        }
+                    }
+
+                    let image_data = match image::load_from_memory(&bytes) {
+                        Ok(data) => data,
+                        Err(e) => {
+                            return Err(anyhow::anyhow!(
+                                "Failed to load image from S3 object '{}' in bucket '{}': {e}",
+                                key,
+                                bucket
+                            ));
+                        }
+                    };
+
+                    FallbackImage::Image(assets::AssetData {
+                        image: ImageData::from(image_data),
+                        resize: wallpaper.resize,
+                        transition: wallpaper.transition,
+                        motion: None,
+                        shader: None,
+                        blend: None,
+                    })
                 }
-            }
-        });
+                #[cfg(not(feature = "s3"))]
+                Data::S3 { .. } => {
+                    return Err(anyhow::anyhow!(
+                        "S3 feature is not enabled. Rebuild with --features s3 to enable S3 support."
+                    ));
+                }
+                #[cfg(feature = "http")]
+                Data::Http { url, .. } => {
+                    let url_clone = url.clone();
+                    let res = match self.client.get(&url_clone).send() {
+                        Ok(res) => res,
+                        Err(e) => {
+                            return Err(anyhow::anyhow!(
+                                "Failed to send HTTP request to '{}': {e}",
+                                url_clone
+                            ));
+                        }
+                    };
+
+                    let bytes = match res.bytes() {
+                        Ok(bytes) => bytes,
+                        Err(e) => {
+                            return Err(anyhow::anyhow!(
+                                "Failed to get response bytes from '{}': {e}",
+                                url_clone
+                            ));
+                        }
+                    };
+
+                    let image_data = match image::load_from_memory(&bytes) {
+                        Ok(data) => data,
+                        Err(e) => {
+                            return Err(anyhow::anyhow!(
+                                "Failed to load image from HTTP response '{}': {e}",
+                                url_clone
+                            ));
+                        }
+                    };
+
+                    FallbackImage::Image(assets::AssetData {
+                        image: ImageData::from(image_data),
+                        resize: wallpaper.resize,
+                        transition: wallpaper.transition,
+                        motion: None,
+                        shader: None,
+                        blend: None,
+                    })
+                }
+                #[cfg(not(feature = "http"))]
+                Data::Http { .. } => {
+                    return Err(anyhow::anyhow!(
+                        "HTTP feature is not enabled. Rebuild with --features http to enable HTTP support."
+                    ));
+                }
+            };
+
+            self.assets.set_fallback(image);
+        } else {
+            let motion = match &wallpaper.data {
+                Data::Path(path) => animated::probe(path, wallpaper.repeat),
+                _ => None,
+            };
+            let shader = match &wallpaper.data {
+                Data::Shader(shader) => shader::load(shader),
+                _ => None,
+            };
+            let background = match &wallpaper.data {
+                Data::Path(path) if timed::is_source(path) => {
+                    assets::AssetData::timed(path, wallpaper.resize, wallpaper.transition.clone())
+                }
+                _ => None,
+            };
+
+            // Nothing changes unless every output has its image.
+            let assets = wallpaper.outputs.iter().enumerate().map(|(index, output_name)| {
+                let sequence = match &wallpaper.data {
+                    Data::Sequence(sequence) => {
+                        animated::sequence(&sequence.staggered(index), wallpaper.repeat)
+                    }
+                    _ => None,
+                };
+                let (first_frame, motion) = match sequence {
+                    Some((image, motion)) => (Some(image), Some(motion)),
+                    None => (None, motion.clone()),
+                };
+
+                let image = match &wallpaper.data {
+                    Data::Image(image) => Some(image.clone()),
+                    Data::Sequence(_) => first_frame,
+                    Data::Shader(_) => shader.as_ref().map(|_| shader::placeholder()),
+                    Data::Path(path) => {
+                        if timed::is_source(path) {
+                            background.as_ref().map(|background| background.image.clone())
+                        } else if path.extension().is_some_and(|e| e == "svg") {
+                            self
+                                .outputs
+                                .iter()
+                                .find(|output| &output.info.name == output_name)
+                                .and_then(|output| {
+                                    render_svg(path, output.info.width, output.info.height)
+                                        .inspect_err(|e| log::error!("SVG render error: {e}"))
+                                        .ok()
+                                })
+                        } else {
+                            image::open(path)
+                                .map(ImageData::from)
+                                .inspect_err(|e| log::error!("Image open error: {e}"))
+                                .ok()
+                        }
+                    }
+                    Data::Color(color) => self
+                        .outputs
+                        .iter()
+                        .find(|output| &output.info.name == output_name)
+                        .map(|output| {
+                            let rgba_image = RgbaImage::from_pixel(
+                                output.info.width,
+                                output.info.height,
+                                image::Rgba([color[0], color[1], color[2], 255]),
+                            );
+
+                            ImageData::from(rgba_image)
+                        }),
+                    Data::Generated(generated) => self
+                        .outputs
+                        .iter()
+                        .find(|output| &output.info.name == output_name)
+                        .map(|output| {
+                            generated::render(
+                                generated,
+                                output.info.width,
+                                output.info.height,
+                            )
+                        }),
+                    #[cfg(feature = "s3")]
+                    Data::S3 { bucket, key } => {
+                        (|| -> Option<ImageData> {
+                            let alias_config = match self.config.buckets.get(bucket) {
+                                Some(config) => config,
+                                None => {
+                                    log::warn!("Alias {} not found", bucket);
+                                    return None;
+                                }
+                            };
+
+                            let access_key = alias_config.get_access_key().map_err(|e| {
+                                log::warn!("Failed to get access key for alias {}: {e}", bucket);
+                            }).ok()?;
+                            let secret_key = alias_config.get_secret_key().map_err(|e| {
+                                log::warn!("Failed to get secret key for alias {}: {e}", bucket);
+                            }).ok()?;
+
+                            let credentials = Credentials {
+                                access_key: Some(access_key),
+                                secret_key: Some(secret_key),
+                                security_token: None,
+                                session_token: None,
+                                expiration: None,
+                            };
+
+                            let s3_region = match alias_config.region.as_ref() {
+                                Some(region) => Region::Custom {
+                                    region: region.clone(),
+                                    endpoint: alias_config.url.clone(),
+                                },
+                                None => {
+                                    match Region::from_env("S3_REGION", Some("S3_ENDPOINT")) {
+                                        Ok(region) => region,
+                                        Err(_) => {
+                                            log::warn!("Endpoint and/or region not configured for {bucket} and S3_REGION/S3_ENDPOINT env var missing");
+                                            return None;
+                                        }
+                                    }
+                                }
+                            };
+
+
+                            let mut bucket_obj = Bucket::new(bucket, s3_region, credentials).map_err(|e| {
+                                log::warn!("Failed to create S3 bucket '{}': {e}", bucket);
+                            }).ok()?;
+                            bucket_obj.set_path_style();
+
+                            let res = bucket_obj.get_object(key).map_err(|e| {
+                                log::warn!(
+                                    "Failed to get S3 object '{}' from bucket '{}': {e}",
+                                    key,
+                                    bucket
+                                );
+                            }).ok()?;
+
+                            if res.status_code() != 200 {
+                                log::warn!(
+                                    "Non 200 status code response for S3 object '{}' in bucket '{}': status {}",
+                                    key,
+                                    bucket,
+                                    res.status_code()
+                                );
+                                return None;
+                            }
+
+                            let bytes = res.bytes();
+
+                            if bytes.len() < 1000 {
+                                let content_str = String::from_utf8_lossy(&bytes);
+                                if content_str.trim_start().starts_with("<?xml") {
+                                    log::warn!(
+                                        "S3 error response for object '{}' in bucket '{}': {}",
+                                        key,
+                                        bucket,
+                                        content_str
+                                    );
+                                    return None;
+                                }
+                            }
+
+                            image::load_from_memory(&bytes)
+                                .map(ImageData::from)
+                                .map_err(|e| {
+                                    log::warn!(
+                                        "Failed to load image from S3 object '{}' in bucket '{}': {e}",
+                                        key,
+                                        bucket
+                                    );
+                                })
+                                .ok()
+                        })()
+                    }
+                    #[cfg(not(feature = "s3"))]
+                    Data::S3 { .. } => {
+                        log::warn!("S3 feature is not enabled. Rebuild with --features s3 to enable S3 support.");
+                        None
+                    }
+                    #[cfg(feature = "http")]
+                    Data::Http { url, .. } => {
+                        let url = url.clone();
+                        (|| -> Option<ImageData> {
+                            let res = self.client.get(&url).send().map_err(|e| {
+                                log::warn!("Failed to send HTTP request to '{}': {e}", url);
+                            }).ok()?;
+
+                            let bytes = res.bytes().map_err(|e| {
+                                log::warn!("Failed to get response bytes from '{}': {e}", url);
+                            }).ok()?;
+
+                            image::load_from_memory(&bytes)
+                                .map(ImageData::from)
+                                .map_err(|e| {
+                                    log::warn!("Failed to load image from HTTP response '{}': {e}", url);
+                                })
+                                .ok()
+                        })()
+                    }
+                    #[cfg(not(feature = "http"))]
+                    Data::Http { .. } => {
+                        log::warn!("HTTP feature is not enabled. Rebuild with --features http to enable HTTP support.");
+                        None
+                    }
+                };
+
+                let image = image
+                    .ok_or_else(|| anyhow::anyhow!("No wallpaper to show on {output_name}"))?;
+                Ok((
+                    Arc::clone(output_name),
+                    assets::AssetData {
+                        image,
+                        resize: wallpaper.resize,
+                        transition: wallpaper.transition.clone(),
+                        motion,
+                        shader: shader.clone(),
+                        blend: background
+                            .as_ref()
+                            .and_then(|background| background.blend.clone()),
+                    },
+                ))
+            }).collect::<anyhow::Result<Vec<_>>>()?;
+
+            assets
+                .into_iter()
+                .for_each(|(name, asset)| self.assets.insert_asset(name, asset));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "s3")]
+fn create_bucket(name: &str, config: &config::S3Bucket) -> Option<Box<Bucket>> {
+    let access_key = config.get_access_key().ok()?;
+    let secret_key = config.get_secret_key().ok()?;
+
+    let credentials = Credentials {
+        access_key: Some(access_key),
+        secret_key: Some(secret_key),
+        security_token: None,
+        session_token: None,
+        expiration: None,
+    };
+
+    let s3_region = match config.region.as_ref() {
+        Some(region) => Region::Custom {
+            region: region.clone(),
+            endpoint: config.url.clone(),
+        },
+        None => Region::from_env("S3_REGION", Some("S3_ENDPOINT")).ok()?,
+    };
+
+    match Bucket::new(name, s3_region, credentials) {
+        Ok(bucket) => Some(bucket),
+        Err(e) => {
+            log::error!("Failed to create bucket '{name}': {e}");
+            None
+        }
     }
 }
 
@@ -238,6 +718,127 @@ struct Cli {
 
     #[arg(short, long, value_name = "FILE", help = "Path to the config file")]
     config: Option<Box<Path>>,
+
+    #[arg(
+        long,
+        help = "Start on the config's wallpapers instead of the ones set before the daemon stopped"
+    )]
+    no_restore: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        help = "Report the problems of the config, or of FILE, instead of running the daemon"
+    )]
+    check_config: Option<Option<Box<Path>>>,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        help = "Print the config evaluated in FORMAT instead of running the daemon, e.g. to move from Nix to TOML"
+    )]
+    convert_config: Option<config::Format>,
+
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["FROM", "TO"],
+        requires = "preview_output",
+        help = "Render a transition between two images to disk instead of running the daemon"
+    )]
+    render_preview: Option<Vec<PathBuf>>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "render_preview",
+        help = "Write the preview as a GIF if PATH ends in .gif, otherwise as PNG frames in the PATH directory"
+    )]
+    preview_output: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "WIDTHxHEIGHT",
+        value_parser = parse_size,
+        default_value = "1920x1080",
+        help = "Output size of the preview"
+    )]
+    preview_size: (u32, u32),
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        help = "Resize strategy of the preview images"
+    )]
+    preview_resize: ResizeStrategy,
+
+    #[arg(
+        long,
+        value_parser = parse_transition_type,
+        requires = "render_preview",
+        help = "Type of the previewed transition, as for `moxpaper img --transition-type`"
+    )]
+    preview_transition_type: Option<TransitionType>,
+
+    #[arg(
+        long,
+        requires = "render_preview",
+        help = "How long the previewed transition takes in milliseconds"
+    )]
+    preview_transition_duration: Option<u128>,
+
+    #[arg(
+        long,
+        requires = "render_preview",
+        help = "Frame rate of the preview, 60 by default"
+    )]
+    preview_transition_fps: Option<u64>,
+
+    #[arg(
+        long,
+        value_parser = parse_bezier,
+        requires = "render_preview",
+        help = "Timing function of the previewed transition, as for `moxpaper img --transition-bezier`"
+    )]
+    preview_transition_bezier: Option<BezierChoice>,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = parse_weighted_transition,
+        requires = "render_preview",
+        help = "Transitions \"random\" picks from, as for `moxpaper img --transition-pool`"
+    )]
+    preview_transition_pool: Vec<WeightedTransition>,
+
+    #[arg(
+        long = "preview-transition-stage",
+        value_parser = parse_stage,
+        requires = "render_preview",
+        help = "Stage of a chained transition, as for `moxpaper img --transition-stage`"
+    )]
+    preview_transition_stages: Vec<TransitionStage>,
+
+    #[arg(
+        long,
+        default_value_t = 0,
+        requires = "render_preview",
+        help = "Seed of the random choices of the previewed transition"
+    )]
+    preview_seed: u64,
+}
+
+fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let (width, height) = s
+        .split_once('x')
+        .ok_or_else(|| format!("Expected WIDTHxHEIGHT, got {s}"))?;
+    let width = width.trim().parse().map_err(|e| format!("Width: {e}"))?;
+    let height = height.trim().parse().map_err(|e| format!("Height: {e}"))?;
+
+    Ok((width, height))
 }
 
 fn main() -> anyhow::Result<()> {
@@ -247,8 +848,52 @@ fn main() -> anyhow::Result<()> {
         .filter(Some("daemon"), cli.log_level.unwrap_or(LevelFilter::Info))
         .init();
 
+    if let Some(path) = cli.check_config {
+        return check::run(path.as_deref().or(cli.config.as_deref()));
+    }
+
+    if let Some(format) = cli.convert_config {
+        let (path, code) = Config::source(cli.config.as_deref())?
+            .ok_or(anyhow::anyhow!("Config file not found"))?;
+        let (value, _) = config::Format::of(&path).evaluate(&code, &[])?;
+        Config::from_value(value.clone())
+            .1
+            .iter()
+            .for_each(|problem| log::warn!("{problem}"));
+        print!("{}", format.print(&value)?);
+        return Ok(());
+    }
+
     let config = Config::load(cli.config.as_ref());
 
+    if let (Some(images), Some(output)) = (cli.render_preview, cli.preview_output) {
+        let (width, height) = cli.preview_size;
+        return preview::render(
+            preview::Preview {
+                from: &images[0],
+                to: &images[1],
+                width,
+                height,
+                resize: cli.preview_resize,
+                transition: Transition {
+                    transition_type: cli.preview_transition_type,
+                    duration: cli.preview_transition_duration,
+                    fps: cli.preview_transition_fps,
+                    bezier: cli.preview_transition_bezier,
+                    random_pool: random_pool(cli.preview_transition_pool, false),
+                    stages: cli.preview_transition_stages,
+                    ..Default::default()
+                },
+                output: &output,
+                seed: cli.preview_seed,
+            },
+            &config,
+        );
+    }
+
+    // Before any other thread starts, see reload::hangup.
+    let hangup = reload::hangup()?;
+
     let conn = Connection::connect_to_env().expect("Connection to wayland failed");
     let display = conn.display();
 
@@ -258,7 +903,19 @@ fn main() -> anyhow::Result<()> {
     let ipc = Ipc::server()?;
 
     let mut event_loop = EventLoop::try_new()?;
-    let mut moxpaper = Moxpaper::new(&conn, qh, ipc, event_loop.handle(), config)?;
+    let mut moxpaper = Moxpaper::new(
+        &conn,
+        qh,
+        ipc,
+        event_loop.handle(),
+        config,
+        cli.config.clone(),
+    )?;
+    if !cli.no_restore {
+        moxpaper.restore(false);
+    }
+
+    reload::watch(&event_loop.handle(), cli.config.as_deref(), hangup)?;
 
     WaylandSource::new(conn, event_queue)
         .insert(event_loop.handle())
@@ -311,305 +968,67 @@ fn main() -> anyhow::Result<()> {
 
         if let Err(e) = state.handle.insert_source(source, move |_, _, state| {
             let wallpaper = match state.ipc.handle_stream_data(&fd) {
-                Ok(data) => data,
+                Ok(Request::Wallpaper(data)) => *data,
+                Ok(Request::Playlist { action, outputs }) => {
+                    state.control_playlists(action, &outputs);
+                    return Ok(calloop::PostAction::Continue);
+                }
+                Ok(Request::Restore) => {
+                    state.restore(true);
+                    state.render();
+                    return Ok(calloop::PostAction::Continue);
+                }
+                Ok(Request::Reload) => {
+                    state.reload_config();
+                    return Ok(calloop::PostAction::Continue);
+                }
+                Ok(Request::Undo { outputs }) => {
+                    state.step_history(&outputs, false);
+                    return Ok(calloop::PostAction::Continue);
+                }
+                Ok(Request::Redo { outputs }) => {
+                    state.step_history(&outputs, true);
+                    return Ok(calloop::PostAction::Continue);
+                }
+                Ok(Request::History { outputs }) => {
+                    let entries = state.history.entries(&outputs);
+                    let reply = match serde_json::to_string(&entries) {
+                        Ok(reply) => reply,
+                        Err(e) => {
+                            log::error!("Failed to serialize history: {e}");
+                            return Ok(calloop::PostAction::Remove);
+                        }
+                    };
+                    if let Some(stream) = state.ipc.get_mut(&fd)
+                        && let Err(e) = stream
+                            .write_all(format!("{reply}\n").as_bytes())
+                            .and_then(|_| stream.flush())
+                    {
+                        log::error!("Stream write error: {e}");
+                    }
+                    return Ok(calloop::PostAction::Continue);
+                }
                 Err(e) => {
                     log::info!("{e}");
                     return Ok(calloop::PostAction::Remove);
                 }
             };
 
-            if wallpaper.outputs.is_empty() {
-                let image = match wallpaper.data {
-                    Data::Image(image) => FallbackImage::Image(assets::AssetData {
-                        image,
-                        resize: wallpaper.resize,
-                        transition: wallpaper.transition,
-                    }),
-                    Data::Path(path) => {
-                        if path.extension().is_some_and(|e| e == "svg") {
-                            let svg_data = std::fs::read(path)?;
-
-                            FallbackImage::Svg {
-                                data: svg_data.into(),
-                                transition: wallpaper.transition,
-                            }
-                        } else {
-                            match image::open(path).map(ImageData::from) {
-                                Ok(img) => FallbackImage::Image(assets::AssetData {
-                                    image: img,
-                                    resize: wallpaper.resize,
-                                    transition: wallpaper.transition,
-                                }),
-                                Err(e) => {
-                                    log::error!("Image open error: {e}");
-                                    return Ok(calloop::PostAction::Continue);
-                                }
-                            }
-                        }
-                    }
-                    Data::Color(color) => FallbackImage::Color {
-                        color: image::Rgb(color),
-                        transition: wallpaper.transition,
-                    },
-                    #[cfg(feature = "s3")]
-                    Data::S3 { bucket, key } => {
-                        let bucket_obj = match state.buckets.get_mut(&bucket) {
-                            Some(bucket_obj) => bucket_obj,
-                            None => {
-                                log::warn!("bucket {bucket} not found");
-                                return Ok(calloop::PostAction::Continue);
-                            }
-                        };
-                        bucket_obj.set_path_style();
-
-                        let res = match bucket_obj.get_object(&key) {
-                            Ok(res) => res,
-                            Err(e) => {
-                                log::warn!("Failed to get S3 object '{}' from bucket '{}': {e}", key, bucket);
-                                return Ok(calloop::PostAction::Continue);
-                            }
-                        };
-
-                        if res.status_code() != 200 {
-                            log::warn!("Non 200 status code response for S3 object '{}' in bucket '{}': status {}", key, bucket, res.status_code());
-                            return Ok(calloop::PostAction::Continue);
-                        }
-
-                        let bytes = res.bytes();
-                        if bytes.len() < 1000 {
-                            let content_str = String::from_utf8_lossy(&bytes);
-                            if content_str.trim_start().starts_with("<?xml") {
-                                log::warn!("S3 error response for object '{}' in bucket '{}': {}", key, bucket, content_str);
-                                return Ok(calloop::PostAction::Continue);
-                            }
-                        }
-
-                        let image_data = match image::load_from_memory(&bytes) {
-                            Ok(data) => data,
-                            Err(e) => {
-                                log::warn!("Failed to load image from S3 object '{}' in bucket '{}': {e}", key, bucket);
-                                return Ok(calloop::PostAction::Continue);
-                            }
-                        };
-
-                        FallbackImage::Image(assets::AssetData {
-                            image: ImageData::from(image_data),
-                            resize: wallpaper.resize,
-                            transition: wallpaper.transition,
-                        })
-                    }
-                    #[cfg(not(feature = "s3"))]
-                    Data::S3 { .. } => {
-                        log::warn!("S3 feature is not enabled. Rebuild with --features s3 to enable S3 support.");
-                        return Ok(calloop::PostAction::Continue);
-                    }
-                    #[cfg(feature = "http")]
-                    Data::Http { url, .. } => {
-                        let url_clone = url.clone();
-                        let res = match state.client.get(&url_clone).send() {
-                            Ok(res) => res,
-                            Err(e) => {
-                                log::warn!("Failed to send HTTP request to '{}': {e}", url_clone);
-                                return Ok(calloop::PostAction::Continue);
-                            }
-                        };
-
-                        let bytes = match res.bytes() {
-                            Ok(bytes) => bytes,
-                            Err(e) => {
-                                log::warn!("Failed to get response bytes from '{}': {e}", url_clone);
-                                return Ok(calloop::PostAction::Continue);
-                            }
-                        };
-
-                        let image_data = match image::load_from_memory(&bytes) {
-                            Ok(data) => data,
-                            Err(e) => {
-                                log::warn!("Failed to load image from HTTP response '{}': {e}", url_clone);
-                                return Ok(calloop::PostAction::Continue);
-                            }
-                        };
-
-                        FallbackImage::Image(assets::AssetData {
-                            image: ImageData::from(image_data),
-                            resize: wallpaper.resize,
-                            transition: wallpaper.transition,
-                        })
-                    }
-                    #[cfg(not(feature = "http"))]
-                    Data::Http { .. } => {
-                        log::warn!("HTTP feature is not enabled. Rebuild with --features http to enable HTTP support.");
-                        return Ok(calloop::PostAction::Continue);
-                    }
-                };
-
-                state.assets.set_fallback(image);
-            } else {
-                wallpaper.outputs.iter().for_each(|output_name| {
-                    let image = match &wallpaper.data {
-                        Data::Image(image) => Some(image.clone()),
-                        Data::Path(path) => {
-                            if path.extension().is_some_and(|e| e == "svg") {
-                                state
-                                    .outputs
-                                    .iter()
-                                    .find(|output| &output.info.name == output_name)
-                                    .and_then(|output| {
-                                        render_svg(path, output.info.width, output.info.height).ok()
-                                    })
-                            } else {
-                                image::open(path).map(ImageData::from).ok()
-                            }
-                        }
-                        Data::Color(color) => state
-                            .outputs
-                            .iter()
-                            .find(|output| &output.info.name == output_name)
-                            .map(|output| {
-                                let rgba_image = RgbaImage::from_pixel(
-                                    output.info.width,
-                                    output.info.height,
-                                    image::Rgba([color[0], color[1], color[2], 255]),
-                                );
-
-                                ImageData::from(rgba_image)
-                            }),
-                        #[cfg(feature = "s3")]
-                        Data::S3 { bucket, key } => {
-                            (|| -> Option<ImageData> {
-                                let alias_config = match state.config.buckets.get(bucket) {
-                                    Some(config) => config,
-                                    None => {
-                                        log::warn!("Alias {} not found", bucket);
-                                        return None;
-                                    }
-                                };
-
-                                let access_key = alias_config.get_access_key().map_err(|e| {
-                                    log::warn!("Failed to get access key for alias {}: {e}", bucket);
-                                }).ok()?;
-                                let secret_key = alias_config.get_secret_key().map_err(|e| {
-                                    log::warn!("Failed to get secret key for alias {}: {e}", bucket);
-                                }).ok()?;
-
-                                let credentials = Credentials {
-                                    access_key: Some(access_key),
-                                    secret_key: Some(secret_key),
-                                    security_token: None,
-                                    session_token: None,
-                                    expiration: None,
-                                };
-
-                                let s3_region = match alias_config.region.as_ref() {
-                                    Some(region) => Region::Custom {
-                                        region: region.clone(),
-                                        endpoint: alias_config.url.clone(),
-                                    },
-                                    None => {
-                                        match Region::from_env("S3_REGION", Some("S3_ENDPOINT")) {
-                                            Ok(region) => region,
-                                            Err(_) => {
-                                                log::warn!("Endpoint and/or region not configured for {bucket} and S3_REGION/S3_ENDPOINT env var missing");
-                                                return None;
-                                            }
-                                        }
-                                    }
-                                };
-
-
-                                let mut bucket_obj = Bucket::new(bucket, s3_region, credentials).map_err(|e| {
-                                    log::warn!("Failed to create S3 bucket '{}': {e}", bucket);
-                                }).ok()?;
-                                bucket_obj.set_path_style();
-
-                                let res = bucket_obj.get_object(key).map_err(|e| {
-                                    log::warn!(
-                                        "Failed to get S3 object '{}' from bucket '{}': {e}",
-                                        key,
-                                        bucket
-                                    );
-                                }).ok()?;
-
-                                if res.status_code() != 200 {
-                                    log::warn!(
-                                        "Non 200 status code response for S3 object '{}' in bucket '{}': status {}",
-                                        key,
-                                        bucket,
-                                        res.status_code()
-                                    );
-                                    return None;
-                                }
-
-                                let bytes = res.bytes();
-
-                                if bytes.len() < 1000 {
-                                    let content_str = String::from_utf8_lossy(&bytes);
-                                    if content_str.trim_start().starts_with("<?xml") {
-                                        log::warn!(
-                                            "S3 error response for object '{}' in bucket '{}': {}",
-                                            key,
-                                            bucket,
-                                            content_str
-                                        );
-                                        return None;
-                                    }
-                                }
-
-                                image::load_from_memory(&bytes)
-                                    .map(ImageData::from)
-                                    .map_err(|e| {
-                                        log::warn!(
-                                            "Failed to load image from S3 object '{}' in bucket '{}': {e}",
-                                            key,
-                                            bucket
-                                        );
-                                    })
-                                    .ok()
-                            })()
-                        }
-                        #[cfg(not(feature = "s3"))]
-                        Data::S3 { .. } => {
-                            log::warn!("S3 feature is not enabled. Rebuild with --features s3 to enable S3 support.");
-                            None
-                        }
-                        #[cfg(feature = "http")]
-                        Data::Http { url, .. } => {
-                            let url = url.clone();
-                            (|| -> Option<ImageData> {
-                                let res = state.client.get(&url).send().map_err(|e| {
-                                    log::warn!("Failed to send HTTP request to '{}': {e}", url);
-                                }).ok()?;
-
-                                let bytes = res.bytes().map_err(|e| {
-                                    log::warn!("Failed to get response bytes from '{}': {e}", url);
-                                }).ok()?;
-
-                                image::load_from_memory(&bytes)
-                                    .map(ImageData::from)
-                                    .map_err(|e| {
-                                        log::warn!("Failed to load image from HTTP response '{}': {e}", url);
-                                    })
-                                    .ok()
-                            })()
-                        }
-                        #[cfg(not(feature = "http"))]
-                        Data::Http { .. } => {
-                            log::warn!("HTTP feature is not enabled. Rebuild with --features http to enable HTTP support.");
-                            None
-                        }
-                    };
-
-                    if let Some(image) = image {
-                        state.assets.insert_asset(
-                            Arc::clone(output_name),
-                            assets::AssetData {
-                                image,
-                                resize: wallpaper.resize,
-                                transition: wallpaper.transition.clone(),
-                            },
-                        );
+            // Recorded once it's shown, images sent along aren't kept.
+            let recorded = match wallpaper.data {
+                Data::Image(_) => None,
+                _ => Some(wallpaper.clone()),
+            };
+            let outputs = wallpaper.outputs.clone();
+            match state.load_wallpaper(wallpaper) {
+                Ok(()) => match recorded {
+                    Some(wallpaper) => {
+                        state.saved.record(&wallpaper);
+                        state.record_history(&wallpaper);
                     }
-                });
+                    None => state.clear_history(&outputs),
+                },
+                Err(e) => log::error!("Failed to load wallpaper: {e}"),
             }
 
             state.render();
@@ -735,6 +1154,7 @@ impl Dispatch<wl_registry::WlRegistry, ()> for Moxpaper {
                         surface,
                         layer_surface,
                         state.handle.clone(),
+                        state.qh.clone(),
                         name,
                     );
 
@@ -752,6 +1172,7 @@ impl Dispatch<wl_registry::WlRegistry, ()> for Moxpaper {
 
                 if let Some(index) = index {
                     state.outputs.swap_remove(index);
+                    state.outputs_changed(&[]);
                 }
             }
             _ => unreachable!(),
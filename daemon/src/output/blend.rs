@@ -0,0 +1,74 @@
+use crate::{assets::BlendSource, solar::SunPlayer, timed::TimedPlayer};
+use image::RgbaImage;
+use libmoxpaper::{ResizeStrategy, image_data::ImageData};
+use std::time::Duration;
+
+/// What a [`Blender`] shows next.
+pub struct BlendFrame {
+    pub image: ImageData,
+    /// Drawn over `image` with the opacity
+    pub overlay: Option<(ImageData, f32)>,
+    /// When to ask for the next frame
+    pub delay: Duration,
+}
+
+/// Plays a [`BlendSource`] resized to an output.
+pub enum Blender {
+    Sun(SunPlayer),
+    Timed(TimedPlayer),
+}
+
+impl Blender {
+    /// `fade` is how long switching images takes where the source doesn't
+    /// say.
+    pub fn new(
+        source: BlendSource,
+        resize: ResizeStrategy,
+        width: u32,
+        height: u32,
+        fade: Duration,
+    ) -> Self {
+        match source {
+            BlendSource::Sun(source) => {
+                Self::Sun(SunPlayer::new(source, resize, width, height, fade))
+            }
+            BlendSource::Timed(source) => {
+                Self::Timed(TimedPlayer::new(source, resize, width, height))
+            }
+        }
+    }
+
+    /// What the wallpaper looks like now, as a single image.
+    pub fn image(&mut self) -> anyhow::Result<ImageData> {
+        match self {
+            Self::Sun(player) => player.image(),
+            Self::Timed(player) => player.image(),
+        }
+    }
+
+    pub fn next_frame(&mut self) -> anyhow::Result<BlendFrame> {
+        match self {
+            Self::Sun(player) => player.next_frame(),
+            Self::Timed(player) => player.next_frame(),
+        }
+    }
+}
+
+/// `overlay` drawn over `base` with the opacity, for images of the same
+/// size.
+pub fn mix(base: &ImageData, overlay: &ImageData, opacity: f32) -> ImageData {
+    if (base.width(), base.height()) != (overlay.width(), overlay.height()) {
+        return base.clone();
+    }
+
+    let data = base
+        .data()
+        .iter()
+        .zip(overlay.data())
+        .map(|(&a, &b)| (a as f32 + (b as f32 - a as f32) * opacity).round() as u8)
+        .collect::<Vec<_>>();
+
+    RgbaImage::from_raw(base.width(), base.height(), data)
+        .map(ImageData::from)
+        .unwrap_or_else(|| base.clone())
+}
@@ -0,0 +1,416 @@
+use super::wgpu_surface::Source;
+use crate::animation::MaskFrame;
+use anyhow::Context;
+use image::{Rgba, RgbaImage};
+use libmoxpaper::{MaskSource, image_data::ImageData};
+use std::{
+    f32::consts::PI,
+    sync::mpsc::{self, Receiver},
+};
+
+/// Mask rendered on a worker thread, so that large outputs don't hold up
+/// the event loop when a stage starts.
+pub struct Pending {
+    receiver: Receiver<anyhow::Result<ImageData>>,
+    mask: Option<ImageData>,
+}
+
+impl Pending {
+    pub fn spawn(source: MaskSource, width: u32, height: u32) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            _ = sender.send(generate(&source, width, height));
+        });
+
+        Self {
+            receiver,
+            mask: None,
+        }
+    }
+
+    /// The mask once it is rendered, `None` while it isn't or if it failed.
+    pub fn get(&mut self) -> Option<&ImageData> {
+        if self.mask.is_none() {
+            match self.receiver.try_recv() {
+                Ok(Ok(mask)) => self.mask = Some(mask),
+                Ok(Err(e)) => log::error!("Failed to generate transition mask: {e}"),
+                Err(_) => {}
+            }
+        }
+
+        self.mask.as_ref()
+    }
+
+    /// Blocks until the mask is rendered.
+    pub fn wait(self) -> anyhow::Result<ImageData> {
+        match self.mask {
+            Some(mask) => Ok(mask),
+            None => self
+                .receiver
+                .recv()
+                .map_err(|_| anyhow::anyhow!("Mask generation stopped"))?,
+        }
+    }
+}
+
+/// Renders the luminance mask of `source` at the given size, as gray RGBA.
+pub fn generate(source: &MaskSource, width: u32, height: u32) -> anyhow::Result<ImageData> {
+    let (half_width, half_height) = (width as f32 / 2., height as f32 / 2.);
+
+    let values = match source {
+        MaskSource::Image(path) => {
+            let image = image::open(path).with_context(|| format!("{}", path.display()))?;
+            return ImageData::from(image.grayscale()).resize_crop(width, height);
+        }
+        MaskSource::Radial => procedural(width, height, |dx, dy| {
+            dx.hypot(dy) / half_width.hypot(half_height)
+        }),
+        MaskSource::Clock => procedural(width, height, |dx, dy| {
+            dx.atan2(-dy).rem_euclid(2. * PI) / (2. * PI)
+        }),
+        MaskSource::Diamond => procedural(width, height, |dx, dy| {
+            (dx.abs() / half_width + dy.abs() / half_height) / 2.
+        }),
+        MaskSource::Noise => noise(width, height),
+    };
+
+    Ok(ImageData::from(values))
+}
+
+/// Evaluates `f` with each pixel's offset from the center of the output.
+fn procedural<F>(width: u32, height: u32, f: F) -> RgbaImage
+where
+    F: Fn(f32, f32) -> f32,
+{
+    let center = [width as f32 / 2., height as f32 / 2.];
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let value = f(x as f32 + 0.5 - center[0], y as f32 + 0.5 - center[1]);
+        let luma = (value.clamp(0., 1.) * 255.).round() as u8;
+        Rgba([luma, luma, luma, 255])
+    })
+}
+
+/// A few octaves of value noise, stretched over the full luminance range so
+/// that the wipe takes the whole transition.
+fn noise(width: u32, height: u32) -> RgbaImage {
+    const OCTAVES: u32 = 4;
+
+    let cell = width.max(height) as f32 / 8.;
+    let values = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x as f32, y as f32)))
+        .map(|(x, y)| {
+            (0..OCTAVES)
+                .map(|octave| {
+                    let frequency = (1 << octave) as f32 / cell;
+                    value_noise(x * frequency, y * frequency, octave) / (1 << octave) as f32
+                })
+                .sum::<f32>()
+        })
+        .collect::<Vec<_>>();
+
+    let min = values.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let range = (max - min).max(f32::EPSILON);
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let value = (values[(y * width + x) as usize] - min) / range;
+        let luma = (value * 255.).round() as u8;
+        Rgba([luma, luma, luma, 255])
+    })
+}
+
+fn value_noise(x: f32, y: f32, seed: u32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (smoothstep(x - x0), smoothstep(y - y0));
+    let (x0, y0) = (x0 as i32, y0 as i32);
+
+    let lattice = |x: i32, y: i32| hash(x, y, seed);
+    let top = lattice(x0, y0) + (lattice(x0 + 1, y0) - lattice(x0, y0)) * tx;
+    let bottom = lattice(x0, y0 + 1) + (lattice(x0 + 1, y0 + 1) - lattice(x0, y0 + 1)) * tx;
+
+    top + (bottom - top) * ty
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3. - 2. * t)
+}
+
+/// Deterministic lattice value in `0..=1`.
+fn hash(x: i32, y: i32, seed: u32) -> f32 {
+    let mut h = (x as u32)
+        .wrapping_mul(0x8da6_b343)
+        .wrapping_add((y as u32).wrapping_mul(0xd816_3841))
+        .wrapping_add(seed.wrapping_mul(0xcb1a_b31f));
+    h ^= h >> 13;
+    h = h.wrapping_mul(0x5bd1_e995);
+    h ^= h >> 15;
+
+    h as f32 / u32::MAX as f32
+}
+
+/// CPU counterpart of `fs_main` in `mask.wgsl`: how much of the new
+/// wallpaper shows at a pixel of the given mask luminance.
+pub fn blend(luma: f32, frame: &MaskFrame) -> f32 {
+    let threshold = frame.progress * (1. + frame.softness);
+    ((threshold - luma) / frame.softness).clamp(0., 1.)
+}
+
+struct MaskTextures {
+    previous: wgpu::Texture,
+    target: wgpu::Texture,
+    mask: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Blends the outgoing and incoming images through a luminance mask.
+pub struct MaskRenderer {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniforms: wgpu::Buffer,
+    textures: Option<MaskTextures>,
+    /// The shader wallpaper draws the target
+    shader: bool,
+}
+
+impl MaskRenderer {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("mask.wgsl"));
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Mask Bind Group Layout"),
+            entries: &[
+                texture_entry(0),
+                texture_entry(1),
+                texture_entry(2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Mask Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            ..Default::default()
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Mask Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview_mask: None,
+            cache: None,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Mask Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Mask Uniforms"),
+            // Uniform buffers are bound in multiples of 16 bytes.
+            size: 16,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            sampler,
+            uniforms,
+            textures: None,
+            shader: false,
+        }
+    }
+
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        previous: &ImageData,
+        target: Source,
+        mask: &ImageData,
+        frame: &MaskFrame,
+    ) {
+        let (previous, mask) = (Source::Image(previous), Source::Image(mask));
+
+        let outdated = self.textures.as_ref().is_none_or(|textures| {
+            textures.previous.size() != previous.size()
+                || textures.target.size() != target.size()
+                || textures.mask.size() != mask.size()
+        });
+        if outdated {
+            self.textures =
+                Some(self.create_textures(device, previous.size(), target.size(), mask.size()));
+        }
+        let Some(textures) = self.textures.as_ref() else {
+            return;
+        };
+
+        [
+            (&textures.previous, previous),
+            (&textures.target, target),
+            (&textures.mask, mask),
+        ]
+        .into_iter()
+        .for_each(|(texture, source)| source.write(queue, texture));
+        self.shader = matches!(target, Source::Shader { .. });
+
+        let uniforms = [frame.progress, frame.softness, 0., 0.];
+        let bytes = uniforms
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect::<Vec<_>>();
+        queue.write_buffer(&self.uniforms, 0, &bytes);
+    }
+
+    /// Texture of the target if the shader wallpaper draws it, to be drawn
+    /// before [`MaskRenderer::render`].
+    pub fn shader_view(&self) -> Option<wgpu::TextureView> {
+        self.textures
+            .as_ref()
+            .filter(|_| self.shader)
+            .map(|textures| {
+                textures
+                    .target
+                    .create_view(&wgpu::TextureViewDescriptor::default())
+            })
+    }
+
+    fn create_textures(
+        &self,
+        device: &wgpu::Device,
+        previous: wgpu::Extent3d,
+        target: wgpu::Extent3d,
+        mask: wgpu::Extent3d,
+    ) -> MaskTextures {
+        let texture = |size, format| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Mask Layer Texture"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_DST
+                    | wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            })
+        };
+
+        let previous = texture(previous, wgpu::TextureFormat::Rgba8UnormSrgb);
+        let target = texture(target, wgpu::TextureFormat::Rgba8UnormSrgb);
+        // Luminance is compared as stored, without sRGB decoding.
+        let mask = texture(mask, wgpu::TextureFormat::Rgba8Unorm);
+
+        let views = [&previous, &target, &mask]
+            .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()));
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Mask Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&views[0]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&views[1]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&views[2]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.uniforms.as_entire_binding(),
+                },
+            ],
+        });
+
+        MaskTextures {
+            previous,
+            target,
+            mask,
+            bind_group,
+        }
+    }
+
+    pub fn render(&self, texture_view: &wgpu::TextureView, encoder: &mut wgpu::CommandEncoder) {
+        let Some(textures) = self.textures.as_ref() else {
+            return;
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Mask Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: texture_view,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &textures.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,45 @@
+// Mirrors `mask::blend`.
+
+struct Uniforms {
+    progress: f32,
+    softness: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var previous_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var target_texture: texture_2d<f32>;
+@group(0) @binding(2)
+var mask_texture: texture_2d<f32>;
+@group(0) @binding(3)
+var texture_sampler: sampler;
+@group(0) @binding(4)
+var<uniform> uniforms: Uniforms;
+
+// A single triangle covering the whole output.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let previous = textureSample(previous_texture, texture_sampler, in.uv);
+    let next = textureSample(target_texture, texture_sampler, in.uv);
+    let luma = textureSample(mask_texture, texture_sampler, in.uv).r;
+
+    let threshold = uniforms.progress * (1.0 + uniforms.softness);
+    let amount = clamp((threshold - luma) / uniforms.softness, 0.0, 1.0);
+
+    return mix(previous, next, amount);
+}
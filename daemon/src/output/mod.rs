@@ -1,24 +1,173 @@
+pub mod blend;
+pub mod mask;
+pub mod perspective;
+pub mod shader;
 pub mod wgpu_surface;
 
 use crate::{
     Moxpaper,
-    animation::{self, FrameData, bezier::BezierBuilder},
+    animated::{Frame, Player},
+    animation::{self, FrameData, Frames, TransitionConfig},
+    assets::AssetData,
+    config::Config,
 };
-use calloop::LoopHandle;
+use anyhow::Context;
+use blend::Blender;
+use calloop::{
+    LoopHandle, RegistrationToken,
+    timer::{TimeoutAction, Timer},
+};
+use image::{Rgba, RgbaImage};
 use libmoxpaper::{
+    Intermediate, InterruptPolicy, OutputInfo, Transition, TransitionStage, TransitionType,
     image_data::ImageData,
-    BezierChoice, OutputInfo, ResizeStrategy,
 };
 use moxui::{
     texture_renderer::{self, TextureArea, TextureBounds},
     viewport,
 };
-use std::sync::Arc;
+use perspective::Layer;
+use shader::{ShaderRenderer, ShaderSource};
+use std::{collections::VecDeque, sync::Arc, time::Duration};
 use wayland_client::{
     Connection, Dispatch, QueueHandle,
-    protocol::{wl_output, wl_surface},
+    protocol::{wl_callback, wl_output, wl_surface},
 };
 use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_surface_v1;
+use wgpu_surface::{Scene, Source};
+
+/// Most transitions queued behind a running one; queuing more drops the
+/// oldest.
+const MAX_QUEUED: usize = 8;
+
+/// One step of a chained transition: the image it ends on and how it gets
+/// there.
+pub struct Stage {
+    pub image: ImageData,
+    pub config: TransitionConfig,
+    /// Plays the rest of an animated wallpaper, only set on the last stage
+    pub player: Option<Player>,
+    /// Draws a shader wallpaper in place of `image`, only set on the last
+    /// stage
+    pub shader: Option<ShaderSource>,
+    /// Blends images over the day in place of `image`, only set on the last
+    /// stage
+    pub blend: Option<Blender>,
+    /// Mask of the stage's transition if it is a mask wipe, rendering from
+    /// when the chain is built
+    pub mask: Option<mask::Pending>,
+}
+
+impl Stage {
+    /// Expands a wallpaper into the stages that lead to it on an output of
+    /// the given size.
+    pub fn chain(
+        wallpaper: AssetData,
+        width: u32,
+        height: u32,
+        config: &Config,
+    ) -> anyhow::Result<Vec<Self>> {
+        let transition = wallpaper.transition.clone();
+        let resize = wallpaper.resize;
+        let player = wallpaper
+            .motion
+            .clone()
+            .map(|motion| Player::new(motion, resize, width, height));
+        let shader = wallpaper.shader.clone();
+        let mut blend = wallpaper.blend.clone().map(|source| {
+            let fade = TransitionConfig::new(&transition, config).duration;
+            Blender::new(
+                source,
+                resize,
+                width,
+                height,
+                Duration::from_millis(fade as u64),
+            )
+        });
+        let image = match blend.as_mut() {
+            Some(blend) => blend.image()?,
+            None => wallpaper.into_resized(width, height)?,
+        };
+
+        let Some((last, intermediate)) = transition.stages.split_last() else {
+            let config = TransitionConfig::new(&transition, config);
+            return Ok(vec![Self {
+                image,
+                mask: stage_mask(&config, width, height),
+                config,
+                player,
+                shader,
+                blend,
+            }]);
+        };
+
+        let stage_config = |stage: &TransitionStage| {
+            let stage_transition = Transition {
+                transition_type: stage
+                    .transition_type
+                    .clone()
+                    .or(transition.transition_type.clone()),
+                fps: stage.fps.or(transition.fps),
+                duration: stage.duration.or(transition.duration),
+                bezier: stage.bezier.clone().or(transition.bezier.clone()),
+                ..transition.clone()
+            };
+            TransitionConfig::new(&stage_transition, config)
+        };
+
+        let mut stages = intermediate
+            .iter()
+            .map(|stage| {
+                let image = match stage.intermediate.as_ref() {
+                    Some(Intermediate::Color(color)) => ImageData::from(RgbaImage::from_pixel(
+                        width,
+                        height,
+                        Rgba([color[0], color[1], color[2], 255]),
+                    )),
+                    Some(Intermediate::Image(path)) => {
+                        let intermediate =
+                            image::open(path).with_context(|| format!("{}", path.display()))?;
+                        AssetData::new(ImageData::from(intermediate), resize, Transition::default())
+                            .into_resized(width, height)?
+                    }
+                    None => image.clone(),
+                };
+
+                let config = stage_config(stage);
+                Ok(Self {
+                    image,
+                    mask: stage_mask(&config, width, height),
+                    config,
+                    player: None,
+                    shader: None,
+                    blend: None,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let config = stage_config(last);
+        stages.push(Self {
+            image,
+            mask: stage_mask(&config, width, height),
+            config,
+            player,
+            shader,
+            blend,
+        });
+
+        Ok(stages)
+    }
+}
+
+/// Starts rendering the mask of a stage that wipes through one.
+fn stage_mask(config: &TransitionConfig, width: u32, height: u32) -> Option<mask::Pending> {
+    match &config.transition_type {
+        TransitionType::Mask(mask) => {
+            Some(mask::Pending::spawn(mask.source.clone(), width, height))
+        }
+        _ => None,
+    }
+}
 
 pub struct Output {
     pub id: u32,
@@ -26,10 +175,33 @@ pub struct Output {
     layer_surface: zwlr_layer_surface_v1::ZwlrLayerSurfaceV1,
     surface: wl_surface::WlSurface,
     wl_output: wl_output::WlOutput,
-    pub previous_image: Option<(ImageData, FrameData)>,
+    pub previous_image: Option<ImageData>,
     pub target_image: Option<ImageData>,
+    /// Luminance mask of the running transition, if it is a mask wipe.
+    mask: Option<mask::Pending>,
+    stages: VecDeque<Stage>,
+    pub queue: VecDeque<Vec<Stage>>,
     pub info: OutputInfo,
+    /// Manufacturer and model the compositor reports, if any
+    pub make: Option<Arc<str>>,
+    pub model: Option<Arc<str>>,
     pub animation: animation::Animation,
+    /// Animated wallpaper being shown, if any
+    player: Option<Player>,
+    /// Shader wallpaper being shown, if any
+    shader: Option<ShaderSource>,
+    /// Wallpaper blending images over the day being shown, if any
+    blend: Option<Blender>,
+    /// Drawn over the target image with its opacity while idle, when
+    /// `blend` is between two images
+    overlay: Option<(ImageData, f32)>,
+    /// Timer advancing the animated, shader or blended wallpaper
+    playback: Option<RegistrationToken>,
+    /// A shader without a frame rate waits for the compositor to ask for
+    /// its next frame
+    frame_requested: bool,
+    handle: LoopHandle<'static, Moxpaper>,
+    qh: QueueHandle<Moxpaper>,
 }
 
 impl Output {
@@ -38,6 +210,7 @@ impl Output {
         surface: wl_surface::WlSurface,
         layer_surface: zwlr_layer_surface_v1::ZwlrLayerSurfaceV1,
         loop_handle: LoopHandle<'static, Moxpaper>,
+        qh: QueueHandle<Moxpaper>,
         id: u32,
     ) -> Self {
         Self {
@@ -46,99 +219,322 @@ impl Output {
             layer_surface,
             surface,
             info: OutputInfo::default(),
+            make: None,
+            model: None,
             wgpu: None,
-            animation: animation::Animation::new(loop_handle),
+            animation: animation::Animation::new(loop_handle.clone()),
             previous_image: None,
             target_image: None,
+            mask: None,
+            stages: VecDeque::new(),
+            queue: VecDeque::new(),
+            player: None,
+            shader: None,
+            blend: None,
+            overlay: None,
+            playback: None,
+            frame_requested: false,
+            handle: loop_handle,
+            qh,
         }
     }
 
-    pub fn render(&mut self) {
-        let Some(wgpu) = self.wgpu.as_mut() else {
+    /// Starts a chain of transitions, honouring the interrupt policy of its
+    /// first stage if another transition is still running.
+    pub fn transition_to(&mut self, stages: Vec<Stage>) {
+        let Some(first) = stages.first() else {
             return;
         };
 
-        let Some(texture) = self.target_image.as_ref() else {
+        // A shader draws straight to the output while nothing transitions,
+        // keep what it shows now.
+        if self.shader.is_some() && !self.animation.is_active() {
+            match self.capture_shader() {
+                Ok(frame) => {
+                    self.target_image = None;
+                    self.previous_image = Some(frame);
+                }
+                Err(e) => log::warn!("Failed to capture shader wallpaper: {e}"),
+            }
+        }
+
+        if self.animation.is_active() {
+            match first.config.interrupt {
+                InterruptPolicy::Drop => return,
+                InterruptPolicy::Queue => {
+                    // A queued transition starts whenever the running one
+                    // ends, a shared start instant would be stale by then.
+                    let stages = stages
+                        .into_iter()
+                        .map(|stage| Stage {
+                            config: TransitionConfig {
+                                start_at: None,
+                                ..stage.config
+                            },
+                            ..stage
+                        })
+                        .collect();
+                    if self.queue.len() == MAX_QUEUED {
+                        log::debug!(
+                            "Dropping the oldest queued transition on {}",
+                            self.info.name
+                        );
+                        self.queue.pop_front();
+                    }
+                    self.queue.push_back(stages);
+                    return;
+                }
+                InterruptPolicy::Interrupt => match self.capture() {
+                    Ok(frame) => {
+                        self.target_image = None;
+                        self.previous_image = Some(frame);
+                    }
+                    Err(e) => {
+                        log::warn!("Failed to capture interrupted transition: {e}");
+                        if let Some(image) = self.target_image.take() {
+                            self.previous_image = Some(image);
+                        }
+                    }
+                },
+            }
+        } else if let Some(image) = self.target_image.take() {
+            // Keep the images of a blended wallpaper as they are shown.
+            self.previous_image = Some(match self.overlay.take() {
+                Some((overlay, opacity)) => blend::mix(&image, &overlay, opacity),
+                None => image,
+            });
+        }
+
+        self.stages = stages.into();
+        self.next_stage();
+    }
+
+    /// Starts the next stage of the running chain. Returns `false` once the
+    /// chain is done.
+    pub fn next_stage(&mut self) -> bool {
+        let Some(stage) = self.stages.pop_front() else {
+            return false;
+        };
+
+        let extents = animation::Extents {
+            x: 0.,
+            y: 0.,
+            width: self.info.width as f32,
+            height: self.info.height as f32,
+        };
+
+        self.target_image = Some(stage.image);
+        self.animation.start(&self.info.name, stage.config, extents);
+
+        // Random transitions are only resolved once started.
+        self.mask = match self.animation.transition_type() {
+            Some(TransitionType::Mask(mask)) => Some(stage.mask.unwrap_or_else(|| {
+                mask::Pending::spawn(mask.source.clone(), self.info.width, self.info.height)
+            })),
+            _ => None,
+        };
+
+        // The outgoing layer keeps the frame it was on.
+        if let Some(token) = self.playback.take() {
+            self.handle.remove(token);
+        }
+        self.player = stage.player;
+        self.blend = stage.blend;
+        self.overlay = None;
+        self.start_shader(stage.shader);
+        if self.player.is_some()
+            || self
+                .shader
+                .as_ref()
+                .and_then(ShaderSource::interval)
+                .is_some()
+            || self.blend.is_some()
+        {
+            let id = self.id;
+            let token = self
+                .handle
+                .insert_source(Timer::immediate(), move |_, _, state| {
+                    let Some(output) = state.outputs.iter_mut().find(|output| output.id == id)
+                    else {
+                        return TimeoutAction::Drop;
+                    };
+
+                    match output.play() {
+                        Some(delay) => TimeoutAction::ToDuration(delay),
+                        None => {
+                            output.playback = None;
+                            TimeoutAction::Drop
+                        }
+                    }
+                })
+                .inspect_err(|e| log::error!("Failed to start playback: {e}"))
+                .ok();
+            self.playback = token;
+        }
+
+        true
+    }
+
+    /// Compiles the shader wallpaper, the previous image is the texture it
+    /// samples. Transitions draw it straight into their incoming layer.
+    fn start_shader(&mut self, shader: Option<ShaderSource>) {
+        self.shader = None;
+        let Some(wgpu) = self.wgpu.as_mut() else {
             return;
         };
 
-        let mut textures = Vec::new();
-
-        let frame_data = self.animation.frame_data().unwrap_or_default();
-        if let Some(prev_texture) = self.previous_image.as_ref() {
-            let frame_data = prev_texture.1;
-            let mut buffer = texture_renderer::Buffer::new(
-                prev_texture.0.width() as f32,
-                prev_texture.0.height() as f32,
-            );
-            buffer.set_bytes(prev_texture.0.data());
-            buffer.set_brightness(frame_data.filters.brightness);
-            buffer.set_contrast(frame_data.filters.contrast);
-            buffer.set_saturation(frame_data.filters.saturation);
-            buffer.set_hue_rotate(frame_data.filters.hue_rotate);
-            buffer.set_sepia(frame_data.filters.sepia);
-            buffer.set_invert(frame_data.filters.invert);
-            buffer.set_grayscale(frame_data.filters.grayscale);
-            buffer.set_blur(frame_data.filters.blur);
-            buffer.set_opacity(frame_data.filters.opacity);
-            buffer.set_scale(frame_data.transforms.scale_x, frame_data.transforms.scale_y);
-            let color = frame_data.filters.blur_color;
-            buffer.set_blur_color(color[0], color[1], color[2], color[3]);
-
-            let prev_texture_area = TextureArea {
-                buffer,
-                radius: frame_data.radius,
-                left: frame_data.transforms.translate[0] * self.info.width as f32,
-                top: frame_data.transforms.translate[1] * self.info.height as f32,
-                scale: 1.0,
-                bounds: TextureBounds {
-                    left: (frame_data.clip.left * self.info.width as f32) as u32,
-                    top: (frame_data.clip.top * self.info.height as f32) as u32,
-                    right: (frame_data.clip.right * self.info.width as f32) as u32,
-                    bottom: (frame_data.clip.bottom * self.info.height as f32) as u32,
-                },
-                rotation: frame_data.rotation,
-                skew: [frame_data.transforms.skew_x, frame_data.transforms.skew_y],
-                depth: 0.5,
+        wgpu.shader_renderer = shader.as_ref().and_then(|source| {
+            ShaderRenderer::new(
+                &wgpu.device,
+                &wgpu.queue,
+                wgpu.config.format,
+                source,
+                self.previous_image.as_ref(),
+            )
+            .inspect_err(|e| log::error!("Failed to compile shader: {e}"))
+            .ok()
+        });
+        if wgpu.shader_renderer.is_none() {
+            return;
+        }
+
+        self.shader = shader;
+    }
+
+    fn capture_shader(&mut self) -> anyhow::Result<ImageData> {
+        self.wgpu
+            .as_mut()
+            .ok_or(anyhow::anyhow!("Output surface is not configured"))?
+            .capture(&Scene::Shader)
+    }
+
+    /// Shows the next frame of the animated or shader wallpaper and returns
+    /// when to look for the one after. `None` once playback is over,
+    /// leaving the last frame.
+    fn play(&mut self) -> Option<Duration> {
+        if let Some(interval) = self.shader.as_ref().and_then(ShaderSource::interval) {
+            // A transition draws it with its own frames.
+            if !self.animation.is_active() {
+                self.render();
+            }
+            return Some(interval);
+        }
+
+        if let Some(blend) = self.blend.as_mut() {
+            if self.animation.is_active() {
+                // The stage image holds, blends change slowly.
+                return Some(Duration::from_millis(100));
+            }
+
+            return match blend.next_frame() {
+                Ok(frame) => {
+                    self.previous_image = None;
+                    self.target_image = Some(frame.image);
+                    self.overlay = frame.overlay;
+                    self.render();
+                    Some(frame.delay)
+                }
+                Err(e) => {
+                    log::error!("Failed to show blended wallpaper: {e}");
+                    self.blend = None;
+                    None
+                }
             };
+        }
 
-            textures.push(prev_texture_area);
+        let player = self.player.as_mut()?;
+        let (frame, delay) = match player.next_frame() {
+            Ok(Frame::Show(frame, delay)) => (frame, delay),
+            Ok(Frame::Wait(delay)) => return Some(delay),
+            Ok(Frame::End) => {
+                self.player = None;
+                return None;
+            }
+            Err(e) => {
+                log::error!("Failed to decode animated wallpaper: {e}");
+                self.player = None;
+                return None;
+            }
+        };
+
+        if self.animation.is_active() {
+            // The transition draws it with its next frame.
+            self.target_image = Some(frame);
+        } else {
+            self.previous_image = None;
+            self.target_image = Some(frame);
+            self.render();
+            self.previous_image = self.target_image.take();
         }
 
-        let mut buffer =
-            texture_renderer::Buffer::new(texture.width() as f32, texture.height() as f32);
-        buffer.set_bytes(texture.data());
-        buffer.set_scale(frame_data.transforms.scale_x, frame_data.transforms.scale_y);
-        buffer.set_brightness(frame_data.filters.brightness);
-        buffer.set_contrast(frame_data.filters.contrast);
-        buffer.set_saturation(frame_data.filters.saturation);
-        buffer.set_hue_rotate(frame_data.filters.hue_rotate);
-        buffer.set_sepia(frame_data.filters.sepia);
-        buffer.set_invert(frame_data.filters.invert);
-        buffer.set_grayscale(frame_data.filters.grayscale);
-        buffer.set_blur(frame_data.filters.blur);
-        buffer.set_opacity(frame_data.filters.opacity);
-        let color = frame_data.filters.blur_color;
-        buffer.set_blur_color(color[0], color[1], color[2], color[3]);
-
-        let texture_area = TextureArea {
-            buffer,
-            radius: frame_data.radius,
-            left: frame_data.transforms.translate[0] * self.info.width as f32,
-            top: frame_data.transforms.translate[1] * self.info.height as f32,
-            scale: 1.0,
-            bounds: TextureBounds {
-                left: (frame_data.clip.left * self.info.width as f32) as u32,
-                top: (frame_data.clip.top * self.info.height as f32) as u32,
-                right: (frame_data.clip.right * self.info.width as f32) as u32,
-                bottom: (frame_data.clip.bottom * self.info.height as f32) as u32,
-            },
-            rotation: frame_data.rotation,
-            skew: [frame_data.transforms.skew_x, frame_data.transforms.skew_y],
-            depth: 0.9,
+        Some(delay)
+    }
+
+    /// Reads back the currently composited frame, so that an interrupted
+    /// transition can continue from exactly what is on screen.
+    fn capture(&mut self) -> anyhow::Result<ImageData> {
+        let frames = self.animation.frames();
+        let scene = scene(
+            self.previous_image.as_ref(),
+            self.target_image.as_ref(),
+            self.shader.is_some(),
+            self.mask.as_mut().and_then(mask::Pending::get),
+            &frames,
+            &self.info,
+        )
+        .ok_or(anyhow::anyhow!("Nothing to capture"))?;
+        let wgpu = self
+            .wgpu
+            .as_mut()
+            .ok_or(anyhow::anyhow!("Output surface is not configured"))?;
+
+        wgpu.capture(&scene)
+    }
+
+    pub fn render(&mut self) {
+        let frames = self.animation.frames();
+        let scene = if self.shader.is_some() && !self.animation.is_active() {
+            Some(Scene::Shader)
+        } else if let (Some(target), Some((overlay, opacity)), false) = (
+            self.target_image.as_ref(),
+            self.overlay.as_ref(),
+            self.animation.is_active(),
+        ) {
+            let mut frame_data = FrameData::default();
+            frame_data.filters.opacity = *opacity;
+            Some(Scene::Flat(vec![
+                texture_area(target, &FrameData::default(), &self.info, 0.5),
+                texture_area(overlay, &frame_data, &self.info, 0.9),
+            ]))
+        } else {
+            scene(
+                self.previous_image.as_ref(),
+                self.target_image.as_ref(),
+                self.shader.is_some(),
+                self.mask.as_mut().and_then(mask::Pending::get),
+                &frames,
+                &self.info,
+            )
+        };
+        let Some(scene) = scene else {
+            return;
         };
 
-        textures.push(texture_area);
+        // Committed along with the frame, the compositor answers once it
+        // wants the next one and not while the output is hidden.
+        let paced = matches!(scene, Scene::Shader)
+            && self
+                .shader
+                .as_ref()
+                .is_some_and(|shader| shader.interval().is_none());
+        if paced && !self.frame_requested {
+            self.surface.frame(&self.qh, self.id);
+            self.frame_requested = true;
+        }
+
+        let Some(wgpu) = self.wgpu.as_mut() else {
+            return;
+        };
 
         let surface_texture = wgpu
             .surface
@@ -150,17 +546,133 @@ impl Output {
 
         let mut encoder = wgpu.device.create_command_encoder(&Default::default());
 
-        wgpu.texture_renderer
-            .prepare(&wgpu.device, &wgpu.queue, &textures);
-
-        wgpu.texture_renderer
-            .render(&texture_view, &mut encoder, &wgpu.viewport);
+        wgpu.draw(&scene, &texture_view, &mut encoder);
 
         wgpu.queue.submit(Some(encoder.finish()));
         surface_texture.present();
     }
 }
 
+/// Layers of the current frame, bottom to top. Frames with any layer out of
+/// the output's plane, or drawn by the shader wallpaper as the `shader`
+/// target does, go through the perspective renderer.
+fn scene<'a>(
+    previous: Option<&'a ImageData>,
+    target: Option<&'a ImageData>,
+    shader: bool,
+    mask: Option<&'a ImageData>,
+    frames: &'a Frames,
+    info: &OutputInfo,
+) -> Option<Scene<'a>> {
+    let target = match shader {
+        true => Source::Shader {
+            width: info.width,
+            height: info.height,
+        },
+        false => Source::Image(target?),
+    };
+
+    if let (Some(previous), Some(frame)) = (previous, frames.mask) {
+        return Some(match mask {
+            Some(mask) => Scene::Masked {
+                previous,
+                target,
+                mask,
+                frame,
+            },
+            // The old wallpaper holds until the mask is rendered.
+            None => Scene::Flat(vec![texture_area(
+                previous,
+                &FrameData::default(),
+                info,
+                0.5,
+            )]),
+        });
+    }
+
+    let mut layers = Vec::new();
+    if let Some(previous) = previous {
+        layers.push(Layer {
+            source: Source::Image(previous),
+            frame_data: frames.outgoing,
+            pieces: &frames.outgoing_pieces,
+        });
+    }
+    layers.push(Layer {
+        source: target,
+        frame_data: frames.incoming,
+        pieces: &frames.incoming_pieces,
+    });
+
+    if frames.outgoing_on_top {
+        layers.reverse();
+    }
+
+    let flat = layers
+        .iter()
+        .all(|layer| layer.pieces.is_empty() && layer.frame_data.is_flat());
+    if flat
+        && let Some(textures) = layers
+            .iter()
+            .enumerate()
+            .map(|(i, layer)| {
+                // Upper layers sit closer to the viewer.
+                let depth = if i + 1 == layers.len() { 0.9 } else { 0.5 };
+                match layer.source {
+                    Source::Image(image) => {
+                        Some(texture_area(image, &layer.frame_data, info, depth))
+                    }
+                    // The texture renderer only takes images.
+                    Source::Shader { .. } => None,
+                }
+            })
+            .collect::<Option<Vec<_>>>()
+    {
+        return Some(Scene::Flat(textures));
+    }
+
+    Some(Scene::Perspective(layers))
+}
+
+fn texture_area(
+    image: &ImageData,
+    frame_data: &FrameData,
+    info: &OutputInfo,
+    depth: f32,
+) -> TextureArea {
+    let mut buffer = texture_renderer::Buffer::new(image.width() as f32, image.height() as f32);
+    buffer.set_bytes(image.data());
+    buffer.set_scale(frame_data.transforms.scale_x, frame_data.transforms.scale_y);
+    buffer.set_brightness(frame_data.filters.brightness);
+    buffer.set_contrast(frame_data.filters.contrast);
+    buffer.set_saturation(frame_data.filters.saturation);
+    buffer.set_hue_rotate(frame_data.filters.hue_rotate);
+    buffer.set_sepia(frame_data.filters.sepia);
+    buffer.set_invert(frame_data.filters.invert);
+    buffer.set_grayscale(frame_data.filters.grayscale);
+    buffer.set_blur(frame_data.filters.blur);
+    buffer.set_opacity(frame_data.filters.opacity);
+    let color = frame_data.filters.blur_color;
+    buffer.set_blur_color(color[0], color[1], color[2], color[3]);
+
+    TextureArea {
+        buffer,
+        radius: frame_data.radius,
+        left: frame_data.transforms.translate[0] * info.width as f32,
+        top: frame_data.transforms.translate[1] * info.height as f32,
+        scale: 1.0,
+        bounds: TextureBounds {
+            left: (frame_data.clip.left * info.width as f32) as u32,
+            top: (frame_data.clip.top * info.height as f32) as u32,
+            right: (frame_data.clip.right * info.width as f32) as u32,
+            bottom: (frame_data.clip.bottom * info.height as f32) as u32,
+        },
+        rotation: frame_data.rotation,
+        skew: [frame_data.transforms.skew_x, frame_data.transforms.skew_y],
+        depth,
+    }
+}
+
 impl Dispatch<wl_output::WlOutput, ()> for Moxpaper {
     fn event(
         state: &mut Self,
@@ -188,6 +700,18 @@ impl Dispatch<wl_output::WlOutput, ()> for Moxpaper {
                 output.info.width = width as u32;
                 output.info.height = height as u32;
             }
+            wl_output::Event::Geometry {
+                x, y, make, model, ..
+            } => {
+                output.info.x = x;
+                output.info.y = y;
+                // Compositors without the information send "unknown" or
+                // nothing.
+                let known =
+                    |value: String| (!value.is_empty() && value != "unknown").then(|| value.into());
+                output.make = known(make);
+                output.model = known(model);
+            }
             wl_output::Event::Scale { factor } => {
                 output.info.scale = factor;
             }
@@ -217,6 +741,31 @@ impl Dispatch<wl_surface::WlSurface, ()> for Moxpaper {
     }
 }
 
+impl Dispatch<wl_callback::WlCallback, u32> for Moxpaper {
+    fn event(
+        state: &mut Self,
+        _proxy: &wl_callback::WlCallback,
+        event: wl_callback::Event,
+        id: &u32,
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+        let wl_callback::Event::Done { .. } = event else {
+            return;
+        };
+        let Some(output) = state.outputs.iter_mut().find(|output| output.id == *id) else {
+            return;
+        };
+
+        output.frame_requested = false;
+        // A transition draws the shader with its own frames, and starts
+        // asking again once it's over.
+        if output.shader.is_some() && !output.animation.is_active() {
+            output.render();
+        }
+    }
+}
+
 impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, ()> for Moxpaper {
     fn event(
         state: &mut Self,
@@ -277,88 +826,12 @@ impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, ()> for Moxpaper {
             width as f32,
             height as f32,
         );
+        wgpu.perspective_renderer
+            .resize(&wgpu.device, width, height);
 
         output.layer_surface.ack_configure(serial);
 
-        let wallpaper = state
-            .assets
-            .get(&output.info.name, output.info.width, output.info.height);
-
-        if let Some(wallpaper) = wallpaper {
-            let resized = match wallpaper.resize {
-                ResizeStrategy::No => {
-                    Ok(wallpaper
-                        .image
-                        .pad(output.info.width, output.info.height, &[0, 0, 0]))
-                }
-                ResizeStrategy::Fit => wallpaper
-                    .image
-                    .resize_to_fit(output.info.width, output.info.height),
-                ResizeStrategy::Crop => wallpaper
-                    .image
-                    .resize_crop(output.info.width, output.info.height),
-                ResizeStrategy::Stretch => wallpaper
-                    .image
-                    .resize_stretch(output.info.width, output.info.height),
-            };
-
-            if let Ok(resized) = resized {
-                let bezier = wallpaper
-                    .transition
-                    .bezier
-                    .as_ref()
-                    .unwrap_or(&state.config.default_bezier);
-                let bezier = match bezier {
-                    BezierChoice::Linear => BezierBuilder::new().linear(),
-                    BezierChoice::Ease => BezierBuilder::new().ease(),
-                    BezierChoice::EaseIn => BezierBuilder::new().ease_in(),
-                    BezierChoice::EaseOut => BezierBuilder::new().ease_out(),
-                    BezierChoice::EaseInOut => BezierBuilder::new().ease_in_out(),
-                    BezierChoice::Custom(curve) => {
-                        BezierBuilder::new().custom(curve.0, curve.1, curve.2, curve.3)
-                    }
-                    BezierChoice::Named(bezier) => {
-                        if let Some(a) = state.config.bezier.get(bezier) {
-                            BezierBuilder::new().custom(a.0, a.1, a.2, a.3)
-                        } else {
-                            log::warn!("Bezier: {bezier} not found");
-                            BezierBuilder::new().linear()
-                        }
-                    }
-                };
-                let extents = animation::Extents {
-                    x: 0.,
-                    y: 0.,
-                    width: output.info.width as f32,
-                    height: output.info.height as f32,
-                };
-                if let Some(image) = output.target_image.take() {
-                    output.previous_image =
-                        Some((image, output.animation.frame_data().unwrap_or_default()));
-                }
-                output.target_image = Some(resized);
-                output.animation.start(
-                    &output.info.name,
-                    animation::TransitionConfig {
-                        enabled_transition_types: state
-                            .config
-                            .enabled_transition_types
-                            .as_ref()
-                            .map(Arc::clone),
-                        transition_type: wallpaper
-                            .transition
-                            .transition_type
-                            .unwrap_or(state.config.default_transition_type.clone()),
-                        fps: wallpaper.transition.fps.or(state.config.default_fps),
-                        duration: wallpaper
-                            .transition
-                            .duration
-                            .unwrap_or(state.config.default_transition_duration),
-                        bezier,
-                    },
-                    extents,
-                );
-            }
-        }
+        let name = Arc::clone(&output.info.name);
+        state.show_configured(&name);
     }
 }
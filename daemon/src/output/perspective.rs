@@ -0,0 +1,580 @@
+use super::wgpu_surface::Source;
+use crate::{
+    animation::{FrameData, Piece},
+    buffers::{
+        DataDescription, DepthBuffer, GpuBuffer, IndexBuffer, Vertex, VertexBuffer,
+        instance::{Instance, InstanceBuffer},
+    },
+};
+use std::{f32::consts::PI, ops::Range};
+
+/// Quads per side of the layer mesh, enough for a smooth page curl.
+const GRID: u16 = 48;
+
+/// Radius of the page curl cylinder as a fraction of the output width.
+const CURL_RADIUS: f32 = 0.1;
+
+/// A layer with a 3D placement.
+pub struct Layer<'a> {
+    pub source: Source<'a>,
+    pub frame_data: FrameData,
+    /// Drawn instead of the whole layer when not empty
+    pub pieces: &'a [Piece],
+}
+
+/// Per-layer or per-piece parameters in output pixels, shared by the GPU
+/// and CPU paths.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct LayerInstance {
+    output: [f32; 2],
+    /// Area covered before any transform: x, y, width, height
+    rect: [f32; 4],
+    /// Area of the texture shown, in texture coordinates
+    uv: [f32; 4],
+    translate: [f32; 2],
+    scale: [f32; 2],
+    rotate: [f32; 3],
+    pivot: [f32; 3],
+    curl: [f32; 2],
+    opacity: f32,
+    /// Bounds the layer is cut to on the output: left, top, right, bottom
+    clip: [f32; 4],
+    /// Corner radii of `clip`
+    radius: [f32; 4],
+}
+
+impl DataDescription for LayerInstance {
+    const ATTRIBS: &'static [wgpu::VertexAttribute] = &wgpu::vertex_attr_array![
+        1 => Float32x2,
+        2 => Float32x4,
+        3 => Float32x4,
+        4 => Float32x2,
+        5 => Float32x2,
+        6 => Float32x3,
+        7 => Float32x3,
+        8 => Float32x2,
+        9 => Float32,
+        10 => Float32x4,
+        11 => Float32x4,
+    ];
+    const STEP_MODE: wgpu::VertexStepMode = wgpu::VertexStepMode::Instance;
+}
+
+impl Instance for LayerInstance {}
+
+/// A transformed mesh vertex in output pixels, `z` points towards the viewer.
+#[derive(Debug, Clone, Copy)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    /// Texture coordinates
+    pub uv: [f32; 2],
+    pub shade: f32,
+    pub back: f32,
+}
+
+impl LayerInstance {
+    /// A whole layer on an output of the given size.
+    pub fn new(frame_data: &FrameData, width: f32, height: f32) -> Self {
+        Self::piece(
+            &Piece {
+                rect: [0., 0., 1., 1.],
+                frame_data: *frame_data,
+            },
+            width,
+            height,
+        )
+    }
+
+    pub fn piece(piece: &Piece, width: f32, height: f32) -> Self {
+        let projection = &piece.frame_data.projection;
+        let transforms = &piece.frame_data.transforms;
+        let clip = &piece.frame_data.clip;
+        let [x, y, w, h] = piece.rect;
+        let rect = [x * width, y * height, w * width, h * height];
+
+        let curl_radius = CURL_RADIUS * rect[2];
+        let curl = if projection.curl > 0. {
+            [
+                rect[2] - projection.curl.min(1.) * (rect[2] + 2. * curl_radius),
+                curl_radius,
+            ]
+        } else {
+            [rect[2], 0.]
+        };
+
+        Self {
+            output: [width, height],
+            rect,
+            uv: piece.rect,
+            translate: [
+                transforms.translate[0] * width,
+                transforms.translate[1] * height,
+            ],
+            scale: [transforms.scale_x, transforms.scale_y],
+            rotate: [
+                projection.rotate_x.to_radians(),
+                projection.rotate_y.to_radians(),
+                transforms.rotate.to_radians(),
+            ],
+            pivot: [
+                rect[0] + projection.pivot[0] * rect[2],
+                rect[1] + projection.pivot[1] * rect[3],
+                projection.pivot[2] * rect[2],
+            ],
+            curl,
+            opacity: piece.frame_data.filters.opacity,
+            // Whole pixels, like the bounds of the texture renderer.
+            clip: [
+                clip.left * width,
+                clip.top * height,
+                clip.right * width,
+                clip.bottom * height,
+            ]
+            .map(|bound| bound.max(0.).trunc()),
+            radius: piece.frame_data.radius,
+        }
+    }
+
+    pub fn opacity(&self) -> f32 {
+        self.opacity
+    }
+
+    /// How much of the output pixel centered on `point` the clip leaves.
+    pub fn coverage(&self, point: [f32; 2]) -> f32 {
+        rounded_coverage(point, self.clip, &self.radius)
+    }
+
+    /// CPU counterpart of `vs_main` in `perspective.wgsl`, before projection.
+    pub fn vertex(&self, uv: [f32; 2]) -> MeshVertex {
+        let mut position = [uv[0] * self.rect[2], uv[1] * self.rect[3], 0.];
+        let mut shade = 1.;
+        let mut back = 0.;
+
+        let [curl_x, radius] = self.curl;
+        let distance = position[0] - curl_x;
+        if radius > 0. && distance > 0. {
+            let theta = distance / radius;
+            if theta < PI {
+                position[0] = curl_x + radius * theta.sin();
+                position[2] = radius * (1. - theta.cos());
+                shade = 1. - 0.35 * theta.sin();
+            } else {
+                position[0] = curl_x - (distance - PI * radius);
+                position[2] = 2. * radius;
+            }
+            if theta > PI / 2. {
+                back = 1.;
+            }
+        }
+
+        let center = [self.rect[2] / 2., self.rect[3] / 2.];
+        position[0] = (position[0] - center[0]) * self.scale[0] + center[0] + self.rect[0];
+        position[1] = (position[1] - center[1]) * self.scale[1] + center[1] + self.rect[1];
+
+        let mut q = [
+            position[0] - self.pivot[0],
+            position[1] - self.pivot[1],
+            position[2] - self.pivot[2],
+        ];
+
+        let (sin, cos) = self.rotate[2].sin_cos();
+        q = [q[0] * cos - q[1] * sin, q[0] * sin + q[1] * cos, q[2]];
+
+        let (sin, cos) = self.rotate[0].sin_cos();
+        q = [q[0], q[1] * cos - q[2] * sin, q[1] * sin + q[2] * cos];
+
+        let (sin, cos) = self.rotate[1].sin_cos();
+        q = [q[0] * cos + q[2] * sin, q[1], -q[0] * sin + q[2] * cos];
+
+        MeshVertex {
+            position: [
+                q[0] + self.pivot[0] + self.translate[0],
+                q[1] + self.pivot[1] + self.translate[1],
+                q[2] + self.pivot[2],
+            ],
+            uv: [
+                self.uv[0] + uv[0] * self.uv[2],
+                self.uv[1] + uv[1] * self.uv[3],
+            ],
+            shade,
+            back,
+        }
+    }
+
+    /// Projects a vertex onto the output, returning screen pixels and depth.
+    pub fn project(&self, position: [f32; 3]) -> [f32; 3] {
+        let distance_to_camera = 1.5 * self.output[0].max(self.output[1]);
+        let factor = distance_to_camera / (distance_to_camera - position[2]);
+        let center = [self.output[0] / 2., self.output[1] / 2.];
+
+        [
+            center[0] + (position[0] - center[0]) * factor,
+            center[1] + (position[1] - center[1]) * factor,
+            0.5 - position[2] / (4. * distance_to_camera),
+        ]
+    }
+}
+
+/// Coverage of `point` inside the rounded bounds, none outside of them.
+/// Radii are fractions of half the shorter side, ordered top-left,
+/// top-right, bottom-right, bottom-left. Mirrored by `rounded_coverage` in
+/// `perspective.wgsl`.
+pub fn rounded_coverage(point: [f32; 2], bounds: [f32; 4], radius: &[f32; 4]) -> f32 {
+    let [left, top, right, bottom] = bounds;
+    if point[0] < left || point[1] < top || point[0] >= right || point[1] >= bottom {
+        return 0.;
+    }
+
+    let half_extent = (right - left).min(bottom - top) / 2.;
+    let center = [(left + right) / 2., (top + bottom) / 2.];
+
+    let corner = match (point[0] < center[0], point[1] < center[1]) {
+        (true, true) => radius[0],
+        (false, true) => radius[1],
+        (false, false) => radius[2],
+        (true, false) => radius[3],
+    };
+    let corner = corner.clamp(0., 1.) * half_extent;
+    if corner <= 0. {
+        return 1.;
+    }
+
+    let half_size = [(right - left) / 2., (bottom - top) / 2.];
+    let qx = (point[0] - center[0]).abs() - half_size[0] + corner;
+    let qy = (point[1] - center[1]).abs() - half_size[1] + corner;
+    let distance = qx.max(0.).hypot(qy.max(0.)) + qx.max(qy).min(0.) - corner;
+
+    (0.5 - distance).clamp(0., 1.)
+}
+
+/// Texture coordinates of the layer mesh.
+pub fn mesh() -> (Vec<[f32; 2]>, Vec<u16>) {
+    let vertices = (0..=GRID)
+        .flat_map(|y| (0..=GRID).map(move |x| [x as f32 / GRID as f32, y as f32 / GRID as f32]))
+        .collect();
+
+    let row = GRID + 1;
+    let indices = (0..GRID)
+        .flat_map(|y| {
+            (0..GRID).flat_map(move |x| {
+                let i = y * row + x;
+                [i, i + 1, i + row, i + 1, i + row + 1, i + row]
+            })
+        })
+        .collect();
+
+    (vertices, indices)
+}
+
+/// Texture coordinates of a single quad, enough for flat pieces.
+pub fn quad() -> (Vec<[f32; 2]>, Vec<u16>) {
+    (
+        vec![[0., 0.], [1., 0.], [0., 1.], [1., 1.]],
+        vec![0, 1, 2, 1, 3, 2],
+    )
+}
+
+struct LayerTexture {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+struct Mesh {
+    vertices: VertexBuffer,
+    indices: IndexBuffer,
+}
+
+impl Mesh {
+    fn new(device: &wgpu::Device, (uvs, indices): (Vec<[f32; 2]>, Vec<u16>)) -> Self {
+        let uvs = uvs
+            .into_iter()
+            .map(|position| Vertex { position })
+            .collect::<Vec<_>>();
+
+        Self {
+            vertices: VertexBuffer::new(device, &uvs),
+            indices: IndexBuffer::new(device, &indices),
+        }
+    }
+}
+
+/// Instances of one layer: the layer itself, or all of its pieces in a
+/// single instanced draw.
+struct LayerDraw {
+    instances: Range<u32>,
+    pieces: bool,
+}
+
+/// Draws layers with rotations and curls in perspective, as well as layers
+/// cut into pieces. Used instead of the texture renderer for frames that
+/// aren't flat.
+pub struct PerspectiveRenderer {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    mesh: Mesh,
+    quad: Mesh,
+    instances: InstanceBuffer<LayerInstance>,
+    instance_capacity: usize,
+    textures: Vec<LayerTexture>,
+    depth: DepthBuffer,
+    layers: Vec<LayerDraw>,
+    /// Layer the shader wallpaper draws into, if any
+    shader_layer: Option<usize>,
+}
+
+impl PerspectiveRenderer {
+    /// Most layers a frame can have: outgoing and incoming.
+    const MAX_LAYERS: usize = 2;
+
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("perspective.wgsl"));
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Perspective Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Perspective Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            ..Default::default()
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Perspective Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[Vertex::desc(), LayerInstance::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: wgpu::TextureFormat::Depth32Float,
+                depth_write_enabled: true,
+                // Later layers win ties, so flat layers keep their order.
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview_mask: None,
+            cache: None,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Perspective Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            sampler,
+            mesh: Mesh::new(device, mesh()),
+            quad: Mesh::new(device, quad()),
+            instances: InstanceBuffer::with_size(
+                device,
+                (std::mem::size_of::<LayerInstance>() * Self::MAX_LAYERS) as u64,
+            ),
+            instance_capacity: Self::MAX_LAYERS,
+            textures: Vec::new(),
+            depth: DepthBuffer::new(device, width, height),
+            layers: Vec::new(),
+            shader_layer: None,
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.depth = DepthBuffer::new(device, width, height);
+    }
+
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layers: &[Layer],
+        width: u32,
+        height: u32,
+    ) {
+        let layers = &layers[..layers.len().min(Self::MAX_LAYERS)];
+        let (width, height) = (width as f32, height as f32);
+
+        let mut instances = Vec::new();
+        self.layers = layers
+            .iter()
+            .map(|layer| {
+                let start = instances.len() as u32;
+                if layer.pieces.is_empty() {
+                    instances.push(LayerInstance::new(&layer.frame_data, width, height));
+                } else {
+                    instances.extend(
+                        layer
+                            .pieces
+                            .iter()
+                            .map(|piece| LayerInstance::piece(piece, width, height)),
+                    );
+                }
+
+                LayerDraw {
+                    instances: start..instances.len() as u32,
+                    pieces: !layer.pieces.is_empty(),
+                }
+            })
+            .collect();
+
+        if instances.len() > self.instance_capacity {
+            self.instance_capacity = instances.len().next_power_of_two();
+            self.instances = InstanceBuffer::with_size(
+                device,
+                (std::mem::size_of::<LayerInstance>() * self.instance_capacity) as u64,
+            );
+        }
+        self.instances.write(queue, &instances);
+
+        self.shader_layer = layers
+            .iter()
+            .position(|layer| matches!(layer.source, Source::Shader { .. }));
+
+        layers.iter().enumerate().for_each(|(i, layer)| {
+            let size = layer.source.size();
+
+            if self
+                .textures
+                .get(i)
+                .is_none_or(|texture| texture.texture.size() != size)
+            {
+                let texture = self.create_texture(device, size);
+                if i < self.textures.len() {
+                    self.textures[i] = texture;
+                } else {
+                    self.textures.push(texture);
+                }
+            }
+
+            layer.source.write(queue, &self.textures[i].texture);
+        });
+    }
+
+    /// Texture of the layer the shader wallpaper draws into, to be drawn
+    /// before [`PerspectiveRenderer::render`].
+    pub fn shader_view(&self) -> Option<&wgpu::TextureView> {
+        self.shader_layer
+            .and_then(|i| self.textures.get(i))
+            .map(|texture| &texture.view)
+    }
+
+    fn create_texture(&self, device: &wgpu::Device, size: wgpu::Extent3d) -> LayerTexture {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Perspective Layer Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Perspective Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+
+        LayerTexture {
+            texture,
+            view,
+            bind_group,
+        }
+    }
+
+    pub fn render(&self, texture_view: &wgpu::TextureView, encoder: &mut wgpu::CommandEncoder) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Perspective Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: texture_view,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: self.depth.view(),
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            ..Default::default()
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(1, self.instances.slice(..));
+
+        self.layers.iter().enumerate().for_each(|(i, layer)| {
+            let mesh = if layer.pieces { &self.quad } else { &self.mesh };
+            render_pass.set_vertex_buffer(0, mesh.vertices.slice(..));
+            render_pass.set_index_buffer(mesh.indices.slice(..), wgpu::IndexFormat::Uint16);
+            render_pass.set_bind_group(0, &self.textures[i].bind_group, &[]);
+            render_pass.draw_indexed(0..mesh.indices.size(), 0, layer.instances.clone());
+        });
+    }
+}
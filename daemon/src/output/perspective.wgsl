@@ -0,0 +1,137 @@
+// Mirrors `perspective::LayerInstance::vertex` and `perspective::LayerInstance::project`.
+
+const PI: f32 = 3.14159265;
+
+struct VertexInput {
+    @location(0) uv: vec2<f32>,
+}
+
+struct InstanceInput {
+    @location(1) output: vec2<f32>,
+    @location(2) rect: vec4<f32>,
+    @location(3) uv: vec4<f32>,
+    @location(4) translate: vec2<f32>,
+    @location(5) scale: vec2<f32>,
+    @location(6) rotate: vec3<f32>,
+    @location(7) pivot: vec3<f32>,
+    @location(8) curl: vec2<f32>,
+    @location(9) opacity: f32,
+    @location(10) clip: vec4<f32>,
+    @location(11) radius: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) shade: f32,
+    @location(2) back: f32,
+    @location(3) opacity: f32,
+    @location(4) @interpolate(flat) clip: vec4<f32>,
+    @location(5) @interpolate(flat) radius: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var layer_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var layer_sampler: sampler;
+
+@vertex
+fn vs_main(vertex: VertexInput, layer: InstanceInput) -> VertexOutput {
+    var position = vec3<f32>(vertex.uv * layer.rect.zw, 0.0);
+    var shade = 1.0;
+    var back = 0.0;
+
+    // Roll everything right of the curl axis around a cylinder.
+    let curl_x = layer.curl.x;
+    let radius = layer.curl.y;
+    let distance = position.x - curl_x;
+    if radius > 0.0 && distance > 0.0 {
+        let theta = distance / radius;
+        if theta < PI {
+            position.x = curl_x + radius * sin(theta);
+            position.z = radius * (1.0 - cos(theta));
+            shade = 1.0 - 0.35 * sin(theta);
+        } else {
+            position.x = curl_x - (distance - PI * radius);
+            position.z = 2.0 * radius;
+        }
+        back = select(0.0, 1.0, theta > PI / 2.0);
+    }
+
+    let half_size = layer.rect.zw / 2.0;
+    position = vec3<f32>(
+        (position.xy - half_size) * layer.scale + half_size + layer.rect.xy,
+        position.z,
+    );
+
+    var q = position - layer.pivot;
+
+    let rz = layer.rotate.z;
+    q = vec3<f32>(q.x * cos(rz) - q.y * sin(rz), q.x * sin(rz) + q.y * cos(rz), q.z);
+
+    let rx = layer.rotate.x;
+    q = vec3<f32>(q.x, q.y * cos(rx) - q.z * sin(rx), q.y * sin(rx) + q.z * cos(rx));
+
+    let ry = layer.rotate.y;
+    q = vec3<f32>(q.x * cos(ry) + q.z * sin(ry), q.y, -q.x * sin(ry) + q.z * cos(ry));
+
+    position = q + layer.pivot + vec3<f32>(layer.translate, 0.0);
+
+    let center = layer.output / 2.0;
+    let distance_to_camera = 1.5 * max(layer.output.x, layer.output.y);
+    let w = (distance_to_camera - position.z) / distance_to_camera;
+    let ndc = (position.xy - center) / center;
+    let depth = 0.5 - position.z / (4.0 * distance_to_camera);
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc.x, -ndc.y, depth * w, w);
+    out.uv = layer.uv.xy + vertex.uv * layer.uv.zw;
+    out.shade = shade;
+    out.back = back;
+    out.opacity = layer.opacity;
+    out.clip = layer.clip;
+    out.radius = layer.radius;
+    return out;
+}
+
+// Mirrors `perspective::rounded_coverage`.
+fn rounded_coverage(point: vec2<f32>, bounds: vec4<f32>, radius: vec4<f32>) -> f32 {
+    if any(point < bounds.xy) || any(point >= bounds.zw) {
+        return 0.0;
+    }
+
+    let half_size = (bounds.zw - bounds.xy) / 2.0;
+    let center = (bounds.xy + bounds.zw) / 2.0;
+
+    var corner = radius.z;
+    if point.x < center.x && point.y < center.y {
+        corner = radius.x;
+    } else if point.y < center.y {
+        corner = radius.y;
+    } else if point.x < center.x {
+        corner = radius.w;
+    }
+    corner = clamp(corner, 0.0, 1.0) * min(half_size.x, half_size.y);
+    if corner <= 0.0 {
+        return 1.0;
+    }
+
+    let q = abs(point - center) - half_size + corner;
+    let distance = length(max(q, vec2<f32>(0.0))) + min(max(q.x, q.y), 0.0) - corner;
+    return clamp(0.5 - distance, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(layer_texture, layer_sampler, in.uv);
+    // The back of a curled page is paper, with the image showing through.
+    let rgb = mix(color.rgb, vec3<f32>(0.9), in.back * 0.6) * in.shade;
+    let alpha = color.a * in.opacity * rounded_coverage(in.clip_position.xy, in.clip, in.radius);
+
+    // Invisible layers must not occlude anything through the depth buffer.
+    if alpha <= 0.0 {
+        discard;
+    }
+
+    return vec4<f32>(rgb, alpha);
+}
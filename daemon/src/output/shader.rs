@@ -0,0 +1,286 @@
+use crate::clock::LocalTime;
+use anyhow::Context;
+use libmoxpaper::image_data::ImageData;
+use std::{
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Uniforms, bindings and entry points around the wallpaper's own code.
+const PRELUDE: &str = include_str!("shader.wgsl");
+
+/// A shader wallpaper: its WGSL code and how often it is drawn.
+#[derive(Clone)]
+pub struct ShaderSource {
+    path: Arc<Path>,
+    code: Arc<str>,
+    fps: Option<f32>,
+}
+
+impl ShaderSource {
+    pub fn load(shader: &libmoxpaper::Shader) -> anyhow::Result<Self> {
+        if shader.fps.is_some_and(|fps| !fps.is_finite() || fps <= 0.) {
+            return Err(anyhow::anyhow!("Frame rate has to be positive"));
+        }
+        if let Some(fps) = shader.fps {
+            Duration::try_from_secs_f32(1. / fps)
+                .map_err(|_| anyhow::anyhow!("Frame rate is too low"))?;
+        }
+
+        let code = std::fs::read_to_string(&shader.path)
+            .with_context(|| format!("{}", shader.path.display()))?;
+
+        Ok(Self {
+            path: shader.path.as_path().into(),
+            code: code.into(),
+            fps: shader.fps,
+        })
+    }
+
+    /// Time between frames, `None` to draw whenever the compositor asks the
+    /// output for one.
+    pub fn interval(&self) -> Option<Duration> {
+        self.fps.map(|fps| Duration::from_secs_f32(1. / fps))
+    }
+}
+
+/// Reads the code of `shader`, logging why it can't be shown.
+pub fn load(shader: &libmoxpaper::Shader) -> Option<ShaderSource> {
+    ShaderSource::load(shader)
+        .inspect_err(|e| log::error!("Failed to load shader: {e}"))
+        .ok()
+}
+
+/// Stands in for the image of a shader wallpaper until it draws its first
+/// frame.
+pub fn placeholder() -> ImageData {
+    ImageData::from(image::RgbaImage::from_pixel(
+        1,
+        1,
+        image::Rgba([0, 0, 0, 255]),
+    ))
+}
+
+/// Draws a [`ShaderSource`] over the whole output.
+pub struct ShaderRenderer {
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    uniforms: wgpu::Buffer,
+    started: Instant,
+    last_frame: Option<Instant>,
+    frame: u32,
+}
+
+impl ShaderRenderer {
+    /// Compiles `source`, failing on errors in the wallpaper's code.
+    /// `channel` is the texture the shader can sample.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format: wgpu::TextureFormat,
+        source: &ShaderSource,
+        channel: Option<&ImageData>,
+    ) -> anyhow::Result<Self> {
+        let scope = device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Wallpaper Shader"),
+            source: wgpu::ShaderSource::Wgsl(format!("{PRELUDE}\n{}", source.code).into()),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Wallpaper Shader Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Wallpaper Shader Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            ..Default::default()
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Wallpaper Shader Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("moxpaper_vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("moxpaper_fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview_mask: None,
+            cache: None,
+        });
+
+        if let Some(e) = pollster::block_on(scope.pop()) {
+            return Err(anyhow::anyhow!("{}: {e}", source.path.display()));
+        }
+
+        let black = placeholder();
+        let channel = channel.unwrap_or(&black);
+        let size = wgpu::Extent3d {
+            width: channel.width(),
+            height: channel.height(),
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Wallpaper Shader Channel"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            // Sampled as stored, like the colors the shader returns.
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            texture.as_image_copy(),
+            channel.data(),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * channel.width()),
+                rows_per_image: Some(channel.height()),
+            },
+            size,
+        );
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Wallpaper Shader Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Wallpaper Shader Uniforms"),
+            size: 48,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Wallpaper Shader Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniforms.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(
+                        &texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        Ok(Self {
+            pipeline,
+            bind_group,
+            uniforms,
+            started: Instant::now(),
+            last_frame: None,
+            frame: 0,
+        })
+    }
+
+    /// Updates the uniforms for a frame drawn now.
+    pub fn prepare(&mut self, queue: &wgpu::Queue, width: u32, height: u32) {
+        let now = Instant::now();
+        let time = now.duration_since(self.started).as_secs_f32();
+        let time_delta = self
+            .last_frame
+            .map(|last| now.duration_since(last).as_secs_f32())
+            .unwrap_or(0.);
+        let date = LocalTime::now();
+
+        // Laid out like `Uniforms` in shader.wgsl.
+        let floats = [
+            width as f32,
+            height as f32,
+            time,
+            time_delta,
+            date.year as f32,
+            date.month as f32,
+            date.day as f32,
+            date.seconds as f32,
+        ];
+        let mut bytes = floats
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect::<Vec<_>>();
+        bytes.extend_from_slice(&self.frame.to_ne_bytes());
+        bytes.resize(48, 0);
+        queue.write_buffer(&self.uniforms, 0, &bytes);
+
+        self.last_frame = Some(now);
+        self.frame = self.frame.wrapping_add(1);
+    }
+
+    pub fn render(&self, texture_view: &wgpu::TextureView, encoder: &mut wgpu::CommandEncoder) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Wallpaper Shader Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: texture_view,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,47 @@
+// Put in front of the code of shader wallpapers, which define
+//
+//     fn main_image(frag_coord: vec2<f32>) -> vec4<f32>
+//
+// returning the color of the pixel centered at `frag_coord`, in pixels from
+// the top left corner. Colors are stored as returned, like on Shadertoy, and
+// alpha is ignored.
+
+struct Uniforms {
+    // Output size in pixels
+    resolution: vec2<f32>,
+    // Seconds since the wallpaper was set
+    time: f32,
+    // Seconds since the previous frame
+    time_delta: f32,
+    // Year, month (1-12), day of the month and seconds since midnight, in
+    // local time
+    date: vec4<f32>,
+    // Frames drawn before this one
+    frame: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+// The wallpaper shown before this one, black if there was none
+@group(0) @binding(1)
+var channel0: texture_2d<f32>;
+@group(0) @binding(2)
+var channel0_sampler: sampler;
+
+// A single triangle covering the whole output.
+@vertex
+fn moxpaper_vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+@fragment
+fn moxpaper_fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let color = clamp(main_image(position.xy).rgb, vec3<f32>(0.0), vec3<f32>(1.0));
+
+    // The output encodes to sRGB, decode first so that it stores the color
+    // as is.
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return vec4<f32>(select(high, low, color <= vec3<f32>(0.04045)), 1.0);
+}
@@ -1,10 +1,17 @@
-use crate::config;
+use super::{
+    mask::MaskRenderer,
+    perspective::{Layer, PerspectiveRenderer},
+    shader::ShaderRenderer,
+};
+use crate::{animation::MaskFrame, config};
+use image::RgbaImage;
+use libmoxpaper::image_data::ImageData;
 use moxui::{
-    texture_renderer::{self},
+    texture_renderer::{self, TextureArea},
     viewport,
 };
 use raw_window_handle::{RawDisplayHandle, RawWindowHandle, WaylandWindowHandle};
-use std::ptr::NonNull;
+use std::{ptr::NonNull, sync::mpsc};
 use wayland_client::{Proxy, protocol::wl_surface};
 
 pub struct WgpuSurface {
@@ -13,9 +20,72 @@ pub struct WgpuSurface {
     pub queue: wgpu::Queue,
     pub device: wgpu::Device,
     pub texture_renderer: texture_renderer::TextureRenderer,
+    pub perspective_renderer: PerspectiveRenderer,
+    pub mask_renderer: MaskRenderer,
+    /// Draws the shader wallpaper, if one is shown
+    pub shader_renderer: Option<ShaderRenderer>,
     pub viewport: viewport::Viewport,
 }
 
+/// What a frame consists of, picking the renderer that draws it.
+pub enum Scene<'a> {
+    Flat(Vec<TextureArea>),
+    Perspective(Vec<Layer<'a>>),
+    Masked {
+        previous: &'a ImageData,
+        target: Source<'a>,
+        mask: &'a ImageData,
+        frame: MaskFrame,
+    },
+    /// The next frame of the shader wallpaper
+    Shader,
+}
+
+/// Where the pixels of a layer come from.
+#[derive(Clone, Copy)]
+pub enum Source<'a> {
+    Image(&'a ImageData),
+    /// The shader wallpaper, drawn straight into the layer's texture at the
+    /// size of the output
+    Shader {
+        width: u32,
+        height: u32,
+    },
+}
+
+impl Source<'_> {
+    pub fn size(&self) -> wgpu::Extent3d {
+        let (width, height) = match self {
+            Self::Image(image) => (image.width(), image.height()),
+            Self::Shader { width, height } => (*width, *height),
+        };
+
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        }
+    }
+
+    /// Uploads the image into `texture`, the shader draws its own.
+    pub fn write(&self, queue: &wgpu::Queue, texture: &wgpu::Texture) {
+        let Self::Image(image) = self else {
+            return;
+        };
+
+        queue.write_texture(
+            texture.as_image_copy(),
+            image.data(),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * image.width()),
+                rows_per_image: Some(image.height()),
+            },
+            self.size(),
+        );
+    }
+}
+
 impl WgpuSurface {
     pub fn new(
         surface: &wl_surface::WlSurface,
@@ -83,8 +153,14 @@ impl WgpuSurface {
             2,
         );
 
+        let perspective_renderer = PerspectiveRenderer::new(&device, config.format, width, height);
+        let mask_renderer = MaskRenderer::new(&device, config.format);
+
         Ok(Self {
             texture_renderer,
+            perspective_renderer,
+            mask_renderer,
+            shader_renderer: None,
             surface: wgpu_surface,
             config,
             queue,
@@ -92,4 +168,140 @@ impl WgpuSurface {
             viewport,
         })
     }
+
+    /// Records the draw commands of `scene` into `encoder`.
+    pub fn draw(
+        &mut self,
+        scene: &Scene,
+        texture_view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        match scene {
+            Scene::Flat(textures) => {
+                self.texture_renderer
+                    .prepare(&self.device, &self.queue, textures);
+                self.texture_renderer
+                    .render(texture_view, encoder, &self.viewport);
+            }
+            Scene::Perspective(layers) => {
+                self.perspective_renderer.prepare(
+                    &self.device,
+                    &self.queue,
+                    layers,
+                    self.config.width,
+                    self.config.height,
+                );
+                if let Some(view) = self.perspective_renderer.shader_view().cloned() {
+                    self.draw_shader(&view, encoder);
+                }
+                self.perspective_renderer.render(texture_view, encoder);
+            }
+            Scene::Masked {
+                previous,
+                target,
+                mask,
+                frame,
+            } => {
+                self.mask_renderer.prepare(
+                    &self.device,
+                    &self.queue,
+                    previous,
+                    *target,
+                    mask,
+                    frame,
+                );
+                if let Some(view) = self.mask_renderer.shader_view() {
+                    self.draw_shader(&view, encoder);
+                }
+                self.mask_renderer.render(texture_view, encoder);
+            }
+            Scene::Shader => self.draw_shader(texture_view, encoder),
+        }
+    }
+
+    /// Records the next frame of the shader wallpaper into `encoder`.
+    fn draw_shader(
+        &mut self,
+        texture_view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        if let Some(shader_renderer) = self.shader_renderer.as_mut() {
+            shader_renderer.prepare(&self.queue, self.config.width, self.config.height);
+            shader_renderer.render(texture_view, encoder);
+        }
+    }
+
+    /// Renders `scene` into an offscreen texture and reads the result back
+    /// into memory.
+    pub fn capture(&mut self, scene: &Scene) -> anyhow::Result<ImageData> {
+        let (width, height) = (self.config.width, self.config.height);
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Capture Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let unpadded_bytes_per_row = width * 4;
+        let padded_bytes_per_row = unpadded_bytes_per_row
+            .div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Capture Buffer"),
+            size: (padded_bytes_per_row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+
+        self.draw(scene, &texture_view, &mut encoder);
+
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            size,
+        );
+
+        self.queue.submit(Some(encoder.finish()));
+
+        let (tx, rx) = mpsc::channel();
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, move |res| _ = tx.send(res));
+        self.device.poll(wgpu::PollType::wait_indefinitely())?;
+        rx.recv()??;
+
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        {
+            let data = slice.get_mapped_range();
+            data.chunks(padded_bytes_per_row as usize).for_each(|row| {
+                pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+            });
+        }
+        buffer.unmap();
+
+        let image = RgbaImage::from_raw(width, height, pixels)
+            .ok_or(anyhow::anyhow!("Captured frame has an unexpected size"))?;
+
+        Ok(ImageData::from(image))
+    }
 }
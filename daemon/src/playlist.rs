@@ -0,0 +1,442 @@
+use crate::{
+    Moxpaper, animated,
+    assets::{AssetData, AssetsManager, FallbackImage},
+    config::{self, Config, PlaylistCursor, PlaylistOrder, PlaylistSource, Wallpaper},
+};
+use anyhow::Context;
+use calloop::{
+    LoopHandle, RegistrationToken,
+    timer::{TimeoutAction, Timer},
+};
+use libmoxpaper::{Data, Playback, PlaylistAction, WallpaperData};
+use rand::seq::SliceRandom;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+/// The playlists of the config and where the outputs showing them are.
+pub struct Playlists {
+    playlists: Vec<Playlist>,
+    handle: LoopHandle<'static, Moxpaper>,
+}
+
+struct Playlist {
+    /// Output the playlist is shown on, "any" for every output without a
+    /// wallpaper or playlist of its own
+    output: Arc<str>,
+    config: config::Playlist,
+    cursors: Vec<Cursor>,
+}
+
+/// Position in a playlist, of one output or of all outputs when shared.
+struct Cursor {
+    /// Output going through the playlist on its own
+    output: Option<Arc<str>>,
+    entries: Vec<PathBuf>,
+    position: usize,
+    paused: bool,
+    timer: Option<RegistrationToken>,
+}
+
+impl Cursor {
+    fn new(output: Option<Arc<str>>, config: &config::Playlist) -> Self {
+        let mut cursor = Self {
+            output,
+            entries: Vec::new(),
+            position: 0,
+            paused: false,
+            timer: None,
+        };
+        cursor.rescan(config);
+
+        cursor
+    }
+
+    /// Looks for the images of the playlist again, so that passes pick up
+    /// added and removed files.
+    fn rescan(&mut self, config: &config::Playlist) {
+        let previous = self.entries.get(self.position).cloned();
+
+        self.entries = entries(config)
+            .inspect_err(|e| log::error!("Failed to read playlist: {e}"))
+            .unwrap_or_default();
+        self.position = 0;
+
+        if config.order == PlaylistOrder::Shuffle {
+            self.entries.shuffle(&mut rand::rng());
+            // Don't show the same wallpaper twice in a row across passes.
+            if self.entries.len() > 1 && self.entries.first() == previous.as_ref() {
+                let last = self.entries.len() - 1;
+                self.entries.swap(0, last);
+            }
+        }
+    }
+
+    fn current(&self) -> Option<&Path> {
+        self.entries.get(self.position).map(PathBuf::as_path)
+    }
+
+    fn step(&mut self, config: &config::Playlist, forward: bool) -> Option<&Path> {
+        match forward {
+            true if self.position + 1 < self.entries.len() => self.position += 1,
+            true => self.rescan(config),
+            false if self.position > 0 => self.position -= 1,
+            false => self.position = self.entries.len().saturating_sub(1),
+        }
+
+        self.current()
+    }
+
+    fn stop(&mut self, handle: &LoopHandle<'static, Moxpaper>) {
+        if let Some(token) = self.timer.take() {
+            handle.remove(token);
+        }
+    }
+}
+
+impl Playlists {
+    /// Loads the shared playlists of `config` and schedules their next
+    /// wallpaper, outputs going through a playlist on their own start in
+    /// [`Playlists::add_output`].
+    pub fn new(
+        config: &Config,
+        assets: &mut AssetsManager,
+        handle: LoopHandle<'static, Moxpaper>,
+    ) -> Self {
+        let mut playlists = Self {
+            playlists: config
+                .playlist
+                .iter()
+                .map(|(output, config)| Playlist {
+                    output: Arc::clone(output),
+                    config: config.clone(),
+                    cursors: Vec::new(),
+                })
+                .collect(),
+            handle,
+        };
+
+        for index in 0..playlists.playlists.len() {
+            playlists.start(index, assets);
+        }
+
+        playlists
+    }
+
+    /// Replaces the playlists whose config changed, the others go on where
+    /// they are.
+    pub fn reload(&mut self, config: &Config, assets: &mut AssetsManager) {
+        let (mut kept, removed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.playlists)
+            .into_iter()
+            .partition(|playlist| config.playlist.get(&playlist.output) == Some(&playlist.config));
+
+        // Outputs going through "any" on their own that got a wallpaper or
+        // playlist of their own leave it.
+        let own = |name: &Arc<str>| {
+            config.wallpaper.contains_key(name)
+                || config.playlist.contains_key(name)
+                || config.schedule.contains_key(name)
+                || config.sun.contains_key(name)
+        };
+        let mut stopped = removed
+            .into_iter()
+            .flat_map(|playlist| playlist.cursors)
+            .collect::<Vec<_>>();
+        kept.iter_mut().for_each(|playlist| {
+            let (left, cursors): (Vec<_>, Vec<_>) = std::mem::take(&mut playlist.cursors)
+                .into_iter()
+                .partition(|cursor| cursor.output.as_ref().is_some_and(own));
+            playlist.cursors = cursors;
+            stopped.extend(left);
+        });
+        stopped.iter_mut().for_each(|cursor| {
+            cursor.stop(&self.handle);
+            if let Some(output) = &cursor.output {
+                assets.remove_asset(output);
+            }
+        });
+
+        self.playlists = kept;
+        for (output, config) in &config.playlist {
+            if self
+                .playlists
+                .iter()
+                .any(|playlist| playlist.output == *output)
+            {
+                continue;
+            }
+
+            self.playlists.push(Playlist {
+                output: Arc::clone(output),
+                config: config.clone(),
+                cursors: Vec::new(),
+            });
+            self.start(self.playlists.len() - 1, assets);
+        }
+    }
+
+    /// Shows and schedules a playlist that all its outputs go through
+    /// together.
+    fn start(&mut self, index: usize, assets: &mut AssetsManager) {
+        let playlist = &mut self.playlists[index];
+        if &*playlist.output == "any" && playlist.config.cursor == PlaylistCursor::PerOutput {
+            return;
+        }
+
+        playlist.cursors.push(Cursor::new(None, &playlist.config));
+        self.show(index, 0, assets);
+        self.schedule(index, 0);
+    }
+
+    /// Starts the output on the "any" playlist if it goes through it on its
+    /// own and isn't yet.
+    pub fn add_output(&mut self, name: &Arc<str>, config: &Config, assets: &mut AssetsManager) {
+        if config.wallpaper.contains_key(name)
+            || config.playlist.contains_key(name)
+            || config.schedule.contains_key(name)
+            || config.sun.contains_key(name)
+        {
+            return;
+        }
+
+        let Some(index) = self.playlists.iter().position(|playlist| {
+            &*playlist.output == "any" && playlist.config.cursor == PlaylistCursor::PerOutput
+        }) else {
+            return;
+        };
+
+        let playlist = &mut self.playlists[index];
+        if playlist
+            .cursors
+            .iter()
+            .any(|cursor| cursor.output.as_ref() == Some(name))
+        {
+            return;
+        }
+
+        playlist
+            .cursors
+            .push(Cursor::new(Some(Arc::clone(name)), &playlist.config));
+        let cursor = playlist.cursors.len() - 1;
+        self.show(index, cursor, assets);
+        self.schedule(index, cursor);
+    }
+
+    /// Makes the wallpaper the cursor is on the one of its outputs.
+    fn show(&self, index: usize, cursor: usize, assets: &mut AssetsManager) {
+        let playlist = &self.playlists[index];
+        let cursor = &playlist.cursors[cursor];
+        let Some(path) = cursor.current() else {
+            return;
+        };
+
+        let Some(asset) = AssetData::load(&Wallpaper {
+            path: path.into(),
+            resize: playlist.config.resize,
+            transition: playlist.config.transition.clone(),
+            repeat: playlist.config.repeat,
+            fps: None,
+            playback: Playback::default(),
+            frame_offset: 0,
+        }) else {
+            return;
+        };
+
+        match (&cursor.output, &*playlist.output) {
+            (Some(output), _) => assets.insert_asset(Arc::clone(output), asset),
+            (None, "any") => assets.set_fallback(FallbackImage::Image(asset)),
+            (None, _) => assets.insert_asset(Arc::clone(&playlist.output), asset),
+        }
+    }
+
+    /// Restarts the timer moving the cursor on, unless it's paused.
+    fn schedule(&mut self, index: usize, cursor: usize) {
+        let playlist = &mut self.playlists[index];
+        let interval = Duration::from_secs(playlist.config.interval.max(1));
+        let cursor = &mut playlist.cursors[cursor];
+        cursor.stop(&self.handle);
+        if cursor.paused || cursor.entries.len() < 2 {
+            return;
+        }
+
+        let (output, cursor_output) = (Arc::clone(&playlist.output), cursor.output.clone());
+        cursor.timer = self
+            .handle
+            .insert_source(Timer::from_duration(interval), move |_, _, state| {
+                let found = state.playlists.find(&output, cursor_output.as_ref());
+                let Some((index, cursor)) = found else {
+                    return TimeoutAction::Drop;
+                };
+
+                // The timer is replaced rather than rescheduled, it's gone
+                // once this returns.
+                state.playlists.playlists[index].cursors[cursor].timer = None;
+                state.step_playlist(index, cursor, true);
+                TimeoutAction::Drop
+            })
+            .inspect_err(|e| log::error!("Failed to schedule playlist: {e}"))
+            .ok();
+    }
+
+    /// Indices of the playlist and the cursor a timer moves.
+    fn find(&self, output: &str, cursor: Option<&Arc<str>>) -> Option<(usize, usize)> {
+        let index = self
+            .playlists
+            .iter()
+            .position(|playlist| &*playlist.output == output)?;
+        let cursor = self.playlists[index]
+            .cursors
+            .iter()
+            .position(|other| other.output.as_ref() == cursor)?;
+
+        Some((index, cursor))
+    }
+
+    /// Cursors moving the wallpaper of `output`, or all of them if `None`.
+    fn cursors(&self, output: Option<&str>) -> Vec<(usize, usize)> {
+        self.playlists
+            .iter()
+            .enumerate()
+            .flat_map(|(index, playlist)| {
+                playlist
+                    .cursors
+                    .iter()
+                    .enumerate()
+                    .filter(move |(_, cursor)| {
+                        let Some(output) = output else {
+                            return true;
+                        };
+
+                        match (&cursor.output, &*playlist.output) {
+                            (Some(name), _) => &**name == output,
+                            (None, "any") => !self
+                                .playlists
+                                .iter()
+                                .any(|playlist| &*playlist.output == output),
+                            (None, name) => name == output,
+                        }
+                    })
+                    .map(move |(cursor, _)| (index, cursor))
+            })
+            .collect()
+    }
+}
+
+impl Moxpaper {
+    /// Moves a cursor to the next or previous wallpaper and shows it.
+    fn step_playlist(&mut self, index: usize, cursor: usize, forward: bool) {
+        let playlist = &mut self.playlists.playlists[index];
+        if playlist.cursors[cursor]
+            .step(&playlist.config, forward)
+            .is_none()
+        {
+            log::warn!("Playlist of {} is empty", playlist.output);
+        }
+
+        let playlist = &self.playlists.playlists[index];
+        if let Some(path) = playlist.cursors[cursor].current() {
+            let outputs = match (&playlist.cursors[cursor].output, &*playlist.output) {
+                (Some(output), _) => vec![Arc::clone(output)],
+                (None, "any") => Vec::new(),
+                (None, _) => vec![Arc::clone(&playlist.output)],
+            };
+            let wallpaper = WallpaperData {
+                outputs,
+                data: Data::Path(path.into()),
+                resize: playlist.config.resize,
+                transition: playlist.config.transition.clone(),
+                repeat: playlist.config.repeat,
+            };
+            self.record_history(&wallpaper);
+        }
+
+        self.playlists.show(index, cursor, &mut self.assets);
+        self.playlists.schedule(index, cursor);
+
+        let playlist = &self.playlists.playlists[index];
+        let output = match &playlist.cursors[cursor].output {
+            Some(output) => Arc::clone(output),
+            None => Arc::clone(&playlist.output),
+        };
+        self.render_configured(&output);
+    }
+
+    /// Handles a playlist command of a client.
+    pub fn control_playlists(&mut self, action: PlaylistAction, outputs: &[Arc<str>]) {
+        let mut cursors = match outputs {
+            [] => self.playlists.cursors(None),
+            outputs => outputs
+                .iter()
+                .flat_map(|output| self.playlists.cursors(Some(&**output)))
+                .collect(),
+        };
+        // Outputs sharing a cursor move it once.
+        cursors.sort_unstable();
+        cursors.dedup();
+
+        if cursors.is_empty() {
+            log::warn!("No playlist is shown on the selected outputs");
+        }
+
+        cursors
+            .into_iter()
+            .for_each(|(index, cursor)| match action {
+                PlaylistAction::Next => self.step_playlist(index, cursor, true),
+                PlaylistAction::Prev => self.step_playlist(index, cursor, false),
+                PlaylistAction::Pause => {
+                    let cursor = &mut self.playlists.playlists[index].cursors[cursor];
+                    cursor.paused = true;
+                    cursor.stop(&self.playlists.handle);
+                }
+                PlaylistAction::Resume => {
+                    self.playlists.playlists[index].cursors[cursor].paused = false;
+                    self.playlists.schedule(index, cursor);
+                }
+            });
+    }
+}
+
+/// Images of a playlist, in natural order.
+pub fn entries(config: &config::Playlist) -> anyhow::Result<Vec<PathBuf>> {
+    let source = match &config.source {
+        PlaylistSource::Paths(paths) => return Ok(paths.clone()),
+        PlaylistSource::Pattern(source) => source,
+    };
+
+    let files = animated::Files::new(source)?;
+
+    let mut entries = Vec::new();
+    let mut dirs = vec![files.dir.to_path_buf()];
+    // Symlinks can lead back into a directory already listed.
+    let mut visited = HashSet::new();
+    while let Some(dir) = dirs.pop() {
+        if let Ok(canonical) = dir.canonicalize()
+            && !visited.insert(canonical)
+        {
+            continue;
+        }
+
+        for path in std::fs::read_dir(&dir)
+            .with_context(|| format!("{}", dir.display()))?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+        {
+            if path.is_dir() {
+                if config.recursive {
+                    dirs.push(path);
+                }
+                continue;
+            }
+
+            if files.matches(&path) {
+                entries.push(path);
+            }
+        }
+    }
+
+    entries.sort_by(|a, b| animated::natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+    Ok(entries)
+}
@@ -0,0 +1,570 @@
+use crate::{
+    animation::{Extents, Filters, FrameData, Frames, state::TransitionState},
+    assets::AssetData,
+    config::Config,
+    output::{
+        Stage, mask,
+        perspective::{self, LayerInstance},
+    },
+};
+use anyhow::Context;
+use image::{
+    Delay, Frame, Rgba, RgbaImage,
+    codecs::gif::{GifEncoder, Repeat},
+};
+use libmoxpaper::{ResizeStrategy, Transition, TransitionType, image_data::ImageData};
+use rand::{SeedableRng, rngs::StdRng};
+use std::{fs::File, io::BufWriter, path::Path, time::Duration};
+
+/// Frame rate used when neither the transition nor the config set one,
+/// in place of the compositor's vsync.
+const DEFAULT_FPS: u64 = 60;
+
+pub struct Preview<'a> {
+    pub from: &'a Path,
+    pub to: &'a Path,
+    pub width: u32,
+    pub height: u32,
+    pub resize: ResizeStrategy,
+    pub transition: Transition,
+    /// A `.gif` file, or a directory that receives one PNG per frame
+    pub output: &'a Path,
+    /// Seeds the random choices of the transition, so that a preview
+    /// renders the same every time
+    pub seed: u64,
+}
+
+/// Renders every frame of a transition between two images without a
+/// compositor or GPU.
+pub fn render(preview: Preview, config: &Config) -> anyhow::Result<()> {
+    let load = |path: &Path| -> anyhow::Result<AssetData> {
+        let image = image::open(path).with_context(|| format!("{}", path.display()))?;
+        Ok(AssetData::new(
+            ImageData::from(image),
+            preview.resize,
+            preview.transition.clone(),
+        ))
+    };
+
+    let mut previous = load(preview.from)?.into_resized(preview.width, preview.height)?;
+    let stages = Stage::chain(load(preview.to)?, preview.width, preview.height, config)?;
+
+    let extents = Extents {
+        x: 0.,
+        y: 0.,
+        width: preview.width as f32,
+        height: preview.height as f32,
+    };
+    let mut sink = Sink::new(preview.output)?;
+    let mut rng = StdRng::seed_from_u64(preview.seed);
+    let mut last_pick = None;
+
+    for stage in stages {
+        let fps = stage.config.fps.unwrap_or(DEFAULT_FPS).max(1);
+        let duration = stage.config.duration;
+        let mut state = TransitionState::new(stage.config, extents, last_pick.as_ref(), &mut rng);
+        let mask = match (state.transition_type(), stage.mask) {
+            (TransitionType::Mask(_), Some(pending)) => Some(pending.wait()?),
+            (TransitionType::Mask(mask), None) => {
+                Some(mask::generate(&mask.source, preview.width, preview.height)?)
+            }
+            _ => None,
+        };
+
+        let frame_count = (duration * fps as u128).div_ceil(1000) as u64 + 1;
+        for frame in 0..frame_count {
+            state.advance(Duration::from_millis(frame * 1000 / fps));
+            let frame = compose(
+                preview.width,
+                preview.height,
+                &previous,
+                &stage.image,
+                mask.as_ref(),
+                &state.frames(),
+            );
+            sink.push(frame, fps)?;
+        }
+
+        last_pick = Some(state.transition_type().clone());
+        previous = stage.image;
+    }
+
+    log::info!(
+        "Rendered {} frames to {}",
+        sink.frames,
+        preview.output.display()
+    );
+
+    Ok(())
+}
+
+/// Writes frames as they are rendered, so that long previews don't have to
+/// fit in memory.
+struct Sink<'a> {
+    encoder: Option<GifEncoder<BufWriter<File>>>,
+    directory: &'a Path,
+    frames: usize,
+}
+
+impl<'a> Sink<'a> {
+    fn new(output: &'a Path) -> anyhow::Result<Self> {
+        let is_gif = output
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("gif"));
+
+        let encoder = if is_gif {
+            let file = File::create(output).with_context(|| format!("{}", output.display()))?;
+            let mut encoder = GifEncoder::new(BufWriter::new(file));
+            encoder.set_repeat(Repeat::Infinite)?;
+            Some(encoder)
+        } else {
+            std::fs::create_dir_all(output).with_context(|| format!("{}", output.display()))?;
+            None
+        };
+
+        Ok(Self {
+            encoder,
+            directory: output,
+            frames: 0,
+        })
+    }
+
+    fn push(&mut self, frame: RgbaImage, fps: u64) -> anyhow::Result<()> {
+        match self.encoder.as_mut() {
+            Some(encoder) => {
+                let delay = Delay::from_numer_denom_ms(1000, fps as u32);
+                encoder.encode_frame(Frame::from_parts(frame, 0, 0, delay))?;
+            }
+            None => frame.save(self.directory.join(format!("frame_{:04}.png", self.frames)))?,
+        }
+
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+/// CPU counterpart of `Output::render`: draws the outgoing and incoming
+/// layers in the same order and with the same `FrameData` semantics as the
+/// texture renderer.
+pub fn compose(
+    width: u32,
+    height: u32,
+    previous: &ImageData,
+    target: &ImageData,
+    mask: Option<&ImageData>,
+    frames: &Frames,
+) -> RgbaImage {
+    if let (Some(mask), Some(frame)) = (mask, frames.mask.as_ref()) {
+        return RgbaImage::from_fn(width, height, |x, y| {
+            let (u, v) = (
+                (x as f32 + 0.5) / width as f32,
+                (y as f32 + 0.5) / height as f32,
+            );
+            let amount = mask::blend(image_texel(mask, u, v)[0], frame);
+            let (from, to) = (image_texel(previous, u, v), image_texel(target, u, v));
+            Rgba(
+                [0, 1, 2, 3].map(|i| ((from[i] + (to[i] - from[i]) * amount) * 255.).round() as u8),
+            )
+        });
+    }
+
+    let mut canvas = RgbaImage::from_pixel(width, height, Rgba([0, 0, 0, 255]));
+
+    let mut layers = [
+        (previous, &frames.outgoing, &frames.outgoing_pieces),
+        (target, &frames.incoming, &frames.incoming_pieces),
+    ];
+    if frames.outgoing_on_top {
+        layers.reverse();
+    }
+
+    if layers
+        .iter()
+        .all(|(_, frame_data, pieces)| pieces.is_empty() && frame_data.is_flat())
+    {
+        layers
+            .into_iter()
+            .for_each(|(image, frame_data, _)| draw_layer(&mut canvas, image, frame_data));
+    } else {
+        let (mesh, quad) = (perspective::mesh(), perspective::quad());
+        let mut depth = vec![1.0; (width * height) as usize];
+        let (width, height) = (width as f32, height as f32);
+        layers.into_iter().for_each(|(image, frame_data, pieces)| {
+            if pieces.is_empty() {
+                let instance = LayerInstance::new(frame_data, width, height);
+                draw_perspective_layer(&mut canvas, &mut depth, &mesh, image, &instance);
+            } else {
+                pieces.iter().for_each(|piece| {
+                    let instance = LayerInstance::piece(piece, width, height);
+                    draw_perspective_layer(&mut canvas, &mut depth, &quad, image, &instance);
+                });
+            }
+        });
+    }
+
+    canvas
+}
+
+/// CPU counterpart of the perspective renderer: rasterizes the mesh of a
+/// layer or piece with a depth buffer.
+fn draw_perspective_layer(
+    canvas: &mut RgbaImage,
+    depth: &mut [f32],
+    (uvs, indices): &(Vec<[f32; 2]>, Vec<u16>),
+    image: &ImageData,
+    instance: &LayerInstance,
+) {
+    let (width, height) = (canvas.width(), canvas.height());
+    let opacity = instance.opacity().clamp(0., 1.);
+    if opacity <= 0. {
+        return;
+    }
+
+    let vertices = uvs
+        .iter()
+        .map(|uv| {
+            let vertex = instance.vertex(*uv);
+            (instance.project(vertex.position), vertex.uv, vertex)
+        })
+        .collect::<Vec<_>>();
+
+    indices.chunks_exact(3).for_each(|triangle| {
+        let [a, b, c] = [0, 1, 2].map(|i| &vertices[triangle[i] as usize]);
+        let (pa, pb, pc) = (a.0, b.0, c.0);
+
+        let area = (pb[0] - pa[0]) * (pc[1] - pa[1]) - (pb[1] - pa[1]) * (pc[0] - pa[0]);
+        if area.abs() < f32::EPSILON {
+            return;
+        }
+
+        let min_x = pa[0].min(pb[0]).min(pc[0]).floor().max(0.) as u32;
+        let min_y = pa[1].min(pb[1]).min(pc[1]).floor().max(0.) as u32;
+        let max_x = (pa[0].max(pb[0]).max(pc[0]).ceil().max(0.) as u32).min(width);
+        let max_y = (pa[1].max(pb[1]).max(pc[1]).ceil().max(0.) as u32).min(height);
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let point = [x as f32 + 0.5, y as f32 + 0.5];
+                let edge = |p0: [f32; 3], p1: [f32; 3]| {
+                    ((p1[0] - p0[0]) * (point[1] - p0[1]) - (p1[1] - p0[1]) * (point[0] - p0[0]))
+                        / area
+                };
+                let weights = [edge(pb, pc), edge(pc, pa), edge(pa, pb)];
+                // Pixels on the edge shared by two triangles must not fall
+                // through the gap left by rounding.
+                if weights.iter().any(|weight| *weight < -1e-4) {
+                    continue;
+                }
+
+                let interpolate = |values: [f32; 3]| {
+                    values[0] * weights[0] + values[1] * weights[1] + values[2] * weights[2]
+                };
+
+                let index = (y * width + x) as usize;
+                let z = interpolate([pa[2], pb[2], pc[2]]);
+                // Later layers win ties, like `LessEqual` on the GPU, which
+                // must survive the rounding of the weights.
+                if z > depth[index] + 1e-6 {
+                    continue;
+                }
+
+                let u = interpolate([a.1[0], b.1[0], c.1[0]]);
+                let v = interpolate([a.1[1], b.1[1], c.1[1]]);
+                let shade = interpolate([a.2.shade, b.2.shade, c.2.shade]);
+                let back = interpolate([a.2.back, b.2.back, c.2.back]);
+
+                let texel = image_texel(image, u, v);
+                let alpha = texel[3] * opacity * instance.coverage(point);
+                if alpha <= 0. {
+                    continue;
+                }
+                depth[index] = z;
+
+                let rgb = [0, 1, 2].map(|i| (texel[i] + (0.9 - texel[i]) * back * 0.6) * shade);
+                let dst = canvas.get_pixel_mut(x, y);
+                let blend = |src: f32, dst: u8| {
+                    ((src * alpha + dst as f32 / 255. * (1. - alpha)) * 255.).round() as u8
+                };
+                *dst = Rgba([
+                    blend(rgb[0], dst[0]),
+                    blend(rgb[1], dst[1]),
+                    blend(rgb[2], dst[2]),
+                    blend(1., dst[3]),
+                ]);
+            }
+        }
+    });
+}
+
+/// Nearest texel at normalized coordinates, as RGBA in `0..=1`.
+fn image_texel(image: &ImageData, u: f32, v: f32) -> [f32; 4] {
+    let x = ((u * image.width() as f32) as u32).min(image.width().saturating_sub(1));
+    let y = ((v * image.height() as f32) as u32).min(image.height().saturating_sub(1));
+    let offset = ((y * image.width() + x) * 4) as usize;
+
+    image
+        .data()
+        .get(offset..offset + 4)
+        .map(|texel| [0, 1, 2, 3].map(|i| texel[i] as f32 / 255.))
+        .unwrap_or([0.; 4])
+}
+
+fn draw_layer(canvas: &mut RgbaImage, image: &ImageData, frame_data: &FrameData) {
+    let (width, height) = (canvas.width() as f32, canvas.height() as f32);
+    let Some(texture) = RgbaImage::from_raw(image.width(), image.height(), image.data().to_vec())
+    else {
+        return;
+    };
+    let texture = match frame_data.filters.blur {
+        0 => texture,
+        blur => image::imageops::fast_blur(&texture, blur as f32),
+    };
+
+    let clip = &frame_data.clip;
+    let bounds = [
+        ((clip.left * width) as u32).min(canvas.width()),
+        ((clip.top * height) as u32).min(canvas.height()),
+        ((clip.right * width) as u32).min(canvas.width()),
+        ((clip.bottom * height) as u32).min(canvas.height()),
+    ];
+    if bounds[0] >= bounds[2] || bounds[1] >= bounds[3] {
+        return;
+    }
+
+    let transforms = &frame_data.transforms;
+    let (texture_width, texture_height) = (texture.width() as f32, texture.height() as f32);
+    let center = [
+        transforms.translate[0] * width + texture_width / 2.,
+        transforms.translate[1] * height + texture_height / 2.,
+    ];
+    let (sin, cos) = (-frame_data.rotation.to_radians()).sin_cos();
+    let skew = [
+        transforms.skew_x.to_radians().tan(),
+        transforms.skew_y.to_radians().tan(),
+    ];
+
+    for y in bounds[1]..bounds[3] {
+        for x in bounds[0]..bounds[2] {
+            let point = [x as f32 + 0.5, y as f32 + 0.5];
+            let coverage = perspective::rounded_coverage(
+                point,
+                bounds.map(|bound| bound as f32),
+                &frame_data.radius,
+            );
+            if coverage <= 0. {
+                continue;
+            }
+
+            // Map the output pixel back into texture space: undo rotation,
+            // skew and scale around the layer center.
+            let dx = point[0] - center[0];
+            let dy = point[1] - center[1];
+            let (dx, dy) = (dx * cos - dy * sin, dx * sin + dy * cos);
+            let (dx, dy) = (dx - skew[0] * dy, dy - skew[1] * dx);
+            let u = dx / transforms.scale_x + texture_width / 2.;
+            let v = dy / transforms.scale_y + texture_height / 2.;
+
+            if u < 0. || v < 0. || u >= texture_width || v >= texture_height {
+                continue;
+            }
+
+            let texel = texture.get_pixel(u as u32, v as u32);
+            let [r, g, b] = filter(
+                [
+                    texel[0] as f32 / 255.,
+                    texel[1] as f32 / 255.,
+                    texel[2] as f32 / 255.,
+                ],
+                &frame_data.filters,
+            );
+            let alpha =
+                texel[3] as f32 / 255. * frame_data.filters.opacity.clamp(0., 1.) * coverage;
+
+            let dst = canvas.get_pixel_mut(x, y);
+            let blend = |src: f32, dst: u8| {
+                ((src * alpha + dst as f32 / 255. * (1. - alpha)) * 255.).round() as u8
+            };
+            *dst = Rgba([
+                blend(r, dst[0]),
+                blend(g, dst[1]),
+                blend(b, dst[2]),
+                blend(1., dst[3]),
+            ]);
+        }
+    }
+}
+
+/// CSS filter functions, applied in the same order as the texture shader.
+fn filter(rgb: [f32; 3], filters: &Filters) -> [f32; 3] {
+    let mut rgb = rgb.map(|c| c + filters.brightness);
+    rgb = rgb.map(|c| (c - 0.5) * filters.contrast + 0.5);
+
+    let s = filters.saturation;
+    rgb = matrix(
+        rgb,
+        [
+            [0.213 + 0.787 * s, 0.715 - 0.715 * s, 0.072 - 0.072 * s],
+            [0.213 - 0.213 * s, 0.715 + 0.285 * s, 0.072 - 0.072 * s],
+            [0.213 - 0.213 * s, 0.715 - 0.715 * s, 0.072 + 0.928 * s],
+        ],
+    );
+
+    let (sin, cos) = filters.hue_rotate.to_radians().sin_cos();
+    rgb = matrix(
+        rgb,
+        [
+            [
+                0.213 + cos * 0.787 - sin * 0.213,
+                0.715 - cos * 0.715 - sin * 0.715,
+                0.072 - cos * 0.072 + sin * 0.928,
+            ],
+            [
+                0.213 - cos * 0.213 + sin * 0.143,
+                0.715 + cos * 0.285 + sin * 0.140,
+                0.072 - cos * 0.072 - sin * 0.283,
+            ],
+            [
+                0.213 - cos * 0.213 - sin * 0.787,
+                0.715 - cos * 0.715 + sin * 0.715,
+                0.072 + cos * 0.928 + sin * 0.072,
+            ],
+        ],
+    );
+
+    let sepia = matrix(
+        rgb,
+        [
+            [0.393, 0.769, 0.189],
+            [0.349, 0.686, 0.168],
+            [0.272, 0.534, 0.131],
+        ],
+    );
+    rgb = mix(rgb, sepia, filters.sepia);
+
+    let luma = 0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2];
+    rgb = mix(rgb, [luma; 3], filters.grayscale);
+
+    rgb = mix(rgb, rgb.map(|c| 1. - c), filters.invert);
+
+    let color = filters.blur_color;
+    if filters.blur > 0 && color[3] > 0. {
+        rgb = mix(rgb, [color[0], color[1], color[2]], color[3]);
+    }
+
+    rgb.map(|c| c.clamp(0., 1.))
+}
+
+fn matrix(rgb: [f32; 3], m: [[f32; 3]; 3]) -> [f32; 3] {
+    m.map(|row| row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2])
+}
+
+fn mix(a: [f32; 3], b: [f32; 3], amount: f32) -> [f32; 3] {
+    let amount = amount.clamp(0., 1.);
+    [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * amount)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::{Clip, MaskFrame};
+
+    const WIDTH: u32 = 8;
+    const HEIGHT: u32 = 4;
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+
+    fn solid(color: Rgba<u8>) -> ImageData {
+        ImageData::from(RgbaImage::from_pixel(WIDTH, HEIGHT, color))
+    }
+
+    fn compose_solid(frames: &Frames, mask: Option<&ImageData>) -> RgbaImage {
+        compose(WIDTH, HEIGHT, &solid(RED), &solid(BLUE), mask, frames)
+    }
+
+    #[test]
+    fn incoming_covers_outgoing() {
+        let canvas = compose_solid(&Frames::default(), None);
+        assert!(canvas.pixels().all(|pixel| *pixel == BLUE));
+    }
+
+    #[test]
+    fn outgoing_on_top_covers_incoming() {
+        let frames = Frames {
+            outgoing_on_top: true,
+            ..Default::default()
+        };
+        let canvas = compose_solid(&frames, None);
+        assert!(canvas.pixels().all(|pixel| *pixel == RED));
+    }
+
+    #[test]
+    fn opacity_mixes_layers() {
+        let mut frames = Frames::default();
+        frames.incoming.filters.opacity = 0.5;
+
+        let canvas = compose_solid(&frames, None);
+        assert!(
+            canvas
+                .pixels()
+                .all(|pixel| *pixel == Rgba([128, 0, 128, 255]))
+        );
+    }
+
+    #[test]
+    fn clip_shows_outgoing_outside() {
+        let mut frames = Frames::default();
+        frames.incoming.clip = Clip {
+            right: 0.5,
+            ..Default::default()
+        };
+
+        let canvas = compose_solid(&frames, None);
+        canvas.enumerate_pixels().for_each(|(x, _, pixel)| {
+            assert_eq!(*pixel, if x < WIDTH / 2 { BLUE } else { RED });
+        });
+    }
+
+    #[test]
+    fn translated_layer_leaves_black() {
+        let mut frames = Frames::default();
+        frames.outgoing.filters.opacity = 0.;
+        frames.incoming.transforms.translate = [0.5, 0.];
+
+        let canvas = compose_solid(&frames, None);
+        canvas.enumerate_pixels().for_each(|(x, _, pixel)| {
+            let expected = if x < WIDTH / 2 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                BLUE
+            };
+            assert_eq!(*pixel, expected);
+        });
+    }
+
+    #[test]
+    fn mask_blends_by_luminance() {
+        // Dark on the left, so that the incoming image shows there first.
+        let mask = ImageData::from(RgbaImage::from_fn(WIDTH, HEIGHT, |x, _| {
+            let luma = if x < WIDTH / 2 { 0 } else { 255 };
+            Rgba([luma, luma, luma, 255])
+        }));
+        let frames = |progress| Frames {
+            mask: Some(MaskFrame {
+                progress,
+                softness: 0.01,
+            }),
+            ..Default::default()
+        };
+
+        let canvas = compose_solid(&frames(0.), Some(&mask));
+        assert!(canvas.pixels().all(|pixel| *pixel == RED));
+
+        let canvas = compose_solid(&frames(0.5), Some(&mask));
+        canvas.enumerate_pixels().for_each(|(x, _, pixel)| {
+            assert_eq!(*pixel, if x < WIDTH / 2 { BLUE } else { RED });
+        });
+
+        let canvas = compose_solid(&frames(1.), Some(&mask));
+        assert!(canvas.pixels().all(|pixel| *pixel == BLUE));
+    }
+}
@@ -0,0 +1,475 @@
+use crate::{
+    Moxpaper,
+    assets::{AssetData, FallbackImage},
+    config::{Config, OutputArgument},
+    schedule,
+};
+use calloop::{
+    Interest, LoopHandle, Mode, PostAction, RegistrationToken,
+    generic::Generic,
+    timer::{TimeoutAction, Timer},
+};
+use std::{
+    collections::HashSet,
+    ffi::{CString, OsStr, OsString},
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+        unix::ffi::OsStrExt,
+    },
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+/// Editors save in several steps, the config is read once they settle.
+const SETTLE: Duration = Duration::from_millis(200);
+
+/// Where the config is read again from, and the pending reload.
+pub struct Reload {
+    path: Option<Box<Path>>,
+    timer: Option<RegistrationToken>,
+    /// What a config written as a function was last called with
+    outputs: Vec<OutputArgument>,
+}
+
+impl Reload {
+    pub fn new(path: Option<Box<Path>>) -> Self {
+        Self {
+            path,
+            timer: None,
+            outputs: Vec::new(),
+        }
+    }
+}
+
+/// Directory the config can be in, watched rather than the files since
+/// those are usually replaced instead of written to.
+struct Directory {
+    path: PathBuf,
+    /// Names of the files in it that belong to the config, any `.nix` file
+    /// if `None`
+    names: Option<Vec<OsString>>,
+    watch: Option<i32>,
+}
+
+/// Inotify instance watching the directories of the config.
+struct Watcher {
+    fd: OwnedFd,
+    directories: Vec<Directory>,
+}
+
+impl Watcher {
+    fn new(path: Option<&Path>) -> anyhow::Result<Self> {
+        let directories = match path {
+            Some(path) => {
+                let name = path
+                    .file_name()
+                    .ok_or_else(|| anyhow::anyhow!("{} is not a file", path.display()))?;
+                vec![Directory {
+                    path: match path.parent() {
+                        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                        _ => PathBuf::from("."),
+                    },
+                    // Like default.nix, it may import the other files next
+                    // to it.
+                    names: match path.extension().is_some_and(|e| e == "nix") {
+                        true => None,
+                        false => Some(vec![name.to_os_string()]),
+                    },
+                    watch: None,
+                }]
+            }
+            None => {
+                let [default, single, ..] = Config::candidates()?;
+                let mox = single.parent().unwrap_or(&single).to_path_buf();
+                vec![
+                    // Also sees mox/moxpaper being created, to watch it next.
+                    Directory {
+                        path: mox,
+                        names: Some(vec![
+                            "moxpaper.nix".into(),
+                            "moxpaper.toml".into(),
+                            "moxpaper.json".into(),
+                            "moxpaper".into(),
+                        ]),
+                        watch: None,
+                    },
+                    // default.nix may import the other files next to it.
+                    Directory {
+                        path: default.parent().unwrap_or(&default).to_path_buf(),
+                        names: None,
+                        watch: None,
+                    },
+                ]
+            }
+        };
+
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        let mut watcher = Self {
+            // SAFETY: the descriptor was just created and isn't owned elsewhere.
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            directories,
+        };
+        watcher.watch();
+
+        Ok(watcher)
+    }
+
+    /// Watches the directories that exist now and weren't yet, returns
+    /// whether there are any. Those that don't exist yet are waited for on
+    /// the closest parent that does.
+    fn watch(&mut self) -> bool {
+        let fd = self.fd.as_raw_fd();
+        let add = |path: &Path, mask: u32| {
+            let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+            let watch = unsafe { libc::inotify_add_watch(fd, path.as_ptr(), mask) };
+            (watch >= 0).then_some(watch)
+        };
+
+        let mut added = false;
+        self.directories
+            .iter_mut()
+            .filter(|directory| directory.watch.is_none())
+            .for_each(|directory| {
+                let mask = libc::IN_CLOSE_WRITE
+                    | libc::IN_CREATE
+                    | libc::IN_DELETE
+                    | libc::IN_MOVED_FROM
+                    | libc::IN_MOVED_TO;
+                if let Some(watch) = add(&directory.path, mask) {
+                    log::debug!("Watching {} for config changes", directory.path.display());
+                    directory.watch = Some(watch);
+                    added = true;
+                    return;
+                }
+
+                // Added to the mask of a parent that's watched already.
+                let mask = libc::IN_CREATE | libc::IN_MOVED_TO | libc::IN_MASK_ADD;
+                if let Some(parent) = directory
+                    .path
+                    .ancestors()
+                    .skip(1)
+                    .find(|parent| add(parent, mask).is_some())
+                {
+                    log::debug!(
+                        "Waiting in {} for {} to be created",
+                        parent.display(),
+                        directory.path.display()
+                    );
+                }
+            });
+
+        added
+    }
+
+    /// Reads the pending events, returns whether any of them touched the
+    /// config.
+    fn changed(&mut self) -> bool {
+        const HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+
+        let mut changed = false;
+        let mut buffer = [0u8; 4096];
+        loop {
+            let read = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    buffer.as_mut_ptr().cast(),
+                    buffer.len(),
+                )
+            };
+            let Ok(read) = usize::try_from(read) else {
+                break;
+            };
+            if read == 0 {
+                break;
+            }
+
+            let mut offset = 0;
+            while offset + HEADER <= read {
+                // SAFETY: the kernel writes whole events, the header is
+                // followed by `len` bytes of name.
+                let event = unsafe {
+                    std::ptr::read_unaligned(
+                        buffer[offset..].as_ptr().cast::<libc::inotify_event>(),
+                    )
+                };
+                let name = &buffer[offset + HEADER..offset + HEADER + event.len as usize];
+                let name = OsStr::from_bytes(name.split(|&b| b == 0).next().unwrap_or_default());
+                offset += HEADER + event.len as usize;
+
+                let Some(directory) = self
+                    .directories
+                    .iter_mut()
+                    .find(|directory| directory.watch == Some(event.wd))
+                else {
+                    continue;
+                };
+
+                if event.mask & libc::IN_IGNORED != 0 {
+                    directory.watch = None;
+                    continue;
+                }
+
+                changed |= match &directory.names {
+                    Some(names) => names.iter().any(|other| other == name),
+                    None => Path::new(name).extension().is_some_and(|e| e == "nix"),
+                };
+            }
+        }
+
+        // A directory that was just created may already hold the config.
+        self.watch() || changed
+    }
+}
+
+impl AsFd for Watcher {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+/// Blocks SIGHUP and returns a descriptor that's readable once it's sent.
+/// Has to be called before other threads start, so that none of them is
+/// left to take the signal.
+pub fn hangup() -> anyhow::Result<OwnedFd> {
+    unsafe {
+        let mut set = std::mem::zeroed::<libc::sigset_t>();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGHUP);
+
+        let error = libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+        if error != 0 {
+            return Err(std::io::Error::from_raw_os_error(error).into());
+        }
+
+        let fd = libc::signalfd(-1, &set, libc::SFD_NONBLOCK | libc::SFD_CLOEXEC);
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        Ok(OwnedFd::from_raw_fd(fd))
+    }
+}
+
+/// Reloads the config when its files change or on SIGHUP.
+pub fn watch(
+    handle: &LoopHandle<'static, Moxpaper>,
+    path: Option<&Path>,
+    hangup: OwnedFd,
+) -> anyhow::Result<()> {
+    let source = Generic::new(hangup, Interest::READ, Mode::Level);
+    handle.insert_source(source, |_, fd, state| {
+        let mut info = [0u8; std::mem::size_of::<libc::signalfd_siginfo>()];
+        while unsafe { libc::read(fd.as_raw_fd(), info.as_mut_ptr().cast(), info.len()) } > 0 {}
+
+        log::info!("Received SIGHUP");
+        state.reload_config();
+        Ok(PostAction::Continue)
+    })?;
+
+    let watcher = match Watcher::new(path) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            log::warn!("Config changes won't be picked up: {e}");
+            return Ok(());
+        }
+    };
+
+    let source = Generic::new(watcher, Interest::READ, Mode::Level);
+    handle.insert_source(source, |_, watcher, state| {
+        // SAFETY: the descriptor isn't replaced or closed.
+        if unsafe { watcher.get_mut() }.changed() {
+            state.schedule_reload();
+        }
+        Ok(PostAction::Continue)
+    })?;
+
+    Ok(())
+}
+
+impl Moxpaper {
+    /// Reloads the config once its files stop changing.
+    fn schedule_reload(&mut self) {
+        if let Some(token) = self.reload.timer.take() {
+            self.handle.remove(token);
+        }
+
+        self.reload.timer = self
+            .handle
+            .insert_source(Timer::from_duration(SETTLE), |_, _, state| {
+                state.reload.timer = None;
+                state.reload_config();
+                TimeoutAction::Drop
+            })
+            .inspect_err(|e| log::error!("Failed to schedule config reload: {e}"))
+            .ok();
+    }
+
+    /// Evaluates the config again and applies what changed. The running
+    /// config is kept if it fails to evaluate.
+    pub fn reload_config(&mut self) {
+        let affected = self.apply_config();
+        self.render_outputs(|name| affected.contains(name));
+    }
+
+    /// Evaluates a config written as a function of the outputs again once
+    /// they changed. `configuring` are left to show their wallpaper
+    /// themselves.
+    pub fn outputs_changed(&mut self, configuring: &[Arc<str>]) {
+        if !self.config.of_outputs || self.config_outputs() == self.reload.outputs {
+            return;
+        }
+
+        let affected = self.apply_config();
+        self.render_outputs(|name| !configuring.contains(name) && affected.contains(name));
+    }
+
+    /// The outputs a config written as a function is called with.
+    fn config_outputs(&self) -> Vec<OutputArgument> {
+        let mut outputs = self
+            .outputs
+            .iter()
+            .filter(|output| output.info.width > 0)
+            .map(|output| OutputArgument {
+                name: Arc::clone(&output.info.name),
+                width: output.info.width,
+                height: output.info.height,
+                scale: output.info.scale,
+                make: output.make.clone(),
+                model: output.model.clone(),
+            })
+            .collect::<Vec<_>>();
+        outputs.sort_by(|a, b| a.name.cmp(&b.name));
+
+        outputs
+    }
+
+    /// Reads the config and applies what changed, returns the outputs whose
+    /// wallpaper did.
+    fn apply_config(&mut self) -> Vec<Arc<str>> {
+        let outputs = self.config_outputs();
+        let config = match Config::read(self.reload.path.as_deref(), &outputs) {
+            Ok(config) => config,
+            Err(e) => {
+                log::error!("Keeping the running config: {e}");
+                return Vec::new();
+            }
+        };
+        self.reload.outputs = outputs;
+
+        if config == self.config {
+            log::info!("Config unchanged");
+            return Vec::new();
+        }
+
+        log::info!("Reloading config");
+        let previous = std::mem::replace(&mut self.config, config);
+
+        if previous.power_preference != self.config.power_preference {
+            log::warn!("power_preference takes effect after a restart");
+        }
+
+        #[cfg(feature = "s3")]
+        self.reload_buckets(&previous);
+
+        let changed = changed_keys(&previous, &self.config);
+        let affected = self
+            .outputs
+            .iter()
+            .map(|output| &output.info.name)
+            .filter(|name| {
+                changed.contains(key(&previous, name)) || changed.contains(key(&self.config, name))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        // What's shown is decided like at startup, schedules over playlists
+        // over the sun over plain wallpapers.
+        changed.iter().for_each(|output| {
+            let scheduled = self
+                .config
+                .schedule
+                .get(output)
+                .is_some_and(|entries| !entries.is_empty());
+            if scheduled || self.config.playlist.contains_key(output) {
+                return;
+            }
+
+            let asset = match (
+                self.config.sun.get(output),
+                self.config.wallpaper.get(output),
+            ) {
+                (Some(sun), _) => AssetData::sun(sun, self.config.location),
+                (None, Some(wallpaper)) => AssetData::load(wallpaper),
+                (None, None) => None,
+            };
+
+            match (&**output, asset) {
+                ("any", Some(asset)) => self.assets.set_fallback(FallbackImage::Image(asset)),
+                ("any", None) => self.assets.clear_fallback(),
+                (_, Some(asset)) => self.assets.insert_asset(Arc::clone(output), asset),
+                (_, None) => self.assets.remove_asset(output),
+            }
+        });
+
+        if previous.playlist != self.config.playlist {
+            self.playlists.reload(&self.config, &mut self.assets);
+            let names = self
+                .outputs
+                .iter()
+                .map(|output| Arc::clone(&output.info.name))
+                .collect::<Vec<_>>();
+            names.iter().for_each(|name| {
+                self.playlists
+                    .add_output(name, &self.config, &mut self.assets)
+            });
+        }
+
+        if previous.schedule != self.config.schedule {
+            self.schedules.stop(&self.handle);
+            self.schedules = schedule::Schedules::new(&self.config, &mut self.assets, &self.handle);
+        }
+
+        affected
+    }
+}
+
+/// Outputs and "any" whose wallpaper, sun, playlist or schedule differ.
+fn changed_keys(previous: &Config, config: &Config) -> HashSet<Arc<str>> {
+    [previous, config]
+        .iter()
+        .flat_map(|config| {
+            config
+                .wallpaper
+                .keys()
+                .chain(config.sun.keys())
+                .chain(config.playlist.keys())
+                .chain(config.schedule.keys())
+        })
+        .filter(|key| {
+            previous.wallpaper.get(*key) != config.wallpaper.get(*key)
+                || previous.sun.get(*key) != config.sun.get(*key)
+                || previous.playlist.get(*key) != config.playlist.get(*key)
+                || previous.schedule.get(*key) != config.schedule.get(*key)
+                || (previous.location != config.location && config.sun.contains_key(*key))
+        })
+        .cloned()
+        .collect()
+}
+
+/// The key of the config an output takes its wallpaper from.
+fn key<'a>(config: &Config, name: &'a str) -> &'a str {
+    let own = config.wallpaper.contains_key(name)
+        || config.sun.contains_key(name)
+        || config.playlist.contains_key(name)
+        || config.schedule.contains_key(name);
+
+    match own {
+        true => name,
+        false => "any",
+    }
+}
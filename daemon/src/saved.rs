@@ -0,0 +1,140 @@
+use crate::Moxpaper;
+use anyhow::Context;
+use libmoxpaper::{Data, Transition, TransitionType, WallpaperData};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+/// The wallpapers clients set last, kept in a file under `XDG_STATE_HOME`
+/// so that they outlive the daemon.
+#[derive(Default)]
+pub struct Saved {
+    path: Option<PathBuf>,
+    /// Keyed by output, "any" for every output without one of its own
+    wallpapers: HashMap<Arc<str>, WallpaperData>,
+    /// Restored wallpapers of outputs that aren't configured yet
+    pending: HashMap<Arc<str>, WallpaperData>,
+}
+
+impl Saved {
+    pub fn load() -> Self {
+        let path = match Self::xdg_state_dir() {
+            Ok(dir) => dir.join("moxpaper").join("wallpapers.json"),
+            Err(e) => {
+                log::warn!("Wallpapers won't be saved, no state directory: {e}");
+                return Self::default();
+            }
+        };
+
+        let wallpapers = match std::fs::read(&path) {
+            Ok(json) => serde_json::from_slice(&json)
+                .inspect_err(|e| log::error!("Failed to read {}: {e}", path.display()))
+                .unwrap_or_default(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                log::error!("Failed to read {}: {e}", path.display());
+                HashMap::new()
+            }
+        };
+
+        Self {
+            path: Some(path),
+            wallpapers,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Remembers a wallpaper a client set, for every output it's set on.
+    pub fn record(&mut self, wallpaper: &WallpaperData) {
+        // Images sent along with the request would bloat the file.
+        if let Data::Image(_) = wallpaper.data {
+            log::debug!("Not saving wallpaper sent as image data");
+            return;
+        }
+
+        match wallpaper.outputs.as_slice() {
+            [] => _ = self.wallpapers.insert("any".into(), wallpaper.clone()),
+            outputs => {
+                outputs
+                    .iter()
+                    .zip(wallpaper.per_output())
+                    .for_each(|(output, wallpaper)| {
+                        self.wallpapers.insert(Arc::clone(output), wallpaper);
+                    })
+            }
+        }
+
+        if let Err(e) = self.write() {
+            log::error!("Failed to save wallpapers: {e}");
+        }
+    }
+
+    fn write(&self) -> anyhow::Result<()> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(());
+        };
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("{}", dir.display()))?;
+        }
+
+        // Written aside first, a crash mid-write keeps the previous file.
+        let temporary = path.with_extension("json.tmp");
+        std::fs::write(&temporary, serde_json::to_vec_pretty(&self.wallpapers)?)
+            .with_context(|| format!("{}", temporary.display()))?;
+        std::fs::rename(&temporary, path).with_context(|| format!("{}", path.display()))?;
+
+        Ok(())
+    }
+
+    fn xdg_state_dir() -> anyhow::Result<PathBuf> {
+        std::env::var("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|_| std::env::var("HOME").map(|h| PathBuf::from(h).join(".local/state")))
+            .map_err(Into::into)
+    }
+}
+
+impl Moxpaper {
+    /// Shows the saved wallpapers again, straight away unless `transition`.
+    /// Outputs that aren't configured yet get theirs in
+    /// [`Moxpaper::restore_output`].
+    pub fn restore(&mut self, transition: bool) {
+        let mut wallpapers = self.saved.wallpapers.clone();
+        if !transition {
+            wallpapers.values_mut().for_each(|wallpaper| {
+                wallpaper.transition = Transition {
+                    transition_type: Some(TransitionType::None),
+                    stages: Vec::new(),
+                    ..wallpaper.transition.clone()
+                };
+            });
+        }
+
+        for (output, wallpaper) in wallpapers {
+            let configured = self
+                .outputs
+                .iter()
+                .any(|other| other.info.name == output && other.info.width > 0);
+            if &*output != "any" && !configured {
+                self.saved.pending.insert(output, wallpaper);
+                continue;
+            }
+
+            match self.load_wallpaper(wallpaper.clone()) {
+                Ok(()) => self.record_history(&wallpaper),
+                Err(e) => log::error!("Failed to restore wallpaper of {output}: {e}"),
+            }
+        }
+    }
+
+    /// Shows the restored wallpaper of an output once it's configured.
+    pub fn restore_output(&mut self, name: &str) {
+        let Some(wallpaper) = self.saved.pending.remove(name) else {
+            return;
+        };
+
+        match self.load_wallpaper(wallpaper.clone()) {
+            Ok(()) => self.record_history(&wallpaper),
+            Err(e) => log::error!("Failed to restore wallpaper of {name}: {e}"),
+        }
+    }
+}
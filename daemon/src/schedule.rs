@@ -0,0 +1,237 @@
+use crate::{
+    Moxpaper,
+    assets::{AssetData, AssetsManager, FallbackImage},
+    clock::LocalTime,
+    config::{Config, ScheduleEntry, Wallpaper},
+};
+use calloop::{
+    LoopHandle, RegistrationToken,
+    timer::{TimeoutAction, Timer},
+};
+use libmoxpaper::Playback;
+use std::{sync::Arc, time::Duration};
+
+/// Longest the wall clock goes unchecked. Timers run on the monotonic clock,
+/// which doesn't follow daylight saving changes and stops while suspended.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+const DAY: f64 = 24. * 3600.;
+
+/// The schedules of the config and the entry each of them shows.
+pub struct Schedules {
+    schedules: Vec<Schedule>,
+    timers: Vec<RegistrationToken>,
+}
+
+struct Schedule {
+    /// Output the schedule is shown on, "any" for every output without a
+    /// wallpaper of its own
+    output: Arc<str>,
+    /// Sorted by time
+    entries: Vec<ScheduleEntry>,
+    current: Option<usize>,
+}
+
+impl Schedule {
+    /// The entry shown at `seconds` since midnight, which is the last one of
+    /// the day before until the first one starts.
+    fn entry_at(&self, seconds: f64) -> Option<usize> {
+        let last = self.entries.len().checked_sub(1)?;
+
+        Some(
+            self.entries
+                .iter()
+                .rposition(|entry| entry.time.seconds() as f64 <= seconds)
+                .unwrap_or(last),
+        )
+    }
+
+    /// Time until the next entry starts, or until the clock has to be
+    /// checked again.
+    fn until_next(&self, seconds: f64) -> Duration {
+        let next = self
+            .entries
+            .iter()
+            .map(|entry| entry.time.seconds() as f64)
+            .find(|&time| time > seconds)
+            .or_else(|| {
+                self.entries
+                    .first()
+                    .map(|entry| entry.time.seconds() as f64 + DAY)
+            })
+            .unwrap_or(seconds + DAY);
+
+        Duration::from_secs_f64((next - seconds).max(0.)).min(CHECK_INTERVAL)
+    }
+
+    /// Makes the current entry the wallpaper of the outputs.
+    fn show(&self, assets: &mut AssetsManager) {
+        let Some(entry) = self.current.and_then(|current| self.entries.get(current)) else {
+            return;
+        };
+
+        let Some(asset) = AssetData::load(&Wallpaper {
+            path: entry.path.clone(),
+            resize: entry.resize,
+            transition: entry.transition.clone(),
+            repeat: entry.repeat,
+            fps: None,
+            playback: Playback::default(),
+            frame_offset: 0,
+        }) else {
+            return;
+        };
+
+        match &*self.output {
+            "any" => assets.set_fallback(FallbackImage::Image(asset)),
+            _ => assets.insert_asset(Arc::clone(&self.output), asset),
+        }
+    }
+}
+
+impl Schedules {
+    /// Shows the entries of `config` that are current and arms the timers
+    /// for the next ones.
+    pub fn new(
+        config: &Config,
+        assets: &mut AssetsManager,
+        handle: &LoopHandle<'static, Moxpaper>,
+    ) -> Self {
+        let now = LocalTime::now();
+        let schedules = config
+            .schedule
+            .iter()
+            .filter(|(_, entries)| !entries.is_empty())
+            .map(|(output, entries)| {
+                let mut entries = entries.clone();
+                entries.sort_by_key(|entry| entry.time);
+
+                let mut schedule = Schedule {
+                    output: Arc::clone(output),
+                    entries,
+                    current: None,
+                };
+                schedule.current = schedule.entry_at(now.seconds);
+                schedule.show(assets);
+
+                schedule
+            })
+            .collect::<Vec<_>>();
+
+        let timers = schedules
+            .iter()
+            .enumerate()
+            .filter_map(|(index, schedule)| {
+                let timer = Timer::from_duration(schedule.until_next(now.seconds));
+                handle
+                    .insert_source(timer, move |_, _, state| {
+                        TimeoutAction::ToDuration(state.check_schedule(index))
+                    })
+                    .inspect_err(|e| {
+                        log::error!("Failed to schedule wallpaper of {}: {e}", schedule.output)
+                    })
+                    .ok()
+            })
+            .collect();
+
+        Self { schedules, timers }
+    }
+
+    /// Stops the timers of all schedules, before they are replaced.
+    pub fn stop(&mut self, handle: &LoopHandle<'static, Moxpaper>) {
+        self.timers.drain(..).for_each(|token| handle.remove(token));
+    }
+}
+
+impl Moxpaper {
+    /// Switches to the entry of the schedule the wall clock is in, returns
+    /// when to check again.
+    fn check_schedule(&mut self, index: usize) -> Duration {
+        let now = LocalTime::now();
+        let schedule = &mut self.schedules.schedules[index];
+
+        let entry = schedule.entry_at(now.seconds);
+        if entry != schedule.current {
+            schedule.current = entry;
+            schedule.show(&mut self.assets);
+
+            let output = Arc::clone(&schedule.output);
+            self.render_configured(&output);
+        }
+
+        self.schedules.schedules[index].until_next(now.seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule_of(times: &[&str]) -> Schedule {
+        let entries = times
+            .iter()
+            .map(|time| {
+                serde_json::from_value(serde_json::json!({ "time": time, "path": "day.png" }))
+                    .unwrap()
+            })
+            .collect();
+
+        Schedule {
+            output: "any".into(),
+            entries,
+            current: None,
+        }
+    }
+
+    fn at(hours: u32, minutes: u32, seconds: u32) -> f64 {
+        (hours * 3600 + minutes * 60 + seconds) as f64
+    }
+
+    #[test]
+    fn entry_wraps_before_the_first() {
+        let schedule = schedule_of(&["07:00", "12:00", "19:30"]);
+        assert_eq!(schedule.entry_at(0.), Some(2));
+        assert_eq!(schedule.entry_at(at(6, 59, 59)), Some(2));
+        assert_eq!(schedule.entry_at(at(12, 30, 0)), Some(1));
+        assert_eq!(schedule.entry_at(at(23, 59, 59) + 0.9), Some(2));
+    }
+
+    #[test]
+    fn entry_starts_on_its_time() {
+        let schedule = schedule_of(&["07:00", "12:00", "19:30"]);
+        assert_eq!(schedule.entry_at(at(7, 0, 0)), Some(0));
+        assert_eq!(schedule.entry_at(at(12, 0, 0) - 0.001), Some(0));
+        assert_eq!(schedule.entry_at(at(12, 0, 0)), Some(1));
+        assert_eq!(schedule.entry_at(at(19, 30, 0)), Some(2));
+    }
+
+    #[test]
+    fn empty_schedule_has_no_entry() {
+        let schedule = schedule_of(&[]);
+        assert_eq!(schedule.entry_at(at(12, 0, 0)), None);
+        assert_eq!(schedule.until_next(at(12, 0, 0)), CHECK_INTERVAL);
+    }
+
+    #[test]
+    fn until_next_entry() {
+        let schedule = schedule_of(&["00:00:10", "12:00"]);
+        assert_eq!(schedule.until_next(at(11, 59, 30)), Duration::from_secs(30));
+        assert_eq!(
+            schedule.until_next(at(11, 59, 59) + 0.5),
+            Duration::from_millis(500)
+        );
+        // An entry starting now is shown, the next one is due.
+        assert_eq!(schedule.until_next(at(12, 0, 0)), CHECK_INTERVAL);
+        assert_eq!(schedule.until_next(at(0, 0, 0)), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn until_next_wraps_to_tomorrow() {
+        let schedule = schedule_of(&["00:00:10", "12:00"]);
+        assert_eq!(schedule.until_next(at(23, 59, 30)), Duration::from_secs(40));
+
+        let midnight = schedule_of(&["00:00"]);
+        assert_eq!(midnight.until_next(DAY - 5.), Duration::from_secs(5));
+        assert_eq!(midnight.until_next(0.), CHECK_INTERVAL);
+    }
+}
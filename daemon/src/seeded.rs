@@ -0,0 +1,16 @@
+//! Pseudo random values that only depend on their seed, so that they are
+//! the same on every run and every frame.
+
+/// Value in `0..=1` for the `index`th item drawn from `seed`, `salt` tells
+/// apart the values used for different purposes.
+pub fn random(seed: u32, index: u64, salt: u32) -> f32 {
+    let index = (index ^ (index >> 32)) as u32;
+    let mut h = seed ^ index.wrapping_mul(0x9e37_79b9) ^ salt.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb_352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846c_a68b);
+    h ^= h >> 16;
+
+    h as f32 / u32::MAX as f32
+}
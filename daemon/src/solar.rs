@@ -0,0 +1,566 @@
+use crate::{
+    assets::{AssetData, BlendSource},
+    clock::LocalTime,
+    config::{self, Location, SunPhase},
+    output::blend::{BlendFrame, mix},
+};
+use libmoxpaper::{ResizeStrategy, image_data::ImageData};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// How often the sun's position is checked again.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Time between the frames of a fade between two variants.
+const FADE_FRAME: Duration = Duration::from_millis(16);
+
+/// Elevation of the sun's center at sunrise and sunset, refraction and the
+/// sun's radius included.
+const SUNRISE: f64 = -0.833;
+
+/// Elevation of the sun's center at the start of dawn and end of dusk.
+const CIVIL_TWILIGHT: f64 = -6.;
+
+/// Where the sun is in the sky.
+#[derive(Debug, Clone, Copy)]
+pub struct Position {
+    /// Degrees above the horizon
+    pub elevation: f64,
+    /// Whether it is before solar noon
+    pub rising: bool,
+}
+
+/// When the sun crosses the elevations of a day, `None` when it stays above
+/// or below them.
+#[derive(Debug, Clone, Copy)]
+pub struct Events {
+    pub dawn: Option<SystemTime>,
+    pub sunrise: Option<SystemTime>,
+    pub noon: SystemTime,
+    pub sunset: Option<SystemTime>,
+    pub dusk: Option<SystemTime>,
+}
+
+/// Declination of the sun and the equation of time at `time`, both in
+/// degrees, after NOAA's solar calculator.
+fn declination_and_equation(time: SystemTime) -> (f64, f64) {
+    let unix = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+    let julian_day = unix / 86400. + 2440587.5;
+    let t = (julian_day - 2451545.) / 36525.;
+
+    let mean_longitude = (280.46646 + t * (36000.76983 + t * 0.0003032)).rem_euclid(360.);
+    let mean_anomaly = 357.52911 + t * (35999.05029 - 0.0001537 * t);
+    let eccentricity = 0.016708634 - t * (0.000042037 + 0.0000001267 * t);
+
+    let m = mean_anomaly.to_radians();
+    let center = m.sin() * (1.914602 - t * (0.004817 + 0.000014 * t))
+        + (2. * m).sin() * (0.019993 - 0.000101 * t)
+        + (3. * m).sin() * 0.000289;
+    let omega = (125.04 - 1934.136 * t).to_radians();
+    let apparent_longitude =
+        (mean_longitude + center - 0.00569 - 0.00478 * omega.sin()).to_radians();
+
+    let mean_obliquity =
+        23. + (26. + (21.448 - t * (46.815 + t * (0.00059 - t * 0.001813))) / 60.) / 60.;
+    let obliquity = (mean_obliquity + 0.00256 * omega.cos()).to_radians();
+
+    let declination = (obliquity.sin() * apparent_longitude.sin()).asin();
+
+    let y = (obliquity / 2.).tan().powi(2);
+    let l = mean_longitude.to_radians();
+    let equation = y * (2. * l).sin() - 2. * eccentricity * m.sin()
+        + 4. * eccentricity * y * m.sin() * (2. * l).cos()
+        - 0.5 * y * y * (4. * l).sin()
+        - 1.25 * eccentricity * eccentricity * (2. * m).sin();
+
+    (declination.to_degrees(), equation.to_degrees())
+}
+
+/// The sun's position seen from `location` at `time`.
+pub fn position(location: Location, time: SystemTime) -> Position {
+    let (declination, equation) = declination_and_equation(time);
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+        .rem_euclid(86400.);
+
+    // Equation of time and longitude in degrees, at four minutes each.
+    let solar_minutes = seconds / 60. + 4. * equation + 4. * location.longitude;
+    let hour_angle = (solar_minutes / 4. - 180.).rem_euclid(360.);
+    let hour_angle = if hour_angle > 180. {
+        hour_angle - 360.
+    } else {
+        hour_angle
+    };
+
+    let (latitude, declination) = (location.latitude.to_radians(), declination.to_radians());
+    let elevation = (latitude.sin() * declination.sin()
+        + latitude.cos() * declination.cos() * hour_angle.to_radians().cos())
+    .clamp(-1., 1.)
+    .asin();
+
+    Position {
+        elevation: elevation.to_degrees(),
+        rising: hour_angle < 0.,
+    }
+}
+
+/// The sun's events on the UTC day of `time`.
+pub fn events(location: Location, time: SystemTime) -> Events {
+    let unix = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+    let midnight = unix - unix.rem_euclid(86400.);
+    let at = |seconds: f64| UNIX_EPOCH + Duration::from_secs_f64(seconds.max(0.));
+
+    let (declination, equation) = declination_and_equation(at(midnight + 43200.));
+    // Four minutes to the degree, as in `position`.
+    let noon = midnight + (720. - 4. * location.longitude - 4. * equation) * 60.;
+
+    let (latitude, declination) = (location.latitude.to_radians(), declination.to_radians());
+    // Seconds from noon until the sun is at `elevation`.
+    let offset = |elevation: f64| {
+        let cos = (elevation.to_radians().sin() - latitude.sin() * declination.sin())
+            / (latitude.cos() * declination.cos());
+        (-1. ..=1.)
+            .contains(&cos)
+            .then(|| cos.acos().to_degrees() * 4. * 60.)
+    };
+
+    Events {
+        dawn: offset(CIVIL_TWILIGHT).map(|offset| at(noon - offset)),
+        sunrise: offset(SUNRISE).map(|offset| at(noon - offset)),
+        noon: at(noon),
+        sunset: offset(SUNRISE).map(|offset| at(noon + offset)),
+        dusk: offset(CIVIL_TWILIGHT).map(|offset| at(noon + offset)),
+    }
+}
+
+/// Variants of a wallpaper picked by the sun's elevation.
+#[derive(Clone)]
+pub struct SunSource {
+    location: Location,
+    /// Sorted by elevation
+    variants: Arc<[Variant]>,
+    crossfade: bool,
+}
+
+struct Variant {
+    image: ImageData,
+    elevation: f64,
+    phase: Option<SunPhase>,
+}
+
+/// Variants to show for a position of the sun: `overlay` is drawn over
+/// `base` with its opacity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Blend {
+    base: usize,
+    overlay: Option<(usize, f32)>,
+}
+
+impl SunSource {
+    pub fn load(config: &config::SunWallpaper, location: Location) -> anyhow::Result<Self> {
+        let mut variants = config
+            .variants
+            .iter()
+            .map(|variant| {
+                let image = image::open(&variant.path)
+                    .map_err(|e| anyhow::anyhow!("{e}: {}", variant.path.display()))?;
+                Ok(Variant {
+                    image: ImageData::from(image),
+                    elevation: variant.elevation,
+                    phase: variant.phase,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        if variants.is_empty() {
+            return Err(anyhow::anyhow!("Sun wallpaper has no variants"));
+        }
+        variants.sort_by(|a, b| a.elevation.total_cmp(&b.elevation));
+
+        let events = events(location, SystemTime::now());
+        let time = |time: Option<SystemTime>| {
+            time.map(|time| {
+                let seconds = LocalTime::at(time).seconds as u32;
+                format!("{:02}:{:02}", seconds / 3600, seconds / 60 % 60)
+            })
+            .unwrap_or_else(|| "-".into())
+        };
+        log::info!(
+            "Sun at {}, {}: dawn {}, sunrise {}, noon {}, sunset {}, dusk {}",
+            location.latitude,
+            location.longitude,
+            time(events.dawn),
+            time(events.sunrise),
+            time(Some(events.noon)),
+            time(events.sunset),
+            time(events.dusk),
+        );
+
+        Ok(Self {
+            location,
+            variants: variants.into(),
+            crossfade: config.crossfade,
+        })
+    }
+
+    /// The variants for the sun's position at `time`: the highest one the
+    /// sun is above, faded into the next one up when crossfading.
+    fn blend(&self, time: SystemTime) -> Blend {
+        let sun = position(self.location, time);
+        let phase = if sun.rising {
+            SunPhase::Rising
+        } else {
+            SunPhase::Setting
+        };
+
+        let candidates = self
+            .variants
+            .iter()
+            .enumerate()
+            .filter(|(_, variant)| variant.phase.is_none_or(|p| p == phase))
+            .collect::<Vec<_>>();
+        let Some(&(lowest, _)) = candidates.first() else {
+            return Blend {
+                base: 0,
+                overlay: None,
+            };
+        };
+
+        let below = candidates
+            .iter()
+            .rposition(|(_, variant)| variant.elevation <= sun.elevation);
+        let Some(below) = below else {
+            return Blend {
+                base: lowest,
+                overlay: None,
+            };
+        };
+
+        let (base, base_variant) = candidates[below];
+        let overlay =
+            candidates
+                .get(below + 1)
+                .filter(|_| self.crossfade)
+                .map(|&(index, variant)| {
+                    let span = variant.elevation - base_variant.elevation;
+                    let amount = if span > 0. {
+                        (sun.elevation - base_variant.elevation) / span
+                    } else {
+                        0.
+                    };
+                    (index, amount.clamp(0., 1.) as f32)
+                });
+
+        Blend { base, overlay }
+    }
+}
+
+impl AssetData {
+    /// Loads a sun wallpaper from the config, logging why it can't be shown.
+    pub fn sun(wallpaper: &config::SunWallpaper, location: Option<Location>) -> Option<Self> {
+        let Some(location) = location else {
+            log::error!("Sun wallpapers need a location in the config");
+            return None;
+        };
+
+        let source = SunSource::load(wallpaper, location)
+            .inspect_err(|e| log::error!("Failed to load sun wallpaper: {e}"))
+            .ok()?;
+        let blend = source.blend(SystemTime::now());
+
+        Some(Self {
+            image: source.variants[blend.base].image.clone(),
+            resize: wallpaper.resize,
+            transition: wallpaper.transition.clone(),
+            motion: None,
+            shader: None,
+            blend: Some(BlendSource::Sun(source)),
+        })
+    }
+}
+
+/// Follows the sun with the variants of a [`SunSource`] resized to an
+/// output.
+pub struct SunPlayer {
+    source: SunSource,
+    resize: ResizeStrategy,
+    width: u32,
+    height: u32,
+    /// How long switching variants takes without crossfading
+    fade: Duration,
+    /// Variant shown without crossfading
+    shown: Option<usize>,
+    /// Variant faded from, to, and when it started
+    fading: Option<(usize, usize, Instant)>,
+    /// Variants resized to the output
+    cache: Vec<(usize, ImageData)>,
+}
+
+impl SunPlayer {
+    pub fn new(
+        source: SunSource,
+        resize: ResizeStrategy,
+        width: u32,
+        height: u32,
+        fade: Duration,
+    ) -> Self {
+        Self {
+            source,
+            resize,
+            width,
+            height,
+            fade,
+            shown: None,
+            fading: None,
+            cache: Vec::new(),
+        }
+    }
+
+    /// What the wallpaper looks like now, as a single image.
+    pub fn image(&mut self) -> anyhow::Result<ImageData> {
+        let blend = self.source.blend(SystemTime::now());
+        self.shown = Some(blend.base);
+
+        let base = self.resized(blend.base)?;
+        match blend.overlay {
+            Some((overlay, amount)) => Ok(mix(&base, &self.resized(overlay)?, amount)),
+            None => Ok(base),
+        }
+    }
+
+    pub fn next_frame(&mut self) -> anyhow::Result<BlendFrame> {
+        let blend = match self.fading {
+            Some((from, to, start)) => {
+                let amount = start.elapsed().as_secs_f32() / self.fade.as_secs_f32().max(0.001);
+                if amount < 1. {
+                    Blend {
+                        base: from,
+                        overlay: Some((to, amount)),
+                    }
+                } else {
+                    self.fading = None;
+                    self.shown = Some(to);
+                    Blend {
+                        base: to,
+                        overlay: None,
+                    }
+                }
+            }
+            None => {
+                let blend = self.source.blend(SystemTime::now());
+                match self.shown {
+                    Some(shown) if !self.source.crossfade && shown != blend.base => {
+                        self.fading = Some((shown, blend.base, Instant::now()));
+                        Blend {
+                            base: shown,
+                            overlay: Some((blend.base, 0.)),
+                        }
+                    }
+                    _ => {
+                        self.shown = Some(blend.base);
+                        blend
+                    }
+                }
+            }
+        };
+
+        let used = [Some(blend.base), blend.overlay.map(|(overlay, _)| overlay)];
+        self.cache.retain(|(index, _)| used.contains(&Some(*index)));
+
+        Ok(BlendFrame {
+            image: self.resized(blend.base)?,
+            overlay: match blend.overlay {
+                Some((overlay, amount)) => Some((self.resized(overlay)?, amount)),
+                None => None,
+            },
+            delay: match self.fading {
+                Some(_) => FADE_FRAME,
+                None => CHECK_INTERVAL,
+            },
+        })
+    }
+
+    fn resized(&mut self, index: usize) -> anyhow::Result<ImageData> {
+        if let Some((_, image)) = self.cache.iter().find(|(cached, _)| *cached == index) {
+            return Ok(image.clone());
+        }
+
+        let variant = &self.source.variants[index];
+        let image = AssetData::new(variant.image.clone(), self.resize, Default::default())
+            .into_resized(self.width, self.height)?;
+        self.cache.push((index, image.clone()));
+
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LONDON: Location = Location {
+        latitude: 51.5074,
+        longitude: -0.1278,
+    };
+
+    const NEW_YORK: Location = Location {
+        latitude: 40.7128,
+        longitude: -74.006,
+    };
+
+    const TROMSO: Location = Location {
+        latitude: 69.6492,
+        longitude: 18.9553,
+    };
+
+    /// 2024-06-21 and 2024-12-21 at midnight UTC.
+    const JUNE_SOLSTICE: u64 = 1_718_928_000;
+    const DECEMBER_SOLSTICE: u64 = 1_734_739_200;
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    fn hours(hours: u64, minutes: u64) -> u64 {
+        hours * 3600 + minutes * 60
+    }
+
+    /// Within a minute of `expected`, published times are rounded to it.
+    fn assert_near(time: Option<SystemTime>, expected: u64) {
+        let time = time
+            .expect("the sun should cross")
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        assert!(
+            time.abs_diff(expected) <= 60,
+            "{time} isn't near {expected}"
+        );
+    }
+
+    #[test]
+    fn london_midsummer() {
+        let day = JUNE_SOLSTICE;
+        let events = events(LONDON, at(day + hours(12, 0)));
+        assert_near(events.sunrise, day + hours(3, 43));
+        assert_near(Some(events.noon), day + hours(12, 2));
+        assert_near(events.sunset, day + hours(20, 21));
+        assert!(events.dawn < events.sunrise && events.sunset < events.dusk);
+    }
+
+    #[test]
+    fn new_york_midwinter() {
+        let day = DECEMBER_SOLSTICE;
+        let events = events(NEW_YORK, at(day + hours(12, 0)));
+        assert_near(events.sunrise, day + hours(12, 16));
+        assert_near(Some(events.noon), day + hours(16, 54));
+        assert_near(events.sunset, day + hours(21, 32));
+    }
+
+    #[test]
+    fn polar_night_and_day() {
+        // Civil twilight, the sun stays below the horizon.
+        let night = events(TROMSO, at(DECEMBER_SOLSTICE + hours(12, 0)));
+        assert!(night.sunrise.is_none() && night.sunset.is_none());
+        assert!(night.dawn.is_some() && night.dusk.is_some());
+
+        let day = events(TROMSO, at(JUNE_SOLSTICE + hours(12, 0)));
+        assert!(day.dawn.is_none() && day.sunrise.is_none());
+        assert!(day.sunset.is_none() && day.dusk.is_none());
+    }
+
+    #[test]
+    fn sun_position() {
+        let noon = position(LONDON, at(JUNE_SOLSTICE + hours(12, 2)));
+        assert!((noon.elevation - 61.93).abs() < 0.1, "{}", noon.elevation);
+
+        let midnight = position(LONDON, at(JUNE_SOLSTICE));
+        assert!(
+            (midnight.elevation + 15.05).abs() < 0.1,
+            "{}",
+            midnight.elevation
+        );
+
+        assert!(position(LONDON, at(JUNE_SOLSTICE + hours(6, 0))).rising);
+        assert!(!position(LONDON, at(JUNE_SOLSTICE + hours(18, 0))).rising);
+    }
+
+    fn source(variants: &[(f64, Option<SunPhase>)], crossfade: bool) -> SunSource {
+        let image = ImageData::from(image::RgbaImage::new(1, 1));
+        SunSource {
+            location: LONDON,
+            variants: variants
+                .iter()
+                .map(|&(elevation, phase)| Variant {
+                    image: image.clone(),
+                    elevation,
+                    phase,
+                })
+                .collect(),
+            crossfade,
+        }
+    }
+
+    #[test]
+    fn blend_follows_the_phase() {
+        let source = source(
+            &[
+                (-90., None),
+                (0., Some(SunPhase::Rising)),
+                (0., Some(SunPhase::Setting)),
+                (40., None),
+            ],
+            false,
+        );
+
+        // Morning and evening, the sun about 18° up.
+        let morning = source.blend(at(JUNE_SOLSTICE + hours(6, 0)));
+        assert_eq!(morning.base, 1);
+        let evening = source.blend(at(JUNE_SOLSTICE + hours(18, 0)));
+        assert_eq!(evening.base, 2);
+
+        let noon = source.blend(at(JUNE_SOLSTICE + hours(12, 0)));
+        assert_eq!(noon.base, 3);
+        let night = source.blend(at(JUNE_SOLSTICE));
+        assert_eq!(night.base, 0);
+        assert!(night.overlay.is_none());
+    }
+
+    #[test]
+    fn blend_crossfades_within_the_phase() {
+        let source = source(
+            &[
+                (0., Some(SunPhase::Rising)),
+                (10., Some(SunPhase::Setting)),
+                (30., None),
+            ],
+            true,
+        );
+
+        // Between the rising variant and the next one up of the morning,
+        // past the setting one.
+        let morning = source.blend(at(JUNE_SOLSTICE + hours(6, 0)));
+        assert_eq!(morning.base, 0);
+        let (overlay, amount) = morning.overlay.unwrap();
+        assert_eq!(overlay, 2);
+        assert!((0.55..0.65).contains(&amount), "{amount}");
+
+        // Below the lowest variant of the phase.
+        let dawn = source.blend(at(JUNE_SOLSTICE + hours(2, 0)));
+        assert_eq!(dawn.base, 0);
+        assert!(dawn.overlay.is_none());
+        let evening = source.blend(at(JUNE_SOLSTICE + hours(22, 0)));
+        assert_eq!(evening.base, 1);
+        assert!(evening.overlay.is_none());
+    }
+}
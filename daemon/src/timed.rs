@@ -0,0 +1,306 @@
+use crate::{
+    assets::{AssetData, BlendSource},
+    clock::LocalTime,
+    output::blend::{BlendFrame, mix},
+};
+use anyhow::Context;
+use libmoxpaper::{ResizeStrategy, Transition, image_data::ImageData};
+use roxmltree::Node;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+/// Longest the wall clock goes unchecked, see [`crate::schedule`].
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Shortest time between two frames of a crossfade.
+const FADE_FRAME: Duration = Duration::from_millis(16);
+
+/// Steps a crossfade takes at most, more aren't visible with 8 bit colors.
+const FADE_STEPS: f64 = 256.;
+
+/// Whether `path` is a GNOME timed background rather than an image.
+pub fn is_source(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "xml")
+}
+
+/// A GNOME timed background: slides shown one after another from the start
+/// time, starting over once all of them are through.
+#[derive(Clone)]
+pub struct TimedSource {
+    start: SystemTime,
+    slides: Arc<[Slide]>,
+    /// Sum of the durations of the slides, in seconds
+    cycle: f64,
+}
+
+struct Slide {
+    /// Seconds
+    duration: f64,
+    from: PathBuf,
+    /// Crossfaded to over the slide, it stays on `from` if `None`
+    to: Option<PathBuf>,
+}
+
+impl TimedSource {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("{}", path.display()))?;
+        let document = roxmltree::Document::parse(&text)?;
+        let root = document.root_element();
+        if !root.has_tag_name("background") {
+            return Err(anyhow::anyhow!(
+                "Expected <background>, found <{}>",
+                root.tag_name().name()
+            ));
+        }
+
+        // Paths in the file are relative to it.
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let file = |node: Node, name: &str| -> anyhow::Result<PathBuf> {
+            let file = child(node, name)?;
+            // Some packs list the image in several sizes, take the largest.
+            let sized = file
+                .children()
+                .filter(|size| size.has_tag_name("size"))
+                .max_by_key(|size| {
+                    let dimension = |name| {
+                        size.attribute(name)
+                            .and_then(|value| value.parse::<u64>().ok())
+                            .unwrap_or(0)
+                    };
+                    dimension("width") * dimension("height")
+                });
+
+            let text = sized.unwrap_or(file).text().unwrap_or_default().trim();
+            if text.is_empty() {
+                return Err(anyhow::anyhow!("<{name}> names no file"));
+            }
+            Ok(dir.join(text))
+        };
+
+        let start = child(root, "starttime")?;
+        let field = |name: &str| -> anyhow::Result<u32> {
+            Ok(child(start, name)?
+                .text()
+                .unwrap_or_default()
+                .trim()
+                .parse()?)
+        };
+        let start = LocalTime {
+            year: field("year")? as i32,
+            month: field("month")?,
+            day: field("day")?,
+            seconds: (field("hour")? * 3600 + field("minute")? * 60 + field("second")?) as f64,
+        };
+        let start = start
+            .system_time()
+            .ok_or(anyhow::anyhow!("Invalid start time: {start:?}"))?;
+
+        let slides = root
+            .children()
+            .filter(Node::is_element)
+            .filter_map(|node| {
+                let slide = match node.tag_name().name() {
+                    "static" => file(node, "file").map(|from| (from, None)),
+                    "transition" => {
+                        file(node, "from").and_then(|from| Ok((from, Some(file(node, "to")?))))
+                    }
+                    _ => return None,
+                };
+
+                Some(slide.and_then(|(from, to)| {
+                    let duration = child(node, "duration")?
+                        .text()
+                        .unwrap_or_default()
+                        .trim()
+                        .parse::<f64>()?;
+                    if !duration.is_finite() || duration < 0. {
+                        return Err(anyhow::anyhow!("Invalid duration: {duration}"));
+                    }
+                    Ok(Slide { duration, from, to })
+                }))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let cycle = slides.iter().map(|slide| slide.duration).sum::<f64>();
+        if !cycle.is_finite() || cycle <= 0. {
+            return Err(anyhow::anyhow!("Timed background shows nothing"));
+        }
+
+        Ok(Self {
+            start,
+            slides: slides.into(),
+            cycle,
+        })
+    }
+
+    /// The slide shown at `time` and the seconds it has been shown for.
+    fn slide_at(&self, time: SystemTime) -> (&Slide, f64) {
+        let elapsed = match time.duration_since(self.start) {
+            Ok(elapsed) => elapsed.as_secs_f64(),
+            Err(e) => -e.duration().as_secs_f64(),
+        };
+
+        let mut offset = elapsed.rem_euclid(self.cycle);
+        for slide in self.slides.iter() {
+            if offset < slide.duration {
+                return (slide, offset);
+            }
+            offset -= slide.duration;
+        }
+
+        // Only reached through rounding, right at the end of the cycle.
+        let last = self.slides.iter().rfind(|slide| slide.duration > 0.);
+        (last.expect("the cycle is positive"), 0.)
+    }
+}
+
+/// The first element named `name` below `node`.
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> anyhow::Result<Node<'a, 'input>> {
+    node.children()
+        .find(|child| child.has_tag_name(name))
+        .ok_or(anyhow::anyhow!(
+            "<{}> is missing <{name}>",
+            node.tag_name().name()
+        ))
+}
+
+impl AssetData {
+    /// Loads a GNOME timed background, logging why it can't be shown.
+    pub fn timed(path: &Path, resize: ResizeStrategy, transition: Transition) -> Option<Self> {
+        let source = TimedSource::load(path)
+            .inspect_err(|e| log::error!("Failed to load timed background: {e}"))
+            .ok()?;
+
+        let (slide, _) = source.slide_at(SystemTime::now());
+        let image = image::open(&slide.from)
+            .inspect_err(|e| log::error!("{e}: {}", slide.from.display()))
+            .ok()?;
+
+        Some(Self {
+            image: ImageData::from(image),
+            resize,
+            transition,
+            motion: None,
+            shader: None,
+            blend: Some(BlendSource::Timed(source)),
+        })
+    }
+}
+
+/// Goes through the slides of a [`TimedSource`] resized to an output.
+pub struct TimedPlayer {
+    source: TimedSource,
+    resize: ResizeStrategy,
+    width: u32,
+    height: u32,
+    /// Images of the current slide resized to the output
+    cache: Vec<(PathBuf, ImageData)>,
+}
+
+impl TimedPlayer {
+    pub fn new(source: TimedSource, resize: ResizeStrategy, width: u32, height: u32) -> Self {
+        Self {
+            source,
+            resize,
+            width,
+            height,
+            cache: Vec::new(),
+        }
+    }
+
+    /// What the wallpaper looks like now, as a single image.
+    pub fn image(&mut self) -> anyhow::Result<ImageData> {
+        let frame = self.next_frame()?;
+        match frame.overlay {
+            Some((overlay, opacity)) => Ok(mix(&frame.image, &overlay, opacity)),
+            None => Ok(frame.image),
+        }
+    }
+
+    pub fn next_frame(&mut self) -> anyhow::Result<BlendFrame> {
+        let source = self.source.clone();
+        let (slide, offset) = source.slide_at(SystemTime::now());
+        let remaining = slide.duration - offset;
+
+        self.cache
+            .retain(|(path, _)| *path == slide.from || Some(path) == slide.to.as_ref());
+
+        let (overlay, delay) = match &slide.to {
+            Some(to) => {
+                let opacity = (offset / slide.duration).clamp(0., 1.) as f32;
+                let step = (slide.duration / FADE_STEPS).min(CHECK_INTERVAL.as_secs_f64());
+                let step = Duration::from_secs_f64(step);
+                (
+                    Some((self.resized(to)?, opacity)),
+                    step.clamp(FADE_FRAME, CHECK_INTERVAL),
+                )
+            }
+            None => (None, CHECK_INTERVAL),
+        };
+
+        Ok(BlendFrame {
+            image: self.resized(&slide.from)?,
+            overlay,
+            delay: delay
+                .min(Duration::from_secs_f64(
+                    remaining.clamp(0., CHECK_INTERVAL.as_secs_f64()),
+                ))
+                .max(FADE_FRAME),
+        })
+    }
+
+    fn resized(&mut self, path: &Path) -> anyhow::Result<ImageData> {
+        if let Some((_, image)) = self.cache.iter().find(|(cached, _)| cached == path) {
+            return Ok(image.clone());
+        }
+
+        let image = image::open(path).with_context(|| format!("{}", path.display()))?;
+        let image = AssetData::new(ImageData::from(image), self.resize, Default::default())
+            .into_resized(self.width, self.height)?;
+        self.cache.push((path.to_path_buf(), image.clone()));
+
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str, durations: &[&str]) -> anyhow::Result<TimedSource> {
+        let slides = durations
+            .iter()
+            .map(|duration| {
+                format!("<static><duration>{duration}</duration><file>a.png</file></static>")
+            })
+            .collect::<String>();
+        let xml = format!(
+            "<background><starttime><year>2024</year><month>1</month><day>1</day>\
+             <hour>0</hour><minute>0</minute><second>0</second></starttime>{slides}</background>"
+        );
+
+        let path = std::env::temp_dir().join(format!("moxpaper-timed-{name}.xml"));
+        std::fs::write(&path, xml).unwrap();
+        let source = TimedSource::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        source
+    }
+
+    #[test]
+    fn valid_durations() {
+        let source = load("valid", &["0", "60.5", "30"]).unwrap();
+        assert_eq!(source.cycle, 90.5);
+    }
+
+    #[test]
+    fn invalid_durations() {
+        for (name, duration) in [("nan", "NaN"), ("inf", "inf"), ("negative", "-5")] {
+            assert!(load(name, &[duration, "60"]).is_err(), "{duration}");
+        }
+        assert!(load("zero", &["0", "0"]).is_err());
+        assert!(load("overflow", &["1e308", "1e308"]).is_err());
+    }
+}
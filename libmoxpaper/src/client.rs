@@ -1,10 +1,15 @@
-use crate::types::{BezierChoice, Data, OutputInfo, ResizeStrategy, Transition, TransitionType, WallpaperData};
 use crate::image_data::ImageData;
 use crate::ipc::Ipc;
+use crate::types::{
+    BezierChoice, Data, Generated, HistoryEntry, OutputInfo, PlaylistAction, Repeat, Request,
+    ResizeStrategy, Sequence, Shader, Transition, TransitionType, WallpaperData,
+};
 use anyhow::Context;
 use std::{
     io::{BufRead, BufReader, Write},
+    net::Shutdown,
     path::PathBuf,
+    sync::Arc,
 };
 
 fn parse_s3_url(url: &str) -> anyhow::Result<(String, String)> {
@@ -36,6 +41,7 @@ pub struct WallpaperBuilder<'a> {
     outputs: Vec<String>,
     resize: Option<ResizeStrategy>,
     transition: Option<Transition>,
+    repeat: Repeat,
 }
 
 impl<'a> WallpaperBuilder<'a> {
@@ -45,6 +51,18 @@ impl<'a> WallpaperBuilder<'a> {
         self
     }
 
+    /// Set the wallpaper source to a directory or glob of frames
+    pub fn sequence(mut self, sequence: Sequence) -> Self {
+        self.data = Some(Data::Sequence(sequence));
+        self
+    }
+
+    /// Set the wallpaper source to a WGSL shader drawn live
+    pub fn shader(mut self, shader: Shader) -> Self {
+        self.data = Some(Data::Shader(shader));
+        self
+    }
+
     /// Set the wallpaper source to raw image data
     pub fn image(mut self, image_data: ImageData) -> Self {
         self.data = Some(Data::Image(image_data));
@@ -57,6 +75,12 @@ impl<'a> WallpaperBuilder<'a> {
         self
     }
 
+    /// Set the wallpaper source to a pattern generated for each output
+    pub fn generated(mut self, generated: Generated) -> Self {
+        self.data = Some(Data::Generated(generated));
+        self
+    }
+
     pub fn http_data(mut self, url: String, headers: Option<Vec<(String, String)>>) -> Self {
         self.data = Some(Data::Http { url, headers });
         self
@@ -92,6 +116,12 @@ impl<'a> WallpaperBuilder<'a> {
         self
     }
 
+    /// Set how often an animated wallpaper plays
+    pub fn repeat(mut self, repeat: Repeat) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
     /// Apply the wallpaper configuration
     pub fn apply(self) -> anyhow::Result<()> {
         let data = self
@@ -111,6 +141,7 @@ impl<'a> WallpaperBuilder<'a> {
             data,
             resize,
             transition,
+            repeat: self.repeat,
         })
     }
 }
@@ -145,16 +176,87 @@ impl MoxpaperClient {
             outputs: Vec::new(),
             resize: None,
             transition: None,
+            repeat: Repeat::default(),
         }
     }
 
+    /// Control the playlists of the given outputs (empty means all outputs)
+    pub fn playlist(
+        &mut self,
+        action: PlaylistAction,
+        outputs: impl IntoIterator<Item = impl Into<String>>,
+    ) -> anyhow::Result<()> {
+        self.send(Request::Playlist {
+            action,
+            outputs: output_names(outputs),
+        })
+    }
+
+    /// Go back to the previous wallpaper of the given outputs (empty means
+    /// all outputs)
+    pub fn undo(
+        &mut self,
+        outputs: impl IntoIterator<Item = impl Into<String>>,
+    ) -> anyhow::Result<()> {
+        self.send(Request::Undo {
+            outputs: output_names(outputs),
+        })
+    }
+
+    /// Go forward again after an undo on the given outputs (empty means all
+    /// outputs)
+    pub fn redo(
+        &mut self,
+        outputs: impl IntoIterator<Item = impl Into<String>>,
+    ) -> anyhow::Result<()> {
+        self.send(Request::Redo {
+            outputs: output_names(outputs),
+        })
+    }
+
+    /// Retrieve the wallpapers the given outputs showed (empty means all
+    /// outputs), oldest first
+    pub fn history(
+        &mut self,
+        outputs: impl IntoIterator<Item = impl Into<String>>,
+    ) -> anyhow::Result<Vec<HistoryEntry>> {
+        self.send(Request::History {
+            outputs: output_names(outputs),
+        })?;
+
+        let mut stream = self.ipc.get_stream();
+        // The daemon reads requests to the end before it answers.
+        stream
+            .shutdown(Shutdown::Write)
+            .context("Failed to finish request")?;
+
+        let mut buf = String::new();
+        BufReader::new(&mut stream).read_line(&mut buf)?;
+
+        serde_json::from_str(&buf).context("Failed to parse history from daemon")
+    }
+
+    /// Show the wallpapers set before the daemon restarted
+    pub fn restore(&mut self) -> anyhow::Result<()> {
+        self.send(Request::Restore)
+    }
+
+    /// Make the daemon read its config again
+    pub fn reload(&mut self) -> anyhow::Result<()> {
+        self.send(Request::Reload)
+    }
+
     /// Helper method to send wallpaper data to the daemon
     fn send_wallpaper_data(&mut self, data: WallpaperData) -> anyhow::Result<()> {
+        self.send(Request::Wallpaper(Box::new(data)))
+    }
+
+    fn send(&mut self, request: Request) -> anyhow::Result<()> {
         let mut stream = self.ipc.get_stream();
-        let json = serde_json::to_string(&data).context("Failed to serialize wallpaper data")?;
+        let json = serde_json::to_string(&request).context("Failed to serialize request")?;
         stream
             .write_all(json.as_bytes())
-            .context("Failed to send wallpaper data to daemon")?;
+            .context("Failed to send request to daemon")?;
         Ok(())
     }
 
@@ -170,7 +272,17 @@ impl MoxpaperClient {
             fps,
             duration,
             bezier,
+            interrupt: None,
+            choreography: None,
+            random_pool: None,
+            stages: Vec::new(),
         }
     }
 }
 
+fn output_names(outputs: impl IntoIterator<Item = impl Into<String>>) -> Vec<Arc<str>> {
+    outputs
+        .into_iter()
+        .map(|s| Into::<String>::into(s).into())
+        .collect()
+}
@@ -218,4 +218,3 @@ impl From<RgbaImage> for ImageData {
         }
     }
 }
-
@@ -1,4 +1,4 @@
-use crate::types::WallpaperData;
+use crate::types::Request;
 use std::{
     collections::HashMap,
     env,
@@ -66,12 +66,12 @@ impl Ipc<Client> {
 
 impl Ipc<Server> {
     pub fn server() -> anyhow::Result<Self> {
-        if let Ok(output) = std::process::Command::new("pidof").arg("moxpaper").output() {
-            if output.status.success() {
-                let pids = String::from_utf8_lossy(&output.stdout);
-                if pids.split_whitespace().count() > 1 {
-                    return Err(anyhow::anyhow!("moxpaper is already running"));
-                }
+        if let Ok(output) = std::process::Command::new("pidof").arg("moxpaper").output()
+            && output.status.success()
+        {
+            let pids = String::from_utf8_lossy(&output.stdout);
+            if pids.split_whitespace().count() > 1 {
+                return Err(anyhow::anyhow!("moxpaper is already running"));
             }
         }
 
@@ -137,7 +137,7 @@ impl Ipc<Server> {
         inner.connections.get_mut(fd)
     }
 
-    pub fn handle_stream_data(&mut self, fd: &i32) -> anyhow::Result<WallpaperData> {
+    pub fn handle_stream_data(&mut self, fd: &i32) -> anyhow::Result<Request> {
         let mut buffer = Vec::new();
 
         if let Some(stream) = self.get_mut(fd) {
@@ -148,7 +148,7 @@ impl Ipc<Server> {
                 }
                 Ok(n) => {
                     let data = &buffer[..n];
-                    Ok(serde_json::from_slice::<WallpaperData>(data)?)
+                    Ok(serde_json::from_slice::<Request>(data)?)
                 }
                 Err(e) => {
                     self.remove_connection(fd);
@@ -160,4 +160,3 @@ impl Ipc<Server> {
         }
     }
 }
-
@@ -10,6 +10,9 @@ mod ipc;
 #[cfg(any(feature = "server", feature = "client"))]
 mod types;
 
+#[cfg(any(feature = "server", feature = "client"))]
+pub mod parse;
+
 #[cfg(feature = "client")]
 mod client;
 
@@ -18,7 +21,10 @@ pub use image_data::ImageData;
 
 #[cfg(any(feature = "server", feature = "client"))]
 pub use types::{
-    BezierChoice, Data, OutputInfo, ResizeStrategy, Transition, TransitionType, WallpaperData,
+    BezierChoice, Choreography, ColorStop, Data, EasingFunction, Generated, HistoryEntry,
+    Intermediate, InterruptPolicy, Mask, MaskSource, OutputInfo, Playback, PlaylistAction,
+    RandomPool, Repeat, Request, ResizeStrategy, Sequence, Shader, StepPosition, Transition,
+    TransitionStage, TransitionType, WallpaperData, WeightedTransition,
 };
 
 #[cfg(feature = "client")]
@@ -0,0 +1,305 @@
+//! Parsers for the transition settings given on the command line, shared
+//! by `moxpaper` and the daemon's preview.
+
+use crate::{
+    BezierChoice, Choreography, EasingFunction, Intermediate, Mask, MaskSource, RandomPool,
+    StepPosition, TransitionStage, TransitionType, WeightedTransition,
+};
+use anyhow::Context;
+
+/// Parses a color in hexadecimal, e.g. "#ff8800"
+pub fn from_hex(hex: &str) -> anyhow::Result<[u8; 3]> {
+    let hex = hex.trim_start_matches('#');
+
+    let chars = hex
+        .chars()
+        .filter(|&c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase() as u8);
+
+    if chars.clone().count() != 6 {
+        return Err(anyhow::anyhow!(
+            "Expected 6 characters for hex color, found {}",
+            chars.count()
+        ));
+    }
+
+    let mut color = [0, 0, 0];
+
+    chars.enumerate().try_for_each(|(i, c)| {
+        match c {
+            b'A'..=b'F' => color[i / 2] += c - b'A' + 10,
+            b'0'..=b'9' => color[i / 2] += c - b'0',
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Expected [0-9], [a-f], or [A-F], found '{}'",
+                    char::from(c)
+                ));
+            }
+        }
+
+        if i % 2 == 0 {
+            color[i / 2] *= 16;
+        }
+
+        Ok(())
+    })?;
+
+    Ok(color)
+}
+
+/// Parses a timing function, e.g. "ease", "0.42,0.0,1.0,1.0",
+/// "steps(4, jump-end)", "spring(170, 8)" or "bounce-out"
+pub fn parse_bezier(s: &str) -> anyhow::Result<BezierChoice> {
+    let nums = s
+        .split(',')
+        .map(str::trim)
+        .map(str::parse)
+        .collect::<Result<Vec<f32>, _>>();
+
+    if let Ok(nums) = nums
+        && nums.len() == 4
+    {
+        return Ok(BezierChoice::Custom((nums[0], nums[1], nums[2], nums[3])));
+    }
+
+    if let Some(args) = s.strip_prefix("steps(").and_then(|s| s.strip_suffix(')')) {
+        return parse_steps(args);
+    }
+
+    if let Some(args) = s.strip_prefix("spring(").and_then(|s| s.strip_suffix(')')) {
+        return parse_spring(args);
+    }
+
+    let bezier = match s {
+        "linear" => BezierChoice::Linear,
+        "ease" => BezierChoice::Ease,
+        "ease-in" => BezierChoice::EaseIn,
+        "ease-out" => BezierChoice::EaseOut,
+        "ease-in-out" => BezierChoice::EaseInOut,
+        "bounce-in" => BezierChoice::Easing(EasingFunction::BounceIn),
+        "bounce-out" => BezierChoice::Easing(EasingFunction::BounceOut),
+        "bounce-in-out" => BezierChoice::Easing(EasingFunction::BounceInOut),
+        "elastic-in" => BezierChoice::Easing(EasingFunction::ElasticIn),
+        "elastic-out" => BezierChoice::Easing(EasingFunction::ElasticOut),
+        "elastic-in-out" => BezierChoice::Easing(EasingFunction::ElasticInOut),
+        "back-in" => BezierChoice::Easing(EasingFunction::BackIn),
+        "back-out" => BezierChoice::Easing(EasingFunction::BackOut),
+        "back-in-out" => BezierChoice::Easing(EasingFunction::BackInOut),
+        _ => BezierChoice::Named(s.into()),
+    };
+
+    Ok(bezier)
+}
+
+/// Parses the arguments of CSS `steps(<n>[, <jump>])`
+fn parse_steps(args: &str) -> anyhow::Result<BezierChoice> {
+    let mut args = args.split(',').map(str::trim);
+
+    let steps = args
+        .next()
+        .ok_or(anyhow::anyhow!("steps() requires a step count"))?
+        .parse::<u32>()?;
+
+    let jump = match args.next() {
+        None | Some("jump-end") | Some("end") => StepPosition::JumpEnd,
+        Some("jump-start") | Some("start") => StepPosition::JumpStart,
+        Some("jump-none") => StepPosition::JumpNone,
+        Some("jump-both") => StepPosition::JumpBoth,
+        Some(jump) => {
+            return Err(anyhow::anyhow!(
+                "Expected jump-start, jump-end, jump-none or jump-both, found '{jump}'"
+            ));
+        }
+    };
+
+    EasingFunction::steps(steps, jump)
+        .map(BezierChoice::Easing)
+        .map_err(|e| anyhow::anyhow!(e))
+}
+
+/// Parses the arguments of `spring(<stiffness>, <damping>[, <mass>])`
+fn parse_spring(args: &str) -> anyhow::Result<BezierChoice> {
+    let nums = args
+        .split(',')
+        .map(str::trim)
+        .map(str::parse)
+        .collect::<Result<Vec<f32>, _>>()?;
+
+    let (stiffness, damping, mass) = match nums.as_slice() {
+        [stiffness, damping] => (*stiffness, *damping, 1.0),
+        [stiffness, damping, mass] => (*stiffness, *damping, *mass),
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Expected spring(stiffness, damping[, mass]), found {} arguments",
+                nums.len()
+            ));
+        }
+    };
+
+    Ok(BezierChoice::Easing(EasingFunction::Spring {
+        stiffness,
+        damping,
+        mass,
+    }))
+}
+
+/// Parses a transition of a random pool with an optional weight, e.g.
+/// "fade:3"
+pub fn parse_weighted_transition(s: &str) -> anyhow::Result<WeightedTransition> {
+    let (transition_type, weight) = match s.split_once(':') {
+        Some((transition_type, weight)) => (transition_type, weight.trim().parse::<f32>()?),
+        None => (s, 1.0),
+    };
+
+    WeightedTransition::new(parse_transition_type(transition_type.trim())?, weight)
+        .map_err(|e| anyhow::anyhow!(e))
+}
+
+/// The pool `random` picks from, `None` if nothing about it was set
+pub fn random_pool(transitions: Vec<WeightedTransition>, avoid_repeat: bool) -> Option<RandomPool> {
+    (!transitions.is_empty() || avoid_repeat).then_some(RandomPool {
+        transitions,
+        avoid_repeat,
+    })
+}
+
+/// Parses a stage of a chained transition from space separated
+/// "key=value" pairs, e.g. "type=fade duration=300 through=#000000"
+pub fn parse_stage(s: &str) -> anyhow::Result<TransitionStage> {
+    s.split_whitespace()
+        .try_fold(TransitionStage::default(), |mut stage, pair| {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or(anyhow::anyhow!("Expected key=value, found '{pair}'"))?;
+
+            match key {
+                "type" => stage.transition_type = Some(parse_transition_type(value)?),
+                "duration" => stage.duration = Some(value.parse()?),
+                "fps" => stage.fps = Some(value.parse()?),
+                "bezier" => stage.bezier = Some(parse_bezier(value)?),
+                "through" => {
+                    stage.intermediate = Some(match from_hex(value) {
+                        Ok(color) => Intermediate::Color(color),
+                        Err(_) => Intermediate::Image(
+                            std::fs::canonicalize(value)
+                                .with_context(|| format!("Intermediate image '{value}'"))?,
+                        ),
+                    })
+                }
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "Unknown stage key '{key}', expected type, duration, fps, bezier or through"
+                    ));
+                }
+            }
+
+            Ok(stage)
+        })
+}
+
+/// Parses the arguments of `mask(<source>[, <softness>])`
+fn parse_mask(args: &str) -> anyhow::Result<TransitionType> {
+    // Image paths may contain commas, only a number at the end is the
+    // softness.
+    let (source, softness) = match args
+        .rsplit_once(',')
+        .and_then(|(source, softness)| Some((source, softness.trim().parse::<f32>().ok()?)))
+    {
+        Some((source, softness)) => (source.trim(), softness),
+        None => (args.trim(), 0.0),
+    };
+    if !softness.is_finite() || softness < 0. {
+        return Err(anyhow::anyhow!("Mask softness has to be positive or zero"));
+    }
+
+    let source = match source {
+        "radial" => MaskSource::Radial,
+        "clock" => MaskSource::Clock,
+        "diamond" => MaskSource::Diamond,
+        "noise" => MaskSource::Noise,
+        path => MaskSource::Image(
+            std::fs::canonicalize(path).with_context(|| format!("Mask image '{path}'"))?,
+        ),
+    };
+
+    Ok(TransitionType::Mask(Mask { source, softness }))
+}
+
+/// Parses "independent", "sync" or "cascade(<delay>[, <direction>])"
+pub fn parse_choreography(s: &str) -> anyhow::Result<Choreography> {
+    if let Some(args) = s.strip_prefix("cascade(").and_then(|s| s.strip_suffix(')')) {
+        let mut args = args.split(',').map(str::trim);
+
+        let delay = args
+            .next()
+            .ok_or(anyhow::anyhow!("cascade() requires a delay"))?
+            .parse::<u64>()?;
+        let direction = args
+            .next()
+            .map(str::parse::<f32>)
+            .transpose()?
+            .unwrap_or(0.0);
+
+        return Ok(Choreography::Cascade { delay, direction });
+    }
+
+    match s {
+        "independent" => Ok(Choreography::Independent),
+        "sync" => Ok(Choreography::Sync),
+        _ => Err(anyhow::anyhow!(
+            "Expected independent, sync or cascade(<delay>[, <direction>]), found '{s}'"
+        )),
+    }
+}
+
+/// Parses a transition name, e.g. "push-left" or "mask(radial, 0.2)"
+pub fn parse_transition_type(s: &str) -> anyhow::Result<TransitionType> {
+    if let Some(args) = s.strip_prefix("mask(").and_then(|s| s.strip_suffix(')')) {
+        return parse_mask(args);
+    }
+
+    Ok(match s {
+        "none" => TransitionType::None,
+        "simple" => TransitionType::Simple,
+        "fade" => TransitionType::Fade,
+        "left" => TransitionType::Left,
+        "right" => TransitionType::Right,
+        "top" => TransitionType::Top,
+        "bottom" => TransitionType::Bottom,
+        "center" => TransitionType::Center,
+        "outer" => TransitionType::Outer,
+        "any" => TransitionType::Any,
+        "random" => TransitionType::Random,
+        "wipe" => TransitionType::Wipe,
+        "wave" => TransitionType::Wave,
+        "grow" => TransitionType::Grow,
+        "push-left" => TransitionType::PushLeft,
+        "push-right" => TransitionType::PushRight,
+        "push-top" => TransitionType::PushTop,
+        "push-bottom" => TransitionType::PushBottom,
+        "cover-left" => TransitionType::CoverLeft,
+        "cover-right" => TransitionType::CoverRight,
+        "cover-top" => TransitionType::CoverTop,
+        "cover-bottom" => TransitionType::CoverBottom,
+        "reveal-left" => TransitionType::RevealLeft,
+        "reveal-right" => TransitionType::RevealRight,
+        "reveal-top" => TransitionType::RevealTop,
+        "reveal-bottom" => TransitionType::RevealBottom,
+        "zoom-in" => TransitionType::ZoomIn,
+        "zoom-out" => TransitionType::ZoomOut,
+        "cube-left" => TransitionType::CubeLeft,
+        "cube-right" => TransitionType::CubeRight,
+        "cube-top" => TransitionType::CubeTop,
+        "cube-bottom" => TransitionType::CubeBottom,
+        "flip-horizontal" => TransitionType::FlipHorizontal,
+        "flip-vertical" => TransitionType::FlipVertical,
+        "page-curl" => TransitionType::PageCurl,
+        "tiles" => TransitionType::Tiles,
+        "dissolve" => TransitionType::Dissolve,
+        "shatter" => TransitionType::Shatter,
+        "particles" => TransitionType::Particles,
+        "blinds-horizontal" => TransitionType::BlindsHorizontal,
+        "blinds-vertical" => TransitionType::BlindsVertical,
+        s => TransitionType::Custom(s.into()),
+    })
+}
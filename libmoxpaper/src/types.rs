@@ -7,7 +7,7 @@ use serde::{Deserialize, Serialize};
 use std::{path::PathBuf, sync::Arc};
 
 #[cfg(any(feature = "server", feature = "client"))]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum BezierChoice {
     Linear,
     Ease,
@@ -16,6 +16,86 @@ pub enum BezierChoice {
     EaseInOut,
     Named(Box<str>),
     Custom((f32, f32, f32, f32)),
+    Easing(EasingFunction),
+}
+
+/// Timing functions that can't be expressed as a cubic bezier
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EasingFunction {
+    /// CSS `steps(n, <jump>)`
+    #[serde(deserialize_with = "deserialize_steps")]
+    Steps {
+        steps: u32,
+        #[serde(default)]
+        jump: StepPosition,
+    },
+    /// Damped harmonic oscillator, may overshoot before settling
+    Spring {
+        stiffness: f32,
+        damping: f32,
+        #[serde(default = "default_spring_mass")]
+        mass: f32,
+    },
+    BounceIn,
+    BounceOut,
+    BounceInOut,
+    ElasticIn,
+    ElasticOut,
+    ElasticInOut,
+    BackIn,
+    BackOut,
+    BackInOut,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+impl EasingFunction {
+    /// CSS `steps()`, which needs a step and two with `jump-none` since
+    /// that one has a step at both ends.
+    pub fn steps(steps: u32, jump: StepPosition) -> Result<Self, &'static str> {
+        match (steps, jump) {
+            (0, _) => Err("steps() requires at least one step"),
+            (1, StepPosition::JumpNone) => {
+                Err("steps() with jump-none requires at least two steps")
+            }
+            _ => Ok(Self::Steps { steps, jump }),
+        }
+    }
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+fn deserialize_steps<'de, D>(deserializer: D) -> Result<(u32, StepPosition), D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct Steps {
+        steps: u32,
+        #[serde(default)]
+        jump: StepPosition,
+    }
+
+    let Steps { steps, jump } = Steps::deserialize(deserializer)?;
+    EasingFunction::steps(steps, jump)
+        .map(|_| (steps, jump))
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+fn default_spring_mass() -> f32 {
+    1.0
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepPosition {
+    JumpStart,
+    #[default]
+    JumpEnd,
+    JumpNone,
+    JumpBoth,
 }
 
 #[cfg(any(feature = "server", feature = "client"))]
@@ -26,16 +106,143 @@ impl Default for BezierChoice {
 }
 
 #[cfg(any(feature = "server", feature = "client"))]
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 pub struct Transition {
     pub transition_type: Option<TransitionType>,
     pub fps: Option<u64>,
     pub duration: Option<u128>,
     pub bezier: Option<BezierChoice>,
+    pub interrupt: Option<InterruptPolicy>,
+    pub choreography: Option<Choreography>,
+    pub random_pool: Option<RandomPool>,
+    /// Run several transitions back to back, unset fields of a stage fall
+    /// back to this transition
+    #[serde(default)]
+    pub stages: Vec<TransitionStage>,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct TransitionStage {
+    pub transition_type: Option<TransitionType>,
+    pub fps: Option<u64>,
+    pub duration: Option<u128>,
+    pub bezier: Option<BezierChoice>,
+    /// What this stage ends on instead of the wallpaper. Ignored on the
+    /// last stage, which always ends on the wallpaper.
+    pub intermediate: Option<Intermediate>,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Intermediate {
+    Color([u8; 3]),
+    Image(PathBuf),
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InterruptPolicy {
+    /// Start the new transition from whatever is currently on screen
+    #[default]
+    Interrupt,
+    /// Wait for the running transition to finish before starting the new one,
+    /// at most 8 wait and the oldest is dropped beyond that
+    Queue,
+    /// Ignore the new wallpaper while a transition is running
+    Drop,
+}
+
+/// Transitions that `random` picks from
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RandomPool {
+    /// Candidates with their relative weights, empty means every built-in
+    /// transition with equal weight
+    pub transitions: Vec<WeightedTransition>,
+    /// Never pick the same transition twice in a row on an output
+    pub avoid_repeat: bool,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "PoolEntry")]
+pub struct WeightedTransition {
+    pub transition_type: TransitionType,
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+impl WeightedTransition {
+    /// Candidate of a random pool. Only transitions the daemon draws can be
+    /// picked, with a weight that isn't negative.
+    pub fn new(transition_type: TransitionType, weight: f32) -> Result<Self, String> {
+        if let TransitionType::Custom(name) = &transition_type {
+            return Err(format!(
+                "Unknown transition '{name}' can't be picked at random"
+            ));
+        }
+        if weight.is_nan() || weight < 0.0 {
+            return Err(format!("Weight of {transition_type:?} can't be negative"));
+        }
+
+        Ok(Self {
+            transition_type,
+            weight,
+        })
+    }
+}
+
+/// [`WeightedTransition`] as written, before it is checked.
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Deserialize)]
+struct PoolEntry {
+    transition_type: TransitionType,
+    #[serde(default = "default_weight")]
+    weight: f32,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+impl TryFrom<PoolEntry> for WeightedTransition {
+    type Error = String;
+
+    fn try_from(entry: PoolEntry) -> Result<Self, Self::Error> {
+        Self::new(entry.transition_type, entry.weight)
+    }
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+fn default_weight() -> f32 {
+    1.0
+}
+
+/// How a transition is timed across the outputs it is applied to
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Choreography {
+    /// Every output starts as soon as it is ready
+    #[default]
+    Independent,
+    /// All outputs share one start instant
+    Sync,
+    /// Outputs start one after another, ordered by their physical position
+    Cascade {
+        /// Milliseconds between the starts of two neighbouring outputs
+        delay: u64,
+        /// Direction of the sweep in degrees, 0 is left to right and 90 is
+        /// top to bottom
+        #[serde(default)]
+        direction: f32,
+    },
 }
 
 #[cfg(any(feature = "server", feature = "client"))]
-#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum TransitionType {
     None,
@@ -53,10 +260,66 @@ pub enum TransitionType {
     Wipe,
     Wave,
     Grow,
+    PushLeft,
+    PushRight,
+    PushTop,
+    PushBottom,
+    CoverLeft,
+    CoverRight,
+    CoverTop,
+    CoverBottom,
+    RevealLeft,
+    RevealRight,
+    RevealTop,
+    RevealBottom,
+    ZoomIn,
+    ZoomOut,
+    CubeLeft,
+    CubeRight,
+    CubeTop,
+    CubeBottom,
+    FlipHorizontal,
+    FlipVertical,
+    PageCurl,
+    Tiles,
+    Dissolve,
+    Shatter,
+    Particles,
+    BlindsHorizontal,
+    BlindsVertical,
+    Mask(Mask),
     #[serde(untagged)]
     Custom(Arc<str>),
 }
 
+/// Wipe driven by a grayscale mask, a pixel switches to the new wallpaper
+/// once the progress passes its luminance
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mask {
+    pub source: MaskSource,
+    /// Width of the blend between old and new, as a fraction of the
+    /// luminance range
+    #[serde(default)]
+    pub softness: f32,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MaskSource {
+    /// Grayscale image, scaled to each output
+    Image(PathBuf),
+    /// Grows from the center
+    Radial,
+    /// Sweeps clockwise from twelve o'clock
+    Clock,
+    /// Grows from the center as a diamond
+    Diamond,
+    /// Dissolves in cloudy noise
+    Noise,
+}
+
 #[cfg(any(feature = "server", feature = "client"))]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OutputInfo {
@@ -64,6 +327,10 @@ pub struct OutputInfo {
     pub width: u32,
     pub height: u32,
     pub scale: i32,
+    #[serde(default)]
+    pub x: i32,
+    #[serde(default)]
+    pub y: i32,
 }
 
 #[cfg(any(feature = "server", feature = "client"))]
@@ -74,6 +341,8 @@ impl Default for OutputInfo {
             width: 0,
             height: 0,
             scale: 1,
+            x: 0,
+            y: 0,
         }
     }
 }
@@ -92,6 +361,116 @@ pub enum Data {
         url: String,
         headers: Option<Vec<(String, String)>>,
     },
+    Sequence(Sequence),
+    Shader(Shader),
+    Generated(Generated),
+}
+
+/// A pattern computed for each output at its resolution
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Generated {
+    /// Colors along a line through the center, `angle` in degrees with 0
+    /// pointing up and 90 to the right, like CSS `linear-gradient`
+    LinearGradient { angle: f32, stops: Vec<ColorStop> },
+    /// Colors in circles around the center, reaching the last stop in the
+    /// corners
+    RadialGradient { stops: Vec<ColorStop> },
+    /// Colors around the center, clockwise from `angle` degrees
+    ConicGradient { angle: f32, stops: Vec<ColorStop> },
+    /// Perlin noise with features `scale` times smaller than the output
+    Noise {
+        seed: u32,
+        scale: f32,
+        palette: Vec<[u8; 3]>,
+    },
+    /// Overlapping sine waves, `scale` of them across the output
+    Plasma {
+        seed: u32,
+        scale: f32,
+        palette: Vec<[u8; 3]>,
+    },
+    /// Cells around random points, `cells` of them across the output
+    Voronoi {
+        seed: u32,
+        cells: u32,
+        palette: Vec<[u8; 3]>,
+    },
+}
+
+/// Color of a gradient at a position from 0 to 1 along it. Stops without a
+/// position are spread evenly between their neighbours.
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ColorStop {
+    pub color: [u8; 3],
+    #[serde(default)]
+    pub position: Option<f32>,
+}
+
+/// A WGSL fragment shader drawn live as the wallpaper
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Shader {
+    /// WGSL file defining `fn main_image(frag_coord: vec2<f32>) -> vec4<f32>`
+    pub path: PathBuf,
+    /// Frames per second, `None` draws every frame the output shows
+    #[serde(default)]
+    pub fps: Option<f32>,
+}
+
+/// Numbered frames on disk played as an animation
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sequence {
+    /// Directory whose images are the frames, in natural order, or a glob
+    /// over file names such as `renders/frame_*.png`
+    pub source: PathBuf,
+    pub fps: f32,
+    #[serde(default)]
+    pub playback: Playback,
+    /// Frames the first output starts into the sequence, each further
+    /// output named starts this many frames further than the one before
+    #[serde(default)]
+    pub offset: u32,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+impl Sequence {
+    /// Frame rate of a sequence that doesn't set one
+    pub const DEFAULT_FPS: f32 = 24.;
+
+    /// Whether `path` names frames rather than a single image: a directory,
+    /// or a file name with `*` or `?` wildcards.
+    pub fn is_source(path: &std::path::Path) -> bool {
+        path.is_dir()
+            || path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.contains(['*', '?']))
+    }
+
+    /// The sequence the output at `index` among those named shows.
+    pub fn staggered(&self, index: usize) -> Self {
+        let step = u32::try_from(index).unwrap_or(u32::MAX).saturating_add(1);
+        Self {
+            offset: self.offset.saturating_mul(step),
+            ..self.clone()
+        }
+    }
+}
+
+/// Order a [`Sequence`] plays its frames in
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Playback {
+    /// Start over from the first frame after the last one
+    #[default]
+    Loop,
+    /// Play forwards, then backwards
+    PingPong,
 }
 
 #[cfg(any(feature = "server", feature = "client"))]
@@ -109,11 +488,101 @@ pub enum ResizeStrategy {
 }
 
 #[cfg(any(feature = "server", feature = "client"))]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WallpaperData {
     pub outputs: Vec<Arc<str>>,
     pub data: Data,
     pub resize: ResizeStrategy,
     pub transition: Transition,
+    #[serde(default)]
+    pub repeat: Repeat,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+impl WallpaperData {
+    /// The wallpaper of each output named, on its own, with sequences
+    /// [staggered](Sequence::staggered) between them.
+    pub fn per_output(&self) -> impl Iterator<Item = WallpaperData> + '_ {
+        self.outputs.iter().enumerate().map(|(index, output)| {
+            let data = match &self.data {
+                Data::Sequence(sequence) => Data::Sequence(sequence.staggered(index)),
+                data => data.clone(),
+            };
+
+            WallpaperData {
+                outputs: vec![Arc::clone(output)],
+                data,
+                resize: self.resize,
+                transition: self.transition.clone(),
+                repeat: self.repeat,
+            }
+        })
+    }
+}
+
+/// How often an animated wallpaper plays before it stops on its last frame
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Repeat {
+    #[default]
+    Forever,
+    Times(u32),
 }
 
+/// Message a client sends to the daemon
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Request {
+    Wallpaper(Box<WallpaperData>),
+    /// Controls the playlists showing on `outputs`, all of them if empty
+    Playlist {
+        action: PlaylistAction,
+        outputs: Vec<Arc<str>>,
+    },
+    /// Shows the wallpapers clients set last again, as saved by the daemon
+    Restore,
+    /// Evaluates the config again and applies what changed
+    Reload,
+    /// Goes back to the previous wallpaper of `outputs`, all of them if
+    /// empty
+    Undo {
+        outputs: Vec<Arc<str>>,
+    },
+    /// Goes forward again after [`Request::Undo`]
+    Redo {
+        outputs: Vec<Arc<str>>,
+    },
+    /// Asks for the wallpapers `outputs` showed, all of them if empty, the
+    /// daemon answers with a line of [`HistoryEntry`] JSON
+    History {
+        outputs: Vec<Arc<str>>,
+    },
+}
+
+/// A wallpaper an output showed
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub output: Arc<str>,
+    pub wallpaper: WallpaperData,
+    /// Seconds since the Unix epoch
+    pub applied: u64,
+    /// Whether the output shows it now, entries after it can be redone
+    pub current: bool,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaylistAction {
+    /// Show the next wallpaper now
+    Next,
+    /// Show the previous wallpaper now
+    Prev,
+    /// Stop changing wallpapers
+    Pause,
+    /// Change wallpapers again, a full interval after resuming
+    Resume,
+}